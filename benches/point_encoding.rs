@@ -0,0 +1,70 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use serde_json::json;
+use smoldb::storage::{
+    point_encoding,
+    segment::{Point, PointId},
+};
+use std::hint::black_box;
+
+fn points() -> Vec<(&'static str, Point)> {
+    let small = Point {
+        id: PointId::Id(0),
+        payload: json!({ "msg": "Hello world" }),
+        version: 117_464_649_406_283_776,
+    };
+    let large = Point {
+        id: PointId::Id(1),
+        payload: json!({
+            "title": "Hello world",
+            "tags": ["a", "b", "c", "d"],
+            "scores": (0..64).map(|i| i as f64 / 3.0).collect::<Vec<_>>(),
+            "nested": { "count": 12345, "active": true, "owner": null },
+        }),
+        version: 117_464_649_406_283_776,
+    };
+    vec![("small", small), ("large", large)]
+}
+
+/// JSON is how points were stored before the binary encoding
+// Takes 131 ns (json) vs 45 ns (binary) for the small point on my machine, 4.2 µs vs 843 ns for the large one
+pub fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Point encoding");
+
+    for (size, point) in points() {
+        group.bench_with_input(BenchmarkId::new("json", size), &point, |b, point| {
+            b.iter(|| serde_json::to_vec(black_box(point)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("binary", size), &point, |b, point| {
+            b.iter(|| point_encoding::encode(black_box(point)).unwrap())
+        });
+    }
+}
+
+// Takes 448 ns (json) vs 131 ns (binary) for the small point on my machine, 5.0 µs vs 4.1 µs for the large one.
+// Reading only the version takes 194 ns / 1.5 µs for json and 2.5 ns for binary.
+pub fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Point decoding");
+
+    for (size, point) in points() {
+        let json = serde_json::to_vec(&point).unwrap();
+        let binary = point_encoding::encode(&point).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("json", size), &json, |b, value| {
+            b.iter(|| point_encoding::decode(PointId::Id(0), black_box(value)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("binary", size), &binary, |b, value| {
+            b.iter(|| point_encoding::decode(PointId::Id(0), black_box(value)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("json_version", size), &json, |b, value| {
+            b.iter(|| point_encoding::stored_version(black_box(value)))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("binary_version", size),
+            &binary,
+            |b, value| b.iter(|| point_encoding::stored_version(black_box(value))),
+        );
+    }
+}
+
+criterion_group!(benches, encode, decode);
+criterion_main!(benches);
@@ -1,9 +1,12 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use serde_json::json;
+use smoldb::channel_service::ChannelService;
 use smoldb::storage::{
-    collection::{Collection, CollectionConfig},
-    segment::{Point, PointId},
+    collection::{Collection, CollectionConfig, DEFAULT_SHARD_COUNT},
+    replicas::{consistency::ReadConsistency, ring::RingConfig, ShardSelector, ShardingMethod},
+    segment::{Point, PointId, StorageType},
 };
+use std::collections::BTreeMap;
 use tempfile::TempDir;
 
 // Takes 619.19 ns on my machine
@@ -24,8 +27,15 @@ pub fn single_write(c: &mut Criterion) {
             "test_collection".to_string(),
             CollectionConfig {
                 params: "...".to_string(),
+                shard_count: DEFAULT_SHARD_COUNT,
+                resharding_shard_count: None,
+                sharding_method: ShardingMethod::Auto,
+                shard_keys: BTreeMap::new(),
+                ring: RingConfig::default(),
+                storage: StorageType::Sled,
             },
             tempdir.path(),
+            ChannelService::default(),
         )
         .await
         .unwrap()
@@ -34,12 +44,13 @@ pub fn single_write(c: &mut Criterion) {
     let points = [Point {
         id: PointId::Id(0),
         payload: json!({ "msg": "Hello world" }),
+        version: 0,
     }];
 
     group.bench_function("single_write", |b| {
         b.to_async(&rt).iter(|| async {
             collection
-                .upsert_points(points.to_vec(), true)
+                .upsert_points(points.to_vec(), &ShardSelector::Auto, true, None)
                 .await
                 .unwrap();
         })
@@ -64,8 +75,15 @@ pub fn concurrent_write(c: &mut Criterion) {
             "test_collection".to_string(),
             CollectionConfig {
                 params: "...".to_string(),
+                shard_count: DEFAULT_SHARD_COUNT,
+                resharding_shard_count: None,
+                sharding_method: ShardingMethod::Auto,
+                shard_keys: BTreeMap::new(),
+                ring: RingConfig::default(),
+                storage: StorageType::Sled,
             },
             tempdir.path(),
+            ChannelService::default(),
         )
         .await
         .unwrap()
@@ -80,6 +98,7 @@ pub fn concurrent_write(c: &mut Criterion) {
         .map(|i| Point {
             id: PointId::Id(i),
             payload: json!({ "msg": format!("Hello world {}", i) }),
+            version: 0,
         })
         .collect();
 
@@ -88,7 +107,7 @@ pub fn concurrent_write(c: &mut Criterion) {
             for chunk in points.chunks(chunk_size) {
                 let collection_clone = collection_arc.clone();
                 collection_clone
-                    .upsert_points(chunk.to_vec(), true)
+                    .upsert_points(chunk.to_vec(), &ShardSelector::Auto, true, None)
                     .await
                     .unwrap();
             }
@@ -114,8 +133,15 @@ pub fn single_read(c: &mut Criterion) {
             "test_collection".to_string(),
             CollectionConfig {
                 params: "...".to_string(),
+                shard_count: DEFAULT_SHARD_COUNT,
+                resharding_shard_count: None,
+                sharding_method: ShardingMethod::Auto,
+                shard_keys: BTreeMap::new(),
+                ring: RingConfig::default(),
+                storage: StorageType::Sled,
             },
             tempdir.path(),
+            ChannelService::default(),
         )
         .await
         .unwrap();
@@ -123,10 +149,11 @@ pub fn single_read(c: &mut Criterion) {
         let points = [Point {
             id: PointId::Id(0),
             payload: json!({ "msg": "Hello world" }),
+            version: 0,
         }];
 
         collection
-            .upsert_points(points.to_vec(), true)
+            .upsert_points(points.to_vec(), &ShardSelector::Auto, true, None)
             .await
             .unwrap();
 
@@ -136,7 +163,12 @@ pub fn single_read(c: &mut Criterion) {
     group.bench_function("single_read", |b| {
         b.to_async(&rt).iter(|| async {
             collection
-                .get_points(Some(vec![PointId::Id(0)]), None, true)
+                .get_points(
+                    Some(vec![PointId::Id(0)]),
+                    &ShardSelector::Auto,
+                    ReadConsistency::One,
+                    true,
+                )
                 .await
                 .unwrap();
         })
@@ -164,6 +196,7 @@ fn concurrent_read(c: &mut Criterion) {
         .map(|i| Point {
             id: PointId::Id(i),
             payload: json!({ "msg": format!("Hello world {}", i) }),
+            version: 0,
         })
         .collect();
 
@@ -174,13 +207,23 @@ fn concurrent_read(c: &mut Criterion) {
             "test_collection".to_string(),
             CollectionConfig {
                 params: "...".to_string(),
+                shard_count: DEFAULT_SHARD_COUNT,
+                resharding_shard_count: None,
+                sharding_method: ShardingMethod::Auto,
+                shard_keys: BTreeMap::new(),
+                ring: RingConfig::default(),
+                storage: StorageType::Sled,
             },
             tempdir.path(),
+            ChannelService::default(),
         )
         .await
         .unwrap();
 
-        collection.upsert_points(points, true).await.unwrap();
+        collection
+            .upsert_points(points, &ShardSelector::Auto, true, None)
+            .await
+            .unwrap();
 
         collection
     });
@@ -189,7 +232,12 @@ fn concurrent_read(c: &mut Criterion) {
         b.to_async(&rt).iter(|| async {
             for chunk in point_ids.chunks(chunk_size) {
                 collection
-                    .get_points(Some(chunk.to_vec()), None, true)
+                    .get_points(
+                        Some(chunk.to_vec()),
+                        &ShardSelector::Auto,
+                        ReadConsistency::One,
+                        true,
+                    )
                     .await
                     .unwrap();
             }
@@ -0,0 +1,39 @@
+use crate::{
+    api::{collection::Dispatcher, helpers},
+    storage::{
+        backup::{BackupDescription, BackupStore},
+        error::StorageError,
+    },
+};
+use actix_web::{web, Responder};
+use std::sync::Arc;
+
+fn backup_store(dispatcher: &Dispatcher) -> Result<&Arc<BackupStore>, StorageError> {
+    dispatcher.backup_store.as_ref().ok_or_else(|| {
+        StorageError::BadInput("Backups are disabled, start the peer with --backup-url".to_string())
+    })
+}
+
+/// Uploads the files that changed since the latest backup, see [`BackupStore`]
+#[actix_web::post("/backups")]
+async fn create_backup(dispatcher: web::Data<Dispatcher>) -> impl Responder {
+    helpers::time(async {
+        let store = backup_store(&dispatcher)?;
+        let consensus_state = dispatcher.serialized_consensus_state().await?;
+        Ok(dispatcher.toc.create_backup(store, consensus_state).await?)
+    })
+    .await
+}
+
+/// Backups of the chain, oldest first. Restore one with `smoldb --restore-backup <id>`
+#[actix_web::get("/backups")]
+async fn list_backups(dispatcher: web::Data<Dispatcher>) -> impl Responder {
+    helpers::time(async {
+        let backups = backup_store(&dispatcher)?.list_backups().await?;
+        Ok(backups
+            .iter()
+            .map(BackupDescription::from)
+            .collect::<Vec<_>>())
+    })
+    .await
+}
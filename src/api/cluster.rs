@@ -2,6 +2,7 @@ use crate::{
     api::{collection::Dispatcher, helpers},
     consensus::{ConsensusOperation, Msg},
     storage::error::CollectionError,
+    types::PeerId,
 };
 use actix_web::{web, HttpResponse, Responder};
 use serde_json::json;
@@ -21,7 +22,7 @@ impl ConsensusAppData {
             .send(Msg::Propose {
                 id: 100, // Example ID, should be replaced with actual logic
                 operation,
-                callback: Box::new(|| println!("Callback executed for adding peer")),
+                callback: Box::new(|| println!("Callback executed for consensus operation")),
             })
             .expect("Failed to send message to consensus");
     }
@@ -43,6 +44,74 @@ async fn get_cluster(dispatcher: web::Data<Dispatcher>) -> impl Responder {
     .await
 }
 
+/// Writes waiting to be handed off to each peer
+#[actix_web::get("/cluster/hints")]
+async fn get_hints(dispatcher: web::Data<Dispatcher>) -> impl Responder {
+    helpers::time(async { Ok(dispatcher.toc.hints_info()?) }).await
+}
+
+/// Moves every shard replica held by the peer to the other peers in the background,
+/// the peer doesn't get replicas of new collections anymore
+#[actix_web::post("/cluster/peer/{peer_id}/drain")]
+async fn drain_peer(
+    peer_id: web::Path<PeerId>,
+    dispatcher: web::Data<Dispatcher>,
+) -> impl Responder {
+    helpers::time(async {
+        let toc = dispatcher.toc.clone();
+        Ok(toc.drain_peer(peer_id.into_inner()).await?)
+    })
+    .await
+}
+
+/// Progress of a drain started through this peer
+#[actix_web::get("/cluster/peer/{peer_id}/drain")]
+async fn get_drain_progress(
+    peer_id: web::Path<PeerId>,
+    dispatcher: web::Data<Dispatcher>,
+) -> impl Responder {
+    helpers::time(async { Ok(dispatcher.toc.drain_progress(peer_id.into_inner())?) }).await
+}
+
+/// Removes a drained peer from the cluster, once it doesn't hold any replica anymore
+#[actix_web::delete("/cluster/peer/{peer_id}")]
+async fn remove_peer(
+    peer_id: web::Path<PeerId>,
+    dispatcher: web::Data<Dispatcher>,
+) -> impl Responder {
+    helpers::time(async {
+        dispatcher.remove_peer(peer_id.into_inner()).await?;
+        Ok(true)
+    })
+    .await
+}
+
+/// Planned shard moves of the rebalancer and their progress
+#[actix_web::get("/cluster/rebalance")]
+async fn get_rebalance(dispatcher: web::Data<Dispatcher>) -> impl Responder {
+    helpers::time(async { Ok(dispatcher.toc.rebalancer.status()) }).await
+}
+
+/// Plans the shard moves again without waiting for a peer to join or leave
+#[actix_web::post("/cluster/rebalance")]
+async fn rebalance(dispatcher: web::Data<Dispatcher>) -> impl Responder {
+    helpers::time(async {
+        dispatcher.toc.rebalancer.replan()?;
+        Ok(true)
+    })
+    .await
+}
+
+#[actix_web::post("/cluster/rebalance/pause")]
+async fn pause_rebalance(dispatcher: web::Data<Dispatcher>) -> impl Responder {
+    helpers::time(async { Ok(dispatcher.toc.rebalancer.set_paused(true)?) }).await
+}
+
+#[actix_web::post("/cluster/rebalance/resume")]
+async fn resume_rebalance(dispatcher: web::Data<Dispatcher>) -> impl Responder {
+    helpers::time(async { Ok(dispatcher.toc.rebalancer.set_paused(false)?) }).await
+}
+
 // ToDo: Drop this API?
 #[actix_web::get("/cluster/peer/add")]
 async fn add_peer(consensus: web::Data<ConsensusAppData>) -> HttpResponse {
@@ -1,37 +1,125 @@
+use crate::api::grpc::p2p_grpc_schema::{raft_client::RaftClient, PeerId as GrpcPeerId};
 use crate::api::helpers;
 use crate::consensus::{ConsensusState, Persistent};
-use crate::storage::collection::{Collection, CollectionInfo};
-use crate::storage::error::CollectionError;
-use crate::storage::toc::{CollectionMetaOperation, TableOfContent};
-use crate::types::{PeerId, ShardId};
+use crate::failure_detector::PeerHealth;
+use crate::settings::Settings;
+use crate::storage::backup::BackupStore;
+use crate::storage::collection::{Collection, CollectionConfig, CollectionInfo};
+use crate::storage::error::{CollectionError, StorageError};
+use crate::storage::replicas::ring::{RingConfig, DEFAULT_VIRTUAL_NODES};
+use crate::storage::replicas::ShardingMethod;
+use crate::storage::segment::StorageType;
+use crate::storage::toc::{CollectionMetaOperation, PeerAck, TableOfContent};
+use crate::types::{PeerId, ShardId, ShardKey};
 use actix_web::{
     web::{self, Json},
     Responder,
 };
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+#[derive(Serialize)]
+pub struct ClusterInfo {
+    #[serde(flatten)]
+    pub persistent: Persistent,
+    /// Liveness of the other peers as seen by this peer
+    pub peer_health: BTreeMap<PeerId, PeerHealth>,
+    /// Peers excluded from new placements, see `POST /cluster/peer/{id}/drain`
+    pub draining_peers: Vec<PeerId>,
+}
 
 // Router that decides if query should go through ToC or consensus
 pub struct Dispatcher {
     pub toc: Arc<TableOfContent>,
     pub consensus_state: Option<Arc<ConsensusState>>,
+    /// Set with `--backup-url`
+    pub backup_store: Option<Arc<BackupStore>>,
 }
 
 impl Dispatcher {
-    pub fn from(toc: Arc<TableOfContent>, consensus_state: Option<Arc<ConsensusState>>) -> Self {
+    pub fn from(
+        toc: Arc<TableOfContent>,
+        consensus_state: Option<Arc<ConsensusState>>,
+        backup_store: Option<Arc<BackupStore>>,
+    ) -> Self {
         Dispatcher {
             toc,
             consensus_state,
+            backup_store,
+        }
+    }
+
+    /// Consensus state stored in snapshots and backups of the storage
+    pub async fn serialized_consensus_state(&self) -> Result<Vec<u8>, StorageError> {
+        match &self.consensus_state {
+            Some(consensus_state) => {
+                let persistent = consensus_state.persistent.read().await;
+                serde_json::to_vec(&*persistent).map_err(|e| {
+                    StorageError::ServiceError(format!("Failed to serialize consensus state: {e}"))
+                })
+            }
+            None => Ok(b"{}".to_vec()),
         }
     }
 
-    pub async fn get_cluster_info(&self) -> Option<Persistent> {
+    pub async fn get_cluster_info(&self) -> Option<ClusterInfo> {
         if let Some(consensus_state) = &self.consensus_state {
-            Some(consensus_state.persistent.read().await.clone())
+            Some(ClusterInfo {
+                persistent: consensus_state.persistent.read().await.clone(),
+                peer_health: self.toc.channel_service.failure_detector.health(),
+                draining_peers: self.toc.drains.draining_peers(),
+            })
         } else {
             None
         }
     }
+
+    /// Removes a drained peer from the consensus state of every other peer, then of this one.
+    pub async fn remove_peer(&self, peer_id: PeerId) -> Result<(), StorageError> {
+        let consensus_state = self.consensus_state.as_ref().ok_or_else(|| {
+            StorageError::ServiceError("Consensus state is not available".to_string())
+        })?;
+
+        let channel_service = &self.toc.channel_service;
+        if peer_id == channel_service.this_peer_id {
+            return Err(StorageError::BadInput(
+                "Peer can't remove itself, send the request to another peer".to_string(),
+            ));
+        }
+
+        self.toc.check_peer_removable(peer_id).await?;
+
+        for (other_peer_id, uri) in channel_service.other_peers().await {
+            if other_peer_id == peer_id {
+                continue;
+            }
+
+            let request = async {
+                let channel = channel_service
+                    .channel_pool
+                    .get_or_create_channel(uri)
+                    .await?;
+                RaftClient::new(channel)
+                    .remove_peer(GrpcPeerId { id: peer_id })
+                    .await?;
+                Ok::<_, CollectionError>(())
+            };
+
+            request.await.map_err(|e| {
+                StorageError::ServiceError(format!(
+                    "Peer {other_peer_id} failed to remove peer {peer_id}: {e}"
+                ))
+            })?;
+        }
+
+        consensus_state
+            .remove_peer(peer_id)
+            .await
+            .map_err(|e| StorageError::ServiceError(format!("Failed to remove peer: {e}")))?;
+        self.toc.forget_peer(peer_id);
+
+        Ok(())
+    }
 }
 
 #[actix_web::get("/collections")]
@@ -144,7 +232,11 @@ impl CollectionClusterInfo {
                 remote_shards.push(CollectionClusterRemoteShard {
                     peer_id: remote_shard.peer_id,
                     shard_id: remote_shard.id,
-                    state: "Active".to_string(), // ToDo: Placeholder for actual state
+                    state: if remote_shard.is_alive() {
+                        "Active".to_string()
+                    } else {
+                        "Dead".to_string()
+                    },
                 });
             }
         }
@@ -158,6 +250,87 @@ impl CollectionClusterInfo {
     }
 }
 
+#[derive(Serialize)]
+pub struct ShardDistribution {
+    pub shard_id: ShardId,
+    pub weight: u32,
+    /// Fraction of the hash space owned by the shard, within its key with custom sharding
+    pub ring_share: f64,
+    pub point_count: usize,
+    /// Fraction of the points of the collection held by the shard
+    pub point_share: f64,
+}
+
+/// How evenly the points of a collection are spread over its shards, based on the local replicas
+#[derive(Serialize)]
+pub struct CollectionDistribution {
+    pub ring: RingConfig,
+    pub point_count: usize,
+    pub shards: Vec<ShardDistribution>,
+}
+
+impl CollectionDistribution {
+    pub async fn from(collection: &Collection) -> Self {
+        let replica_holder = collection.replica_holder.read().await;
+        let ring = replica_holder.ring_config().clone();
+        let ring_shares = replica_holder.ring_shares();
+
+        let mut point_counts = replica_holder
+            .shards
+            .iter()
+            .map(|(shard_id, replica_set)| (*shard_id, replica_set.local.count_points()))
+            .collect::<Vec<_>>();
+        point_counts.sort();
+        let point_count = point_counts.iter().map(|(_, count)| count).sum::<usize>();
+
+        let shards = point_counts
+            .into_iter()
+            .map(|(shard_id, count)| ShardDistribution {
+                shard_id,
+                weight: ring.weight(shard_id),
+                ring_share: ring_shares.get(&shard_id).copied().unwrap_or(0.0),
+                point_count: count,
+                point_share: if point_count == 0 {
+                    0.0
+                } else {
+                    count as f64 / point_count as f64
+                },
+            })
+            .collect();
+
+        CollectionDistribution {
+            ring,
+            point_count,
+            shards,
+        }
+    }
+}
+
+#[actix_web::get("/collections/{collection_name}/distribution")]
+async fn get_collection_distribution(
+    collection_name: web::Path<String>,
+    dispatcher: web::Data<Dispatcher>,
+) -> impl Responder {
+    helpers::time(async {
+        let collection_name = collection_name.into_inner();
+
+        if let Some(collection) = dispatcher
+            .toc
+            .collections
+            .read()
+            .await
+            .get(&collection_name)
+        {
+            return Ok(CollectionDistribution::from(collection).await);
+        }
+
+        Err(CollectionError::ServiceError(format!(
+            "Collection: {collection_name} doesn't exist",
+        )))
+    })
+    .await
+}
+
 #[actix_web::get("/collections/{collection_name}/cluster")]
 async fn get_collection_cluster_info(
     collection_name: web::Path<String>,
@@ -186,31 +359,271 @@ async fn get_collection_cluster_info(
 #[derive(Serialize, Deserialize)]
 pub struct CreateCollection {
     pub params: String,
+    pub shard_count: Option<u32>,
+    /// `custom` collections start without shards, they are added with `PUT /collections/{name}/shard_keys/{key}`
+    #[serde(default)]
+    pub sharding_method: ShardingMethod,
+    /// Virtual nodes per shard on the hash ring, defaults to [`DEFAULT_VIRTUAL_NODES`]
+    pub virtual_nodes: Option<u32>,
+    /// Relative weight of the shards, 1 if missing
+    #[serde(default)]
+    pub shard_weights: BTreeMap<ShardId, u32>,
+    /// Engine of the segments: `sled` (default), `log` or `memory`. `memory` collections are
+    /// never written to disk, their points are lost on restart
+    #[serde(default)]
+    pub storage: StorageType,
+}
+
+#[derive(Deserialize)]
+pub struct CreateCollectionParams {
+    /// Wait until every peer created the collection
+    #[serde(default)]
+    pub wait: bool,
+    /// Seconds to wait for the other peers, defaults to `cluster.wait_timeout_sec` of the settings
+    pub timeout: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum CreateCollectionResponse {
+    Created(bool),
+    Acknowledged {
+        created: bool,
+        /// True if every peer created the collection in time
+        acknowledged: bool,
+        peers: BTreeMap<PeerId, PeerAck>,
+    },
 }
 
+/// Creates the collection locally and then on every other peer.
+/// With `wait=true` the response is sent once all the peers acknowledged it, or timed out.
 #[actix_web::put("/collections/{collection_name}")]
 async fn create_collection(
     collection_name: web::Path<String>,
     operation: Json<CreateCollection>,
+    params: web::Query<CreateCollectionParams>,
     dispatcher: web::Data<Dispatcher>,
+    settings: web::Data<Settings>,
 ) -> impl Responder {
     helpers::time(async {
         let collection_name = collection_name.into_inner();
+        let CreateCollection {
+            params: collection_params,
+            shard_count,
+            sharding_method,
+            virtual_nodes,
+            shard_weights,
+            storage,
+        } = operation.into_inner();
+
+        let ring = RingConfig {
+            virtual_nodes: virtual_nodes.unwrap_or(DEFAULT_VIRTUAL_NODES),
+            shard_weights,
+        };
+        let config = CollectionConfig::new(
+            collection_params.clone(),
+            shard_count,
+            sharding_method,
+            ring.clone(),
+            storage,
+        );
 
         // ToDo: Push this to consensus instead of directly committing locally?
-        let result = dispatcher
+        let created = dispatcher
             .toc
             .perform_collection_meta_op(CollectionMetaOperation::CreateCollection {
                 collection_name: collection_name.clone(),
-                params: operation.params.clone(),
+                params: collection_params,
+                shard_count,
+                sharding_method,
+                ring,
+                storage,
             })
+            .await
+            .map_err(CollectionError::StorageError)?;
+
+        let timeout = params
+            .timeout
+            .map(Duration::from_secs)
+            .unwrap_or_else(|| settings.cluster.wait_timeout());
+
+        let toc = dispatcher.toc.clone();
+        let create_on_peers = async move {
+            toc.create_collection_on_peers(&collection_name, &config, timeout)
+                .await
+        };
+
+        if !params.wait {
+            tokio::spawn(async move {
+                for (peer_id, ack) in create_on_peers.await {
+                    if !ack.status.is_ok() {
+                        eprintln!("Failed to create collection on peer {peer_id}: {ack:?}");
+                    }
+                }
+            });
+            return Ok(CreateCollectionResponse::Created(created));
+        }
+
+        let peers = create_on_peers.await;
+        Ok(CreateCollectionResponse::Acknowledged {
+            created,
+            acknowledged: peers.values().all(|ack| ack.status.is_ok()),
+            peers,
+        })
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct CreateShardKeyParams {
+    /// Number of shards of the key
+    #[serde(default = "default_key_shard_count")]
+    pub shard_count: u32,
+    /// Wait until every peer created the shards of the key
+    #[serde(default)]
+    pub wait: bool,
+    /// Seconds to wait for the other peers, defaults to `cluster.wait_timeout_sec` of the settings
+    pub timeout: Option<u64>,
+}
+
+fn default_key_shard_count() -> u32 {
+    1
+}
+
+/// Adds the shards of a key to a collection with custom sharding, locally and then on every other peer.
+/// Same as collections, `wait=true` waits for the acknowledgements of the other peers.
+#[actix_web::put("/collections/{collection_name}/shard_keys/{shard_key}")]
+async fn create_shard_key(
+    path: web::Path<(String, ShardKey)>,
+    params: web::Query<CreateShardKeyParams>,
+    dispatcher: web::Data<Dispatcher>,
+    settings: web::Data<Settings>,
+) -> impl Responder {
+    helpers::time(async {
+        let (collection_name, shard_key) = path.into_inner();
+
+        if params.shard_count == 0 {
+            return Err(CollectionError::StorageError(StorageError::BadInput(
+                "Shard key needs at least one shard".to_string(),
+            )));
+        }
+
+        // An existing key keeps its shards, creating it again only waits for the peers
+        let (created, shard_ids) = dispatcher
+            .toc
+            .create_shard_key(&collection_name, shard_key.clone(), params.shard_count)
+            .await?;
+
+        let timeout = params
+            .timeout
+            .map(Duration::from_secs)
+            .unwrap_or_else(|| settings.cluster.wait_timeout());
+
+        let toc = dispatcher.toc.clone();
+        let create_on_peers = async move {
+            toc.create_shard_key_on_peers(&collection_name, &shard_key, &shard_ids, timeout)
+                .await
+        };
+
+        if !params.wait {
+            tokio::spawn(async move {
+                for (peer_id, ack) in create_on_peers.await {
+                    if !ack.status.is_ok() {
+                        eprintln!("Failed to create shard key on peer {peer_id}: {ack:?}");
+                    }
+                }
+            });
+            return Ok(CreateCollectionResponse::Created(created));
+        }
+
+        let peers = create_on_peers.await;
+        Ok(CreateCollectionResponse::Acknowledged {
+            created,
+            acknowledged: peers.values().all(|ack| ack.status.is_ok()),
+            peers,
+        })
+    })
+    .await
+}
+
+#[actix_web::get("/collections/{collection_name}/shard_keys")]
+async fn list_shard_keys(
+    collection_name: web::Path<String>,
+    dispatcher: web::Data<Dispatcher>,
+) -> impl Responder {
+    helpers::time(async {
+        let collection_name = collection_name.into_inner();
+        let collections = dispatcher.toc.collections.read().await;
+        let collection = collections.get(&collection_name).ok_or_else(|| {
+            CollectionError::ServiceError(format!(
+                "Collection with name '{collection_name}' does not exist"
+            ))
+        })?;
+
+        Ok(collection.config.shard_keys.clone())
+    })
+    .await
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UpdateShardCount {
+    pub shard_count: u32,
+}
+
+/// Changes the number of shards of a collection without downtime.
+///
+/// The new shards are created locally and then on every other peer, the response holds their
+/// acknowledgements. Once all of them started, every peer migrates its points to the new shards
+/// in the background and this peer finishes the resharding on the others before switching itself.
+/// Calling it again with the same shard count resumes an interrupted resharding.
+#[actix_web::put("/collections/{collection_name}/shards")]
+async fn update_shard_count(
+    collection_name: web::Path<String>,
+    operation: Json<UpdateShardCount>,
+    dispatcher: web::Data<Dispatcher>,
+    settings: web::Data<Settings>,
+) -> impl Responder {
+    helpers::time(async {
+        let collection_name = collection_name.into_inner();
+        let shard_count = operation.shard_count;
+        let toc = dispatcher.toc.clone();
+
+        toc.check_resharding(&collection_name, shard_count)
+            .await
+            .map_err(CollectionError::StorageError)?;
+
+        let started = toc
+            .perform_collection_meta_op(CollectionMetaOperation::StartResharding {
+                collection_name: collection_name.clone(),
+                shard_count,
+            })
+            .await?;
+
+        let timeout = settings.cluster.wait_timeout();
+        let peers = toc
+            .start_resharding_on_peers(&collection_name, shard_count, timeout)
             .await;
+        let acknowledged = peers.values().all(|ack| ack.status.is_ok());
 
-        // ToDo: Return Created() and BadRequest() based on the result?
-        match result {
-            Ok(res) => Ok(res),
-            Err(e) => Err(CollectionError::StorageError(e)),
+        if acknowledged {
+            tokio::spawn(async move {
+                match toc
+                    .finish_resharding_everywhere(&collection_name, shard_count, timeout)
+                    .await
+                {
+                    Ok(()) => {
+                        println!("Resharded collection {collection_name} to {shard_count} shards")
+                    }
+                    Err(e) => eprintln!("Resharding of collection {collection_name} failed: {e}"),
+                }
+            });
         }
+
+        Ok(CreateCollectionResponse::Acknowledged {
+            created: started,
+            acknowledged,
+            peers,
+        })
     })
     .await
 }
@@ -0,0 +1,291 @@
+use crate::{
+    api::grpc::p2p_grpc_schema::{
+        collections_internal_server::CollectionsInternal, CreateCollectionRequest,
+        CreateCollectionResponse, CreateShardKeyRequest, DrainPeerRequest, DrainPeerResponse,
+        ReshardingRequest,
+    },
+    storage::{
+        collection::CollectionConfig,
+        replicas::ring::RingConfig,
+        toc::{CollectionMetaOperation, TableOfContent},
+    },
+};
+use std::sync::Arc;
+use tonic::{async_trait, Request, Response, Status};
+
+pub struct CollectionsInternalService {
+    toc: Arc<TableOfContent>,
+}
+
+impl CollectionsInternalService {
+    pub fn new(toc: Arc<TableOfContent>) -> Self {
+        CollectionsInternalService { toc }
+    }
+
+    /// Whether the collection already has `shard_count` shards and isn't being resharded
+    async fn resharded(&self, collection_name: &str, shard_count: u32) -> Result<bool, Status> {
+        let collections = self.toc.collections.read().await;
+        let collection = collections.get(collection_name).ok_or_else(|| {
+            Status::not_found(format!("Collection '{collection_name}' does not exist"))
+        })?;
+        Ok(collection.config.resharding_shard_count.is_none()
+            && collection.config.shard_count == shard_count)
+    }
+
+    /// Acknowledges a resharding step with the local shards of the collection
+    async fn resharding_response(
+        &self,
+        collection_name: &str,
+        created: bool,
+    ) -> Result<Response<CreateCollectionResponse>, Status> {
+        let collections = self.toc.collections.read().await;
+        let collection = collections.get(collection_name).ok_or_else(|| {
+            Status::not_found(format!("Collection '{collection_name}' was deleted"))
+        })?;
+
+        let mut shard_ids = collection
+            .replica_holder
+            .read()
+            .await
+            .shards
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        shard_ids.sort();
+
+        Ok(Response::new(CreateCollectionResponse {
+            created,
+            shard_ids,
+        }))
+    }
+}
+
+#[async_trait]
+impl CollectionsInternal for CollectionsInternalService {
+    async fn create(
+        &self,
+        request: Request<CreateCollectionRequest>,
+    ) -> Result<Response<CreateCollectionResponse>, Status> {
+        let request = request.into_inner();
+        let sharding_method = request.sharding_method().into();
+        let storage = request.storage().into();
+        let CreateCollectionRequest {
+            collection_name,
+            params,
+            shard_count,
+            virtual_nodes,
+            shard_weights,
+            ..
+        } = request;
+
+        let ring = match virtual_nodes {
+            Some(virtual_nodes) => RingConfig {
+                virtual_nodes,
+                shard_weights: shard_weights.into_iter().collect(),
+            },
+            None => RingConfig::legacy(),
+        };
+
+        println!("Received internal request to create collection: {collection_name}");
+
+        // Creation is retried by the coordinating peer, so an existing collection is fine
+        // as long as it was created with the same config
+        let requested = CollectionConfig::new(
+            params.clone(),
+            shard_count,
+            sharding_method,
+            ring.clone(),
+            storage,
+        );
+        let exists = match self.toc.collections.read().await.get(&collection_name) {
+            Some(collection) => {
+                collection.config.check_matches(&requested).map_err(|e| {
+                    Status::already_exists(format!("Collection '{collection_name}': {e}"))
+                })?;
+                true
+            }
+            None => false,
+        };
+
+        let created = if exists {
+            false
+        } else {
+            self.toc
+                .perform_collection_meta_op(CollectionMetaOperation::CreateCollection {
+                    collection_name: collection_name.clone(),
+                    params,
+                    shard_count,
+                    sharding_method,
+                    ring,
+                    storage,
+                })
+                .await
+                .map_err(|e| {
+                    Status::internal(format!(
+                        "Failed to create collection '{collection_name}': {e}"
+                    ))
+                })?
+        };
+
+        let collections = self.toc.collections.read().await;
+        let collection = collections.get(&collection_name).ok_or_else(|| {
+            Status::not_found(format!("Collection '{collection_name}' was deleted"))
+        })?;
+
+        let mut shard_ids = collection
+            .replica_holder
+            .read()
+            .await
+            .shards
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        shard_ids.sort();
+
+        Ok(Response::new(CreateCollectionResponse {
+            created,
+            shard_ids,
+        }))
+    }
+
+    async fn create_shard_key(
+        &self,
+        request: Request<CreateShardKeyRequest>,
+    ) -> Result<Response<CreateCollectionResponse>, Status> {
+        let CreateShardKeyRequest {
+            collection_name,
+            shard_key,
+            shard_ids,
+        } = request.into_inner();
+
+        println!(
+            "Received internal request to create shard key {shard_key} of collection {collection_name}"
+        );
+
+        // Same as collections, an existing key with the same shards is fine
+        let created = self
+            .toc
+            .perform_collection_meta_op(CollectionMetaOperation::CreateShardKey {
+                collection_name: collection_name.clone(),
+                shard_key: shard_key.clone(),
+                shard_ids: shard_ids.clone(),
+            })
+            .await
+            .map_err(|e| {
+                Status::internal(format!(
+                    "Failed to create shard key {shard_key} of collection '{collection_name}': {e}"
+                ))
+            })?;
+
+        let mut shard_ids = shard_ids;
+        shard_ids.sort();
+
+        Ok(Response::new(CreateCollectionResponse {
+            created,
+            shard_ids,
+        }))
+    }
+
+    async fn start_resharding(
+        &self,
+        request: Request<ReshardingRequest>,
+    ) -> Result<Response<CreateCollectionResponse>, Status> {
+        let ReshardingRequest {
+            collection_name,
+            shard_count,
+        } = request.into_inner();
+
+        println!(
+            "Received internal request to reshard collection {collection_name} to {shard_count} shards"
+        );
+
+        // Retried by the coordinating peer, a collection that already finished is fine
+        if self.resharded(&collection_name, shard_count).await? {
+            return self.resharding_response(&collection_name, false).await;
+        }
+
+        let started = self
+            .toc
+            .perform_collection_meta_op(CollectionMetaOperation::StartResharding {
+                collection_name: collection_name.clone(),
+                shard_count,
+            })
+            .await
+            .map_err(|e| {
+                Status::internal(format!(
+                    "Failed to start resharding of collection '{collection_name}': {e}"
+                ))
+            })?;
+
+        // Every peer moves the points of its own shards
+        let toc = self.toc.clone();
+        let name = collection_name.clone();
+        tokio::spawn(async move {
+            match toc.migrate_resharded_points(&name).await {
+                Ok(moved_points) => {
+                    println!("Moved {moved_points} points of collection {name} while resharding")
+                }
+                Err(e) => eprintln!("Resharding of collection {name} failed: {e}"),
+            }
+        });
+
+        self.resharding_response(&collection_name, started).await
+    }
+
+    async fn finish_resharding(
+        &self,
+        request: Request<ReshardingRequest>,
+    ) -> Result<Response<CreateCollectionResponse>, Status> {
+        let ReshardingRequest {
+            collection_name,
+            shard_count,
+        } = request.into_inner();
+
+        println!("Received internal request to finish resharding of collection {collection_name}");
+
+        let finished = self
+            .toc
+            .perform_collection_meta_op(CollectionMetaOperation::FinishResharding {
+                collection_name: collection_name.clone(),
+                shard_count,
+            })
+            .await
+            .map_err(|e| {
+                Status::internal(format!(
+                    "Failed to finish resharding of collection '{collection_name}': {e}"
+                ))
+            })?;
+
+        self.resharding_response(&collection_name, finished).await
+    }
+
+    async fn mark_draining(
+        &self,
+        request: Request<DrainPeerRequest>,
+    ) -> Result<Response<DrainPeerResponse>, Status> {
+        let peer_id = request.into_inner().peer_id;
+        println!("Peer {peer_id} is being drained, excluding it from new placements");
+
+        self.toc.drains.mark(peer_id);
+
+        Ok(Response::new(DrainPeerResponse {
+            removed_replicas: 0,
+        }))
+    }
+
+    async fn detach_peer(
+        &self,
+        request: Request<DrainPeerRequest>,
+    ) -> Result<Response<DrainPeerResponse>, Status> {
+        let peer_id = request.into_inner().peer_id;
+
+        let removed_replicas = self.toc.detach_peer(peer_id).await.map_err(|e| {
+            Status::internal(format!("Failed to detach drained peer {peer_id}: {e}"))
+        })?;
+        println!("Dropped {removed_replicas} replicas of drained peer {peer_id}");
+
+        Ok(Response::new(DrainPeerResponse {
+            removed_replicas: removed_replicas as u32,
+        }))
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{
+    api::grpc::p2p_grpc_schema::{
+        payload_value::Kind, point_id::PointIdOptions, NullValue, PayloadList, PayloadStruct,
+        PayloadValue, Point as GrpcPoint, PointId as GrpcPointId,
+        ShardingMethod as GrpcShardingMethod, StorageType as GrpcStorageType,
+    },
+    storage::{
+        error::StorageError,
+        replicas::ShardingMethod,
+        segment::{Point, PointId, StorageType},
+    },
+};
+
+impl From<PointId> for GrpcPointId {
+    fn from(value: PointId) -> Self {
+        let point_id_options = match value {
+            PointId::Id(num) => PointIdOptions::Num(num),
+            PointId::Uuid(uuid) => PointIdOptions::Uuid(uuid.to_string()),
+        };
+
+        GrpcPointId {
+            point_id_options: Some(point_id_options),
+        }
+    }
+}
+
+impl TryFrom<GrpcPointId> for PointId {
+    type Error = StorageError;
+
+    fn try_from(value: GrpcPointId) -> Result<Self, Self::Error> {
+        match value.point_id_options {
+            Some(PointIdOptions::Num(num)) => Ok(PointId::Id(num)),
+            Some(PointIdOptions::Uuid(uuid)) => uuid.parse(),
+            None => Err(StorageError::BadInput("Point id is empty".to_string())),
+        }
+    }
+}
+
+impl From<ShardingMethod> for GrpcShardingMethod {
+    fn from(value: ShardingMethod) -> Self {
+        match value {
+            ShardingMethod::Auto => GrpcShardingMethod::Auto,
+            ShardingMethod::Custom => GrpcShardingMethod::Custom,
+        }
+    }
+}
+
+impl From<GrpcShardingMethod> for ShardingMethod {
+    fn from(value: GrpcShardingMethod) -> Self {
+        match value {
+            GrpcShardingMethod::Auto => ShardingMethod::Auto,
+            GrpcShardingMethod::Custom => ShardingMethod::Custom,
+        }
+    }
+}
+
+impl From<StorageType> for GrpcStorageType {
+    fn from(value: StorageType) -> Self {
+        match value {
+            StorageType::Sled => GrpcStorageType::Sled,
+            StorageType::Log => GrpcStorageType::Log,
+            StorageType::Memory => GrpcStorageType::Memory,
+        }
+    }
+}
+
+impl From<GrpcStorageType> for StorageType {
+    fn from(value: GrpcStorageType) -> Self {
+        match value {
+            GrpcStorageType::Sled => StorageType::Sled,
+            GrpcStorageType::Log => StorageType::Log,
+            GrpcStorageType::Memory => StorageType::Memory,
+        }
+    }
+}
+
+impl From<Point> for GrpcPoint {
+    fn from(value: Point) -> Self {
+        GrpcPoint {
+            id: Some(value.id.into()),
+            payload: Some(json_to_proto(value.payload)),
+            version: value.version,
+        }
+    }
+}
+
+impl TryFrom<GrpcPoint> for Point {
+    type Error = StorageError;
+
+    fn try_from(value: GrpcPoint) -> Result<Self, Self::Error> {
+        let id = value
+            .id
+            .ok_or_else(|| StorageError::BadInput("Point id is missing".to_string()))?
+            .try_into()?;
+
+        let payload = match value.payload {
+            Some(payload) => proto_to_json(payload)?,
+            None => serde_json::Value::Null,
+        };
+
+        Ok(Point {
+            id,
+            payload,
+            version: value.version,
+        })
+    }
+}
+
+pub fn points_to_grpc(points: Vec<Point>) -> Vec<GrpcPoint> {
+    points.into_iter().map(GrpcPoint::from).collect()
+}
+
+pub fn points_from_grpc(points: Vec<GrpcPoint>) -> Result<Vec<Point>, StorageError> {
+    points.into_iter().map(Point::try_from).collect()
+}
+
+pub fn point_ids_to_grpc(ids: Vec<PointId>) -> Vec<GrpcPointId> {
+    ids.into_iter().map(GrpcPointId::from).collect()
+}
+
+pub fn point_ids_from_grpc(ids: Vec<GrpcPointId>) -> Result<Vec<PointId>, StorageError> {
+    ids.into_iter().map(PointId::try_from).collect()
+}
+
+fn json_to_proto(value: serde_json::Value) -> PayloadValue {
+    let kind = match value {
+        serde_json::Value::Null => Kind::NullValue(NullValue::NullValue.into()),
+        serde_json::Value::Bool(b) => Kind::BoolValue(b),
+        serde_json::Value::Number(number) => {
+            if let Some(integer) = number.as_i64() {
+                Kind::IntegerValue(integer)
+            } else if let Some(unsigned) = number.as_u64() {
+                Kind::UnsignedValue(unsigned)
+            } else {
+                Kind::DoubleValue(number.as_f64().unwrap_or_default())
+            }
+        }
+        serde_json::Value::String(s) => Kind::StringValue(s),
+        serde_json::Value::Array(values) => Kind::ListValue(PayloadList {
+            values: values.into_iter().map(json_to_proto).collect(),
+        }),
+        serde_json::Value::Object(map) => Kind::StructValue(PayloadStruct {
+            fields: map
+                .into_iter()
+                .map(|(key, value)| (key, json_to_proto(value)))
+                .collect(),
+        }),
+    };
+
+    PayloadValue { kind: Some(kind) }
+}
+
+fn proto_to_json(value: PayloadValue) -> Result<serde_json::Value, StorageError> {
+    let json = match value.kind {
+        None | Some(Kind::NullValue(_)) => serde_json::Value::Null,
+        Some(Kind::BoolValue(b)) => serde_json::Value::Bool(b),
+        Some(Kind::IntegerValue(integer)) => serde_json::Value::from(integer),
+        Some(Kind::UnsignedValue(unsigned)) => serde_json::Value::from(unsigned),
+        Some(Kind::DoubleValue(number)) => serde_json::Number::from_f64(number)
+            .map(serde_json::Value::Number)
+            .ok_or_else(|| {
+                StorageError::BadInput(format!("Payload number {number} is not valid JSON"))
+            })?,
+        Some(Kind::StringValue(s)) => serde_json::Value::String(s),
+        Some(Kind::ListValue(list)) => serde_json::Value::Array(
+            list.values
+                .into_iter()
+                .map(proto_to_json)
+                .collect::<Result<_, _>>()?,
+        ),
+        Some(Kind::StructValue(map)) => serde_json::Value::Object(
+            map.fields
+                .into_iter()
+                .map(|(key, value)| Ok((key, proto_to_json(value)?)))
+                .collect::<Result<_, StorageError>>()?,
+        ),
+    };
+
+    Ok(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_point_round_trip() {
+        let points = vec![
+            Point {
+                id: PointId::Id(0),
+                payload: json!({ "msg": "hello world" }),
+                version: 1,
+            },
+            Point {
+                id: PointId::Uuid("5c56c793-69f3-4fbf-87e6-c4bf54c28c26".parse().unwrap()),
+                payload: json!({
+                    "int": -42,
+                    "float": 0.5,
+                    "nested": { "list": [1, "two", null, true, { "a": [] }] },
+                }),
+                version: u64::MAX,
+            },
+            Point {
+                id: PointId::Id(u64::MAX),
+                payload: json!("not an object"),
+                version: 0,
+            },
+            Point {
+                id: PointId::Id(1),
+                // Numbers keep their type and precision
+                payload: json!({
+                    "double": 2.0,
+                    "above_f64_precision": (1u64 << 53) + 1,
+                    "u64_max": u64::MAX,
+                    "i64_min": i64::MIN,
+                    "i64_max": i64::MAX,
+                    "doubles": [-0.0, 1e300, f64::MIN_POSITIVE],
+                }),
+                version: 2,
+            },
+        ];
+
+        let grpc_points = points_to_grpc(points.clone());
+        assert_eq!(grpc_points.len(), points.len());
+
+        let round_trip = points_from_grpc(grpc_points).unwrap();
+        for (point, expected) in round_trip.iter().zip(&points) {
+            assert_eq!(point.id, expected.id);
+            assert_eq!(point.payload, expected.payload);
+            assert_eq!(point.payload.to_string(), expected.payload.to_string());
+            assert_eq!(point.version, expected.version);
+        }
+
+        assert!(Point::try_from(GrpcPoint::default()).is_err());
+    }
+}
@@ -1,13 +1,17 @@
 #[rustfmt::skip] // tonic uses `prettyplease` to format its output
 pub mod p2p_grpc_schema;
 
+mod collections_service;
+pub mod conversions;
 mod points_service;
 mod raft_service;
 mod simple_service;
 
 use crate::{
     api::grpc::{
+        collections_service::CollectionsInternalService,
         p2p_grpc_schema::{
+            collections_internal_server::CollectionsInternalServer,
             points_internal_server::PointsInternalServer, raft_server::RaftServer,
             service_server::ServiceServer,
         },
@@ -77,11 +81,14 @@ pub async fn init(
         toc.clone(),
         consensus_state.clone(),
     ));
+    let collections_service =
+        CollectionsInternalServer::new(CollectionsInternalService::new(toc.clone()));
     let points_service = PointsInternalServer::new(PointsInternalService::new(toc));
 
     server
         .add_service(p2p_service)
         .add_service(raft_service)
+        .add_service(collections_service)
         .add_service(points_service)
         .serve_with_shutdown(socket, async {
             #[cfg(unix)]
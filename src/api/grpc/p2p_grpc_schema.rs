@@ -26,15 +26,21 @@ pub struct Peer {
     pub uri: ::prost::alloc::string::String,
     #[prost(uint64, tag = "2")]
     pub id: u64,
+    #[prost(string, optional, tag = "3")]
+    pub http_uri: ::core::option::Option<::prost::alloc::string::String>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct AddPeerToKnownMessage {
+    /// Advertised p2p uri, if missing the address the request came from is used with `port`
     #[prost(string, optional, tag = "1")]
     pub uri: ::core::option::Option<::prost::alloc::string::String>,
     #[prost(uint32, optional, tag = "2")]
     pub port: ::core::option::Option<u32>,
     #[prost(uint64, tag = "3")]
     pub id: u64,
+    /// Advertised uri of the HTTP API
+    #[prost(string, optional, tag = "4")]
+    pub http_uri: ::core::option::Option<::prost::alloc::string::String>,
 }
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct PeerId {
@@ -47,6 +53,60 @@ pub struct Uri {
     pub uri: ::prost::alloc::string::String,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateCollectionRequest {
+    #[prost(string, tag = "1")]
+    pub collection_name: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub params: ::prost::alloc::string::String,
+    #[prost(uint32, optional, tag = "3")]
+    pub shard_count: ::core::option::Option<u32>,
+    #[prost(enumeration = "ShardingMethod", tag = "4")]
+    pub sharding_method: i32,
+    /// Missing if the coordinating peer doesn't know virtual nodes, which means one per shard
+    #[prost(uint32, optional, tag = "5")]
+    pub virtual_nodes: ::core::option::Option<u32>,
+    #[prost(map = "uint32, uint32", tag = "6")]
+    pub shard_weights: ::std::collections::HashMap<u32, u32>,
+    #[prost(enumeration = "StorageType", tag = "7")]
+    pub storage: i32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateShardKeyRequest {
+    #[prost(string, tag = "1")]
+    pub collection_name: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub shard_key: ::prost::alloc::string::String,
+    #[prost(uint32, repeated, tag = "3")]
+    pub shard_ids: ::prost::alloc::vec::Vec<u32>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateCollectionResponse {
+    /// false if the collection already existed
+    #[prost(bool, tag = "1")]
+    pub created: bool,
+    /// local shards of the collection on the peer
+    #[prost(uint32, repeated, tag = "2")]
+    pub shard_ids: ::prost::alloc::vec::Vec<u32>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReshardingRequest {
+    #[prost(string, tag = "1")]
+    pub collection_name: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "2")]
+    pub shard_count: u32,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct DrainPeerRequest {
+    #[prost(uint64, tag = "1")]
+    pub peer_id: u64,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct DrainPeerResponse {
+    /// replicas of the drained peer dropped by this peer
+    #[prost(uint32, tag = "1")]
+    pub removed_replicas: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct UpsertPointsRequest {
     #[prost(string, tag = "1")]
     pub collection_name: ::prost::alloc::string::String,
@@ -69,21 +129,195 @@ pub struct GetPointsResponse {
 pub struct GetPointsRequest {
     #[prost(string, tag = "1")]
     pub collection_name: ::prost::alloc::string::String,
-    #[prost(uint64, repeated, tag = "2")]
-    pub ids: ::prost::alloc::vec::Vec<u64>,
+    #[prost(message, repeated, tag = "5")]
+    pub ids: ::prost::alloc::vec::Vec<PointId>,
     /// If true, return all points in the collection
     #[prost(bool, tag = "3")]
     pub return_all: bool,
     #[prost(uint32, optional, tag = "4")]
     pub shard_id: ::core::option::Option<u32>,
+    /// With `limit`, returns a page of the points of `shard_id` after `offset` instead
+    #[prost(message, optional, tag = "6")]
+    pub offset: ::core::option::Option<PointId>,
+    #[prost(uint32, optional, tag = "7")]
+    pub limit: ::core::option::Option<u32>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetRangeHashesRequest {
+    #[prost(string, tag = "1")]
+    pub collection_name: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "2")]
+    pub shard_id: u32,
+    #[prost(uint32, tag = "3")]
+    pub num_ranges: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetRangeHashesResponse {
+    #[prost(uint64, repeated, tag = "1")]
+    pub hashes: ::prost::alloc::vec::Vec<u64>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetPointsInRangesRequest {
+    #[prost(string, tag = "1")]
+    pub collection_name: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "2")]
+    pub shard_id: u32,
+    #[prost(uint32, tag = "3")]
+    pub num_ranges: u32,
+    #[prost(uint32, repeated, tag = "4")]
+    pub ranges: ::prost::alloc::vec::Vec<u32>,
+    /// With `limit`, returns a page of the points of the ranges after `offset` instead of all of them
+    #[prost(message, optional, tag = "5")]
+    pub offset: ::core::option::Option<PointId>,
+    #[prost(uint32, optional, tag = "6")]
+    pub limit: ::core::option::Option<u32>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PointId {
+    #[prost(oneof = "point_id::PointIdOptions", tags = "1, 2")]
+    pub point_id_options: ::core::option::Option<point_id::PointIdOptions>,
+}
+/// Nested message and enum types in `PointId`.
+pub mod point_id {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum PointIdOptions {
+        #[prost(uint64, tag = "1")]
+        Num(u64),
+        #[prost(string, tag = "2")]
+        Uuid(::prost::alloc::string::String),
+    }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Point {
-    #[prost(uint64, tag = "1")]
-    pub id: u64,
-    /// limiting the payload type for now
-    #[prost(string, tag = "2")]
-    pub payload: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "4")]
+    pub id: ::core::option::Option<PointId>,
+    /// any JSON value
+    #[prost(message, optional, tag = "5")]
+    pub payload: ::core::option::Option<PayloadValue>,
+    /// hybrid logical clock of the write, newest version wins
+    #[prost(uint64, tag = "3")]
+    pub version: u64,
+}
+/// Same wire format as google.protobuf.Value, with integers kept apart from doubles
+/// so that they don't lose precision and doubles stay doubles
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PayloadValue {
+    #[prost(oneof = "payload_value::Kind", tags = "1, 2, 3, 4, 5, 6, 7, 8")]
+    pub kind: ::core::option::Option<payload_value::Kind>,
+}
+/// Nested message and enum types in `PayloadValue`.
+pub mod payload_value {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Kind {
+        #[prost(enumeration = "super::NullValue", tag = "1")]
+        NullValue(i32),
+        #[prost(double, tag = "2")]
+        DoubleValue(f64),
+        #[prost(string, tag = "3")]
+        StringValue(::prost::alloc::string::String),
+        #[prost(bool, tag = "4")]
+        BoolValue(bool),
+        #[prost(message, tag = "5")]
+        StructValue(super::PayloadStruct),
+        #[prost(message, tag = "6")]
+        ListValue(super::PayloadList),
+        #[prost(int64, tag = "7")]
+        IntegerValue(i64),
+        /// only for integers above the range of int64
+        #[prost(uint64, tag = "8")]
+        UnsignedValue(u64),
+    }
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PayloadStruct {
+    #[prost(map = "string, message", tag = "1")]
+    pub fields: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        PayloadValue,
+    >,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PayloadList {
+    #[prost(message, repeated, tag = "1")]
+    pub values: ::prost::alloc::vec::Vec<PayloadValue>,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ShardingMethod {
+    Auto = 0,
+    Custom = 1,
+}
+impl ShardingMethod {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Auto => "AUTO",
+            Self::Custom => "CUSTOM",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "AUTO" => Some(Self::Auto),
+            "CUSTOM" => Some(Self::Custom),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum StorageType {
+    Sled = 0,
+    Memory = 1,
+    Log = 2,
+}
+impl StorageType {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Sled => "SLED",
+            Self::Memory => "MEMORY",
+            Self::Log => "LOG",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "SLED" => Some(Self::Sled),
+            "MEMORY" => Some(Self::Memory),
+            "LOG" => Some(Self::Log),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum NullValue {
+    NullValue = 0,
+}
+impl NullValue {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::NullValue => "NULL_VALUE",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "NULL_VALUE" => Some(Self::NullValue),
+            _ => None,
+        }
+    }
 }
 /// Generated client implementations.
 pub mod service_client {
@@ -359,10 +593,32 @@ pub mod raft_client {
                 .insert(GrpcMethod::new("p2p_grpc_schema.Raft", "AddPeerToKnown"));
             self.inner.unary(req, path, codec).await
         }
+        /// Forgets a drained peer, see `DELETE /cluster/peer/{id}`
+        pub async fn remove_peer(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PeerId>,
+        ) -> std::result::Result<tonic::Response<()>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/p2p_grpc_schema.Raft/RemovePeer",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("p2p_grpc_schema.Raft", "RemovePeer"));
+            self.inner.unary(req, path, codec).await
+        }
     }
 }
 /// Generated client implementations.
-pub mod points_internal_client {
+pub mod collections_internal_client {
     #![allow(
         unused_variables,
         dead_code,
@@ -373,10 +629,10 @@ pub mod points_internal_client {
     use tonic::codegen::*;
     use tonic::codegen::http::Uri;
     #[derive(Debug, Clone)]
-    pub struct PointsInternalClient<T> {
+    pub struct CollectionsInternalClient<T> {
         inner: tonic::client::Grpc<T>,
     }
-    impl PointsInternalClient<tonic::transport::Channel> {
+    impl CollectionsInternalClient<tonic::transport::Channel> {
         /// Attempt to create a new client by connecting to a given endpoint.
         pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
         where
@@ -387,7 +643,7 @@ pub mod points_internal_client {
             Ok(Self::new(conn))
         }
     }
-    impl<T> PointsInternalClient<T>
+    impl<T> CollectionsInternalClient<T>
     where
         T: tonic::client::GrpcService<tonic::body::Body>,
         T::Error: Into<StdError>,
@@ -405,7 +661,7 @@ pub mod points_internal_client {
         pub fn with_interceptor<F>(
             inner: T,
             interceptor: F,
-        ) -> PointsInternalClient<InterceptedService<T, F>>
+        ) -> CollectionsInternalClient<InterceptedService<T, F>>
         where
             F: tonic::service::Interceptor,
             T::ResponseBody: Default,
@@ -419,7 +675,7 @@ pub mod points_internal_client {
                 http::Request<tonic::body::Body>,
             >>::Error: Into<StdError> + std::marker::Send + std::marker::Sync,
         {
-            PointsInternalClient::new(InterceptedService::new(inner, interceptor))
+            CollectionsInternalClient::new(InterceptedService::new(inner, interceptor))
         }
         /// Compress requests with the given encoding.
         ///
@@ -452,11 +708,12 @@ pub mod points_internal_client {
             self.inner = self.inner.max_encoding_message_size(limit);
             self
         }
-        pub async fn get_points(
+        /// Creates the collection and its local shards, acknowledges once they are ready
+        pub async fn create(
             &mut self,
-            request: impl tonic::IntoRequest<super::GetPointsRequest>,
+            request: impl tonic::IntoRequest<super::CreateCollectionRequest>,
         ) -> std::result::Result<
-            tonic::Response<super::GetPointsResponse>,
+            tonic::Response<super::CreateCollectionResponse>,
             tonic::Status,
         > {
             self.inner
@@ -469,18 +726,21 @@ pub mod points_internal_client {
                 })?;
             let codec = tonic::codec::ProstCodec::default();
             let path = http::uri::PathAndQuery::from_static(
-                "/p2p_grpc_schema.PointsInternal/GetPoints",
+                "/p2p_grpc_schema.CollectionsInternal/Create",
             );
             let mut req = request.into_request();
             req.extensions_mut()
-                .insert(GrpcMethod::new("p2p_grpc_schema.PointsInternal", "GetPoints"));
+                .insert(
+                    GrpcMethod::new("p2p_grpc_schema.CollectionsInternal", "Create"),
+                );
             self.inner.unary(req, path, codec).await
         }
-        pub async fn upsert_points(
+        /// Creates the shards of a key with the ids chosen by the coordinating peer
+        pub async fn create_shard_key(
             &mut self,
-            request: impl tonic::IntoRequest<super::UpsertPointsRequest>,
+            request: impl tonic::IntoRequest<super::CreateShardKeyRequest>,
         ) -> std::result::Result<
-            tonic::Response<super::UpsertPointsResponse>,
+            tonic::Response<super::CreateCollectionResponse>,
             tonic::Status,
         > {
             self.inner
@@ -493,19 +753,139 @@ pub mod points_internal_client {
                 })?;
             let codec = tonic::codec::ProstCodec::default();
             let path = http::uri::PathAndQuery::from_static(
-                "/p2p_grpc_schema.PointsInternal/UpsertPoints",
+                "/p2p_grpc_schema.CollectionsInternal/CreateShardKey",
             );
             let mut req = request.into_request();
             req.extensions_mut()
                 .insert(
-                    GrpcMethod::new("p2p_grpc_schema.PointsInternal", "UpsertPoints"),
+                    GrpcMethod::new(
+                        "p2p_grpc_schema.CollectionsInternal",
+                        "CreateShardKey",
+                    ),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        /// Stops placing new replicas on the peer while it's being drained
+        pub async fn mark_draining(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DrainPeerRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::DrainPeerResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/p2p_grpc_schema.CollectionsInternal/MarkDraining",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new(
+                        "p2p_grpc_schema.CollectionsInternal",
+                        "MarkDraining",
+                    ),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        /// Drops the replicas held by the drained peer, the drained peer itself drops all its shards
+        pub async fn detach_peer(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DrainPeerRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::DrainPeerResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/p2p_grpc_schema.CollectionsInternal/DetachPeer",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("p2p_grpc_schema.CollectionsInternal", "DetachPeer"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        /// Creates the new shards of the collection and migrates the local points to them in the background
+        pub async fn start_resharding(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ReshardingRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::CreateCollectionResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/p2p_grpc_schema.CollectionsInternal/StartResharding",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new(
+                        "p2p_grpc_schema.CollectionsInternal",
+                        "StartResharding",
+                    ),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        /// Migrates the remaining local points and switches to the new shards
+        pub async fn finish_resharding(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ReshardingRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::CreateCollectionResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/p2p_grpc_schema.CollectionsInternal/FinishResharding",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new(
+                        "p2p_grpc_schema.CollectionsInternal",
+                        "FinishResharding",
+                    ),
                 );
             self.inner.unary(req, path, codec).await
         }
     }
 }
-/// Generated server implementations.
-pub mod service_server {
+/// Generated client implementations.
+pub mod points_internal_client {
     #![allow(
         unused_variables,
         dead_code,
@@ -514,44 +894,243 @@ pub mod service_server {
         clippy::let_unit_value,
     )]
     use tonic::codegen::*;
-    /// Generated trait containing gRPC methods that should be implemented for use with ServiceServer.
-    #[async_trait]
-    pub trait Service: std::marker::Send + std::marker::Sync + 'static {
-        /// Returns the title and version of the service - useful for debugging
-        async fn root_api(
-            &self,
-            request: tonic::Request<super::RootApiRequest>,
-        ) -> std::result::Result<tonic::Response<super::RootApiReply>, tonic::Status>;
+    use tonic::codegen::http::Uri;
+    #[derive(Debug, Clone)]
+    pub struct PointsInternalClient<T> {
+        inner: tonic::client::Grpc<T>,
     }
-    #[derive(Debug)]
-    pub struct ServiceServer<T> {
-        inner: Arc<T>,
-        accept_compression_encodings: EnabledCompressionEncodings,
-        send_compression_encodings: EnabledCompressionEncodings,
-        max_decoding_message_size: Option<usize>,
-        max_encoding_message_size: Option<usize>,
+    impl PointsInternalClient<tonic::transport::Channel> {
+        /// Attempt to create a new client by connecting to a given endpoint.
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
     }
-    impl<T> ServiceServer<T> {
+    impl<T> PointsInternalClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::Body>,
+        T::Error: Into<StdError>,
+        T::ResponseBody: Body<Data = Bytes> + std::marker::Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + std::marker::Send,
+    {
         pub fn new(inner: T) -> Self {
-            Self::from_arc(Arc::new(inner))
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
         }
-        pub fn from_arc(inner: Arc<T>) -> Self {
-            Self {
-                inner,
-                accept_compression_encodings: Default::default(),
-                send_compression_encodings: Default::default(),
-                max_decoding_message_size: None,
-                max_encoding_message_size: None,
-            }
+        pub fn with_origin(inner: T, origin: Uri) -> Self {
+            let inner = tonic::client::Grpc::with_origin(inner, origin);
+            Self { inner }
         }
         pub fn with_interceptor<F>(
             inner: T,
             interceptor: F,
-        ) -> InterceptedService<Self, F>
+        ) -> PointsInternalClient<InterceptedService<T, F>>
         where
             F: tonic::service::Interceptor,
+            T::ResponseBody: Default,
+            T: tonic::codegen::Service<
+                http::Request<tonic::body::Body>,
+                Response = http::Response<
+                    <T as tonic::client::GrpcService<tonic::body::Body>>::ResponseBody,
+                >,
+            >,
+            <T as tonic::codegen::Service<
+                http::Request<tonic::body::Body>,
+            >>::Error: Into<StdError> + std::marker::Send + std::marker::Sync,
         {
-            InterceptedService::new(Self::new(inner), interceptor)
+            PointsInternalClient::new(InterceptedService::new(inner, interceptor))
+        }
+        /// Compress requests with the given encoding.
+        ///
+        /// This requires the server to support it otherwise it might respond with an
+        /// error.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.send_compressed(encoding);
+            self
+        }
+        /// Enable decompressing responses.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.accept_compressed(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_decoding_message_size(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_encoding_message_size(limit);
+            self
+        }
+        pub async fn get_points(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetPointsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetPointsResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/p2p_grpc_schema.PointsInternal/GetPoints",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("p2p_grpc_schema.PointsInternal", "GetPoints"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn upsert_points(
+            &mut self,
+            request: impl tonic::IntoRequest<super::UpsertPointsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::UpsertPointsResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/p2p_grpc_schema.PointsInternal/UpsertPoints",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("p2p_grpc_schema.PointsInternal", "UpsertPoints"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        /// Anti-entropy: compare replicas range by range and only transfer the differing ranges
+        pub async fn get_range_hashes(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetRangeHashesRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetRangeHashesResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/p2p_grpc_schema.PointsInternal/GetRangeHashes",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("p2p_grpc_schema.PointsInternal", "GetRangeHashes"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn get_points_in_ranges(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetPointsInRangesRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetPointsResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/p2p_grpc_schema.PointsInternal/GetPointsInRanges",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new(
+                        "p2p_grpc_schema.PointsInternal",
+                        "GetPointsInRanges",
+                    ),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+    }
+}
+/// Generated server implementations.
+pub mod service_server {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with ServiceServer.
+    #[async_trait]
+    pub trait Service: std::marker::Send + std::marker::Sync + 'static {
+        /// Returns the title and version of the service - useful for debugging
+        async fn root_api(
+            &self,
+            request: tonic::Request<super::RootApiRequest>,
+        ) -> std::result::Result<tonic::Response<super::RootApiReply>, tonic::Status>;
+    }
+    #[derive(Debug)]
+    pub struct ServiceServer<T> {
+        inner: Arc<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    impl<T> ServiceServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
         }
         /// Enable decompressing requests with the given encoding.
         #[must_use]
@@ -713,6 +1292,11 @@ pub mod raft_server {
             &self,
             request: tonic::Request<super::AddPeerToKnownMessage>,
         ) -> std::result::Result<tonic::Response<super::AllPeers>, tonic::Status>;
+        /// Forgets a drained peer, see `DELETE /cluster/peer/{id}`
+        async fn remove_peer(
+            &self,
+            request: tonic::Request<super::PeerId>,
+        ) -> std::result::Result<tonic::Response<()>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct RaftServer<T> {
@@ -921,6 +1505,49 @@ pub mod raft_server {
                     };
                     Box::pin(fut)
                 }
+                "/p2p_grpc_schema.Raft/RemovePeer" => {
+                    #[allow(non_camel_case_types)]
+                    struct RemovePeerSvc<T: Raft>(pub Arc<T>);
+                    impl<T: Raft> tonic::server::UnaryService<super::PeerId>
+                    for RemovePeerSvc<T> {
+                        type Response = ();
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PeerId>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Raft>::remove_peer(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = RemovePeerSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         let mut response = http::Response::new(
@@ -962,7 +1589,7 @@ pub mod raft_server {
     }
 }
 /// Generated server implementations.
-pub mod points_internal_server {
+pub mod collections_internal_server {
     #![allow(
         unused_variables,
         dead_code,
@@ -971,33 +1598,67 @@ pub mod points_internal_server {
         clippy::let_unit_value,
     )]
     use tonic::codegen::*;
-    /// Generated trait containing gRPC methods that should be implemented for use with PointsInternalServer.
+    /// Generated trait containing gRPC methods that should be implemented for use with CollectionsInternalServer.
     #[async_trait]
-    pub trait PointsInternal: std::marker::Send + std::marker::Sync + 'static {
-        async fn get_points(
+    pub trait CollectionsInternal: std::marker::Send + std::marker::Sync + 'static {
+        /// Creates the collection and its local shards, acknowledges once they are ready
+        async fn create(
             &self,
-            request: tonic::Request<super::GetPointsRequest>,
+            request: tonic::Request<super::CreateCollectionRequest>,
         ) -> std::result::Result<
-            tonic::Response<super::GetPointsResponse>,
+            tonic::Response<super::CreateCollectionResponse>,
             tonic::Status,
         >;
-        async fn upsert_points(
+        /// Creates the shards of a key with the ids chosen by the coordinating peer
+        async fn create_shard_key(
             &self,
-            request: tonic::Request<super::UpsertPointsRequest>,
+            request: tonic::Request<super::CreateShardKeyRequest>,
         ) -> std::result::Result<
-            tonic::Response<super::UpsertPointsResponse>,
+            tonic::Response<super::CreateCollectionResponse>,
+            tonic::Status,
+        >;
+        /// Stops placing new replicas on the peer while it's being drained
+        async fn mark_draining(
+            &self,
+            request: tonic::Request<super::DrainPeerRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::DrainPeerResponse>,
+            tonic::Status,
+        >;
+        /// Drops the replicas held by the drained peer, the drained peer itself drops all its shards
+        async fn detach_peer(
+            &self,
+            request: tonic::Request<super::DrainPeerRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::DrainPeerResponse>,
+            tonic::Status,
+        >;
+        /// Creates the new shards of the collection and migrates the local points to them in the background
+        async fn start_resharding(
+            &self,
+            request: tonic::Request<super::ReshardingRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::CreateCollectionResponse>,
+            tonic::Status,
+        >;
+        /// Migrates the remaining local points and switches to the new shards
+        async fn finish_resharding(
+            &self,
+            request: tonic::Request<super::ReshardingRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::CreateCollectionResponse>,
             tonic::Status,
         >;
     }
     #[derive(Debug)]
-    pub struct PointsInternalServer<T> {
+    pub struct CollectionsInternalServer<T> {
         inner: Arc<T>,
         accept_compression_encodings: EnabledCompressionEncodings,
         send_compression_encodings: EnabledCompressionEncodings,
         max_decoding_message_size: Option<usize>,
         max_encoding_message_size: Option<usize>,
     }
-    impl<T> PointsInternalServer<T> {
+    impl<T> CollectionsInternalServer<T> {
         pub fn new(inner: T) -> Self {
             Self::from_arc(Arc::new(inner))
         }
@@ -1048,9 +1709,9 @@ pub mod points_internal_server {
             self
         }
     }
-    impl<T, B> tonic::codegen::Service<http::Request<B>> for PointsInternalServer<T>
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for CollectionsInternalServer<T>
     where
-        T: PointsInternal,
+        T: CollectionsInternal,
         B: Body + std::marker::Send + 'static,
         B::Error: Into<StdError> + std::marker::Send + 'static,
     {
@@ -1065,25 +1726,25 @@ pub mod points_internal_server {
         }
         fn call(&mut self, req: http::Request<B>) -> Self::Future {
             match req.uri().path() {
-                "/p2p_grpc_schema.PointsInternal/GetPoints" => {
+                "/p2p_grpc_schema.CollectionsInternal/Create" => {
                     #[allow(non_camel_case_types)]
-                    struct GetPointsSvc<T: PointsInternal>(pub Arc<T>);
+                    struct CreateSvc<T: CollectionsInternal>(pub Arc<T>);
                     impl<
-                        T: PointsInternal,
-                    > tonic::server::UnaryService<super::GetPointsRequest>
-                    for GetPointsSvc<T> {
-                        type Response = super::GetPointsResponse;
+                        T: CollectionsInternal,
+                    > tonic::server::UnaryService<super::CreateCollectionRequest>
+                    for CreateSvc<T> {
+                        type Response = super::CreateCollectionResponse;
                         type Future = BoxFuture<
                             tonic::Response<Self::Response>,
                             tonic::Status,
                         >;
                         fn call(
                             &mut self,
-                            request: tonic::Request<super::GetPointsRequest>,
+                            request: tonic::Request<super::CreateCollectionRequest>,
                         ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
                             let fut = async move {
-                                <T as PointsInternal>::get_points(&inner, request).await
+                                <T as CollectionsInternal>::create(&inner, request).await
                             };
                             Box::pin(fut)
                         }
@@ -1094,7 +1755,7 @@ pub mod points_internal_server {
                     let max_encoding_message_size = self.max_encoding_message_size;
                     let inner = self.inner.clone();
                     let fut = async move {
-                        let method = GetPointsSvc(inner);
+                        let method = CreateSvc(inner);
                         let codec = tonic::codec::ProstCodec::default();
                         let mut grpc = tonic::server::Grpc::new(codec)
                             .apply_compression_config(
@@ -1110,9 +1771,452 @@ pub mod points_internal_server {
                     };
                     Box::pin(fut)
                 }
-                "/p2p_grpc_schema.PointsInternal/UpsertPoints" => {
+                "/p2p_grpc_schema.CollectionsInternal/CreateShardKey" => {
                     #[allow(non_camel_case_types)]
-                    struct UpsertPointsSvc<T: PointsInternal>(pub Arc<T>);
+                    struct CreateShardKeySvc<T: CollectionsInternal>(pub Arc<T>);
+                    impl<
+                        T: CollectionsInternal,
+                    > tonic::server::UnaryService<super::CreateShardKeyRequest>
+                    for CreateShardKeySvc<T> {
+                        type Response = super::CreateCollectionResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CreateShardKeyRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as CollectionsInternal>::create_shard_key(
+                                        &inner,
+                                        request,
+                                    )
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = CreateShardKeySvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/p2p_grpc_schema.CollectionsInternal/MarkDraining" => {
+                    #[allow(non_camel_case_types)]
+                    struct MarkDrainingSvc<T: CollectionsInternal>(pub Arc<T>);
+                    impl<
+                        T: CollectionsInternal,
+                    > tonic::server::UnaryService<super::DrainPeerRequest>
+                    for MarkDrainingSvc<T> {
+                        type Response = super::DrainPeerResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DrainPeerRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as CollectionsInternal>::mark_draining(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = MarkDrainingSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/p2p_grpc_schema.CollectionsInternal/DetachPeer" => {
+                    #[allow(non_camel_case_types)]
+                    struct DetachPeerSvc<T: CollectionsInternal>(pub Arc<T>);
+                    impl<
+                        T: CollectionsInternal,
+                    > tonic::server::UnaryService<super::DrainPeerRequest>
+                    for DetachPeerSvc<T> {
+                        type Response = super::DrainPeerResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DrainPeerRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as CollectionsInternal>::detach_peer(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = DetachPeerSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/p2p_grpc_schema.CollectionsInternal/StartResharding" => {
+                    #[allow(non_camel_case_types)]
+                    struct StartReshardingSvc<T: CollectionsInternal>(pub Arc<T>);
+                    impl<
+                        T: CollectionsInternal,
+                    > tonic::server::UnaryService<super::ReshardingRequest>
+                    for StartReshardingSvc<T> {
+                        type Response = super::CreateCollectionResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ReshardingRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as CollectionsInternal>::start_resharding(
+                                        &inner,
+                                        request,
+                                    )
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = StartReshardingSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/p2p_grpc_schema.CollectionsInternal/FinishResharding" => {
+                    #[allow(non_camel_case_types)]
+                    struct FinishReshardingSvc<T: CollectionsInternal>(pub Arc<T>);
+                    impl<
+                        T: CollectionsInternal,
+                    > tonic::server::UnaryService<super::ReshardingRequest>
+                    for FinishReshardingSvc<T> {
+                        type Response = super::CreateCollectionResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ReshardingRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as CollectionsInternal>::finish_resharding(
+                                        &inner,
+                                        request,
+                                    )
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = FinishReshardingSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => {
+                    Box::pin(async move {
+                        let mut response = http::Response::new(
+                            tonic::body::Body::default(),
+                        );
+                        let headers = response.headers_mut();
+                        headers
+                            .insert(
+                                tonic::Status::GRPC_STATUS,
+                                (tonic::Code::Unimplemented as i32).into(),
+                            );
+                        headers
+                            .insert(
+                                http::header::CONTENT_TYPE,
+                                tonic::metadata::GRPC_CONTENT_TYPE,
+                            );
+                        Ok(response)
+                    })
+                }
+            }
+        }
+    }
+    impl<T> Clone for CollectionsInternalServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+                max_decoding_message_size: self.max_decoding_message_size,
+                max_encoding_message_size: self.max_encoding_message_size,
+            }
+        }
+    }
+    /// Generated gRPC service name
+    pub const SERVICE_NAME: &str = "p2p_grpc_schema.CollectionsInternal";
+    impl<T> tonic::server::NamedService for CollectionsInternalServer<T> {
+        const NAME: &'static str = SERVICE_NAME;
+    }
+}
+/// Generated server implementations.
+pub mod points_internal_server {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with PointsInternalServer.
+    #[async_trait]
+    pub trait PointsInternal: std::marker::Send + std::marker::Sync + 'static {
+        async fn get_points(
+            &self,
+            request: tonic::Request<super::GetPointsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetPointsResponse>,
+            tonic::Status,
+        >;
+        async fn upsert_points(
+            &self,
+            request: tonic::Request<super::UpsertPointsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::UpsertPointsResponse>,
+            tonic::Status,
+        >;
+        /// Anti-entropy: compare replicas range by range and only transfer the differing ranges
+        async fn get_range_hashes(
+            &self,
+            request: tonic::Request<super::GetRangeHashesRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetRangeHashesResponse>,
+            tonic::Status,
+        >;
+        async fn get_points_in_ranges(
+            &self,
+            request: tonic::Request<super::GetPointsInRangesRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetPointsResponse>,
+            tonic::Status,
+        >;
+    }
+    #[derive(Debug)]
+    pub struct PointsInternalServer<T> {
+        inner: Arc<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    impl<T> PointsInternalServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for PointsInternalServer<T>
+    where
+        T: PointsInternal,
+        B: Body + std::marker::Send + 'static,
+        B::Error: Into<StdError> + std::marker::Send + 'static,
+    {
+        type Response = http::Response<tonic::body::Body>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            match req.uri().path() {
+                "/p2p_grpc_schema.PointsInternal/GetPoints" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetPointsSvc<T: PointsInternal>(pub Arc<T>);
+                    impl<
+                        T: PointsInternal,
+                    > tonic::server::UnaryService<super::GetPointsRequest>
+                    for GetPointsSvc<T> {
+                        type Response = super::GetPointsResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetPointsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as PointsInternal>::get_points(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetPointsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/p2p_grpc_schema.PointsInternal/UpsertPoints" => {
+                    #[allow(non_camel_case_types)]
+                    struct UpsertPointsSvc<T: PointsInternal>(pub Arc<T>);
                     impl<
                         T: PointsInternal,
                     > tonic::server::UnaryService<super::UpsertPointsRequest>
@@ -1155,6 +2259,98 @@ pub mod points_internal_server {
                     };
                     Box::pin(fut)
                 }
+                "/p2p_grpc_schema.PointsInternal/GetRangeHashes" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetRangeHashesSvc<T: PointsInternal>(pub Arc<T>);
+                    impl<
+                        T: PointsInternal,
+                    > tonic::server::UnaryService<super::GetRangeHashesRequest>
+                    for GetRangeHashesSvc<T> {
+                        type Response = super::GetRangeHashesResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetRangeHashesRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as PointsInternal>::get_range_hashes(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetRangeHashesSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/p2p_grpc_schema.PointsInternal/GetPointsInRanges" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetPointsInRangesSvc<T: PointsInternal>(pub Arc<T>);
+                    impl<
+                        T: PointsInternal,
+                    > tonic::server::UnaryService<super::GetPointsInRangesRequest>
+                    for GetPointsInRangesSvc<T> {
+                        type Response = super::GetPointsResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetPointsInRangesRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as PointsInternal>::get_points_in_ranges(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetPointsInRangesSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         let mut response = http::Response::new(
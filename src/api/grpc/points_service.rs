@@ -1,10 +1,15 @@
 use crate::{
-    api::grpc::p2p_grpc_schema::{
-        points_internal_server::PointsInternal, GetPointsRequest, GetPointsResponse,
-        Point as GrpcPoint, UpsertPointsRequest, UpsertPointsResponse,
+    api::grpc::{
+        conversions::{point_ids_from_grpc, points_from_grpc, points_to_grpc},
+        p2p_grpc_schema::{
+            points_internal_server::PointsInternal, GetPointsInRangesRequest, GetPointsRequest,
+            GetPointsResponse, GetRangeHashesRequest, GetRangeHashesResponse, UpsertPointsRequest,
+            UpsertPointsResponse,
+        },
     },
     storage::{
-        segment::{Point, PointId},
+        replicas::{consistency::ReadConsistency, ShardOperationTrait, ShardSelector},
+        segment::PointId,
         toc::TableOfContent,
     },
 };
@@ -32,6 +37,8 @@ impl PointsInternal for PointsInternalService {
             ids,
             return_all,
             shard_id,
+            offset,
+            limit,
         } = request.into_inner();
 
         println!("Received internal request to get points from collection: {collection_name}");
@@ -41,13 +48,56 @@ impl PointsInternal for PointsInternalService {
             tonic::Status::not_found(format!("Collection '{collection_name}' not found"))
         })?;
 
+        if let Some(limit) = limit {
+            let shard_id = shard_id.ok_or_else(|| {
+                tonic::Status::invalid_argument("Pages are read from a single shard")
+            })?;
+            let offset = offset
+                .map(PointId::try_from)
+                .transpose()
+                .map_err(|e| tonic::Status::invalid_argument(e.to_string()))?;
+
+            let local = collection
+                .replica_holder
+                .read()
+                .await
+                .get_replica_set(shard_id)
+                .await
+                .map_err(|e| tonic::Status::not_found(e.to_string()))?
+                .local
+                .clone();
+            drop(collections);
+
+            let points = local
+                .get_points_page(offset, limit as usize)
+                .await
+                .map_err(|e| {
+                    tonic::Status::internal(format!(
+                        "Failed to read points of shard {shard_id} of collection '{collection_name}': {e}"
+                    ))
+                })?;
+            return Ok(Response::new(GetPointsResponse {
+                points: points_to_grpc(points),
+            }));
+        }
+
         let point_ids = if return_all {
             None
         } else {
-            Some(ids.into_iter().map(PointId::Id).collect::<Vec<_>>())
+            Some(
+                point_ids_from_grpc(ids)
+                    .map_err(|e| tonic::Status::invalid_argument(e.to_string()))?,
+            )
         };
 
-        let points = collection.get_points(point_ids, shard_id, true).await;
+        let points = collection
+            .get_points(
+                point_ids,
+                &shard_id.map_or(ShardSelector::Auto, ShardSelector::Shard),
+                ReadConsistency::One,
+                true,
+            )
+            .await;
 
         let points = points.map_err(|e| {
             tonic::Status::internal(format!(
@@ -56,17 +106,7 @@ impl PointsInternal for PointsInternalService {
         })?;
 
         Ok(Response::new(GetPointsResponse {
-            points: points
-                .into_iter()
-                .filter_map(|p| {
-                    if let PointId::Id(id) = p.id {
-                        // FixMe: This is a workaround for not being able to pass json just yet.
-                        let payload = p.payload.to_string();
-                        return Some(GrpcPoint { id, payload });
-                    }
-                    None // ignore UUIDs for now
-                })
-                .collect(),
+            points: points_to_grpc(points),
         }))
     }
 
@@ -77,7 +117,7 @@ impl PointsInternal for PointsInternalService {
         let UpsertPointsRequest {
             collection_name,
             points,
-            shard_id: _, // ToDo: We should specify shard_id when upserting?
+            shard_id,
         } = _request.into_inner();
         println!("Received internal request to upsert points from collection: {collection_name}");
 
@@ -86,22 +126,107 @@ impl PointsInternal for PointsInternalService {
             tonic::Status::not_found(format!("Collection '{collection_name}' not found"))
         })?;
 
-        let points = points
-            .into_iter()
-            .map(|p| Point {
-                id: PointId::Id(p.id),
-                payload: serde_json::from_str(&p.payload).unwrap(),
-            })
-            .collect::<Vec<_>>();
+        let points =
+            points_from_grpc(points).map_err(|e| tonic::Status::invalid_argument(e.to_string()))?;
 
-        collection.upsert_points(points, true).await.map_err(|e| {
-            tonic::Status::internal(format!(
-                "Failed to upsert points in collection '{collection_name}': {e}"
-            ))
-        })?;
+        collection
+            .upsert_points(
+                points,
+                &shard_id.map_or(ShardSelector::Auto, ShardSelector::Shard),
+                true,
+                None,
+            )
+            .await
+            .map_err(|e| {
+                tonic::Status::internal(format!(
+                    "Failed to upsert points in collection '{collection_name}': {e}"
+                ))
+            })?;
 
         Ok(Response::new(UpsertPointsResponse {
             message: "Upsert operation completed".to_string(),
         }))
     }
+
+    async fn get_range_hashes(
+        &self,
+        request: tonic::Request<GetRangeHashesRequest>,
+    ) -> Result<Response<GetRangeHashesResponse>, tonic::Status> {
+        let GetRangeHashesRequest {
+            collection_name,
+            shard_id,
+            num_ranges,
+        } = request.into_inner();
+
+        let collections = self.toc.collections.read().await;
+        let collection = collections.get(&collection_name).ok_or_else(|| {
+            tonic::Status::not_found(format!("Collection '{collection_name}' not found"))
+        })?;
+
+        let replica_holder = collection.replica_holder.read().await;
+        let replica_set = replica_holder
+            .get_replica_set(shard_id)
+            .await
+            .map_err(|e| tonic::Status::not_found(e.to_string()))?;
+
+        let hashes = replica_set
+            .local
+            .range_hashes(num_ranges)
+            .await
+            .map_err(|e| {
+                tonic::Status::internal(format!(
+                    "Failed to compute range hashes of shard {shard_id} in collection '{collection_name}': {e}"
+                ))
+            })?;
+
+        Ok(Response::new(GetRangeHashesResponse { hashes }))
+    }
+
+    async fn get_points_in_ranges(
+        &self,
+        request: tonic::Request<GetPointsInRangesRequest>,
+    ) -> Result<Response<GetPointsResponse>, tonic::Status> {
+        let GetPointsInRangesRequest {
+            collection_name,
+            shard_id,
+            num_ranges,
+            ranges,
+            offset,
+            limit,
+        } = request.into_inner();
+        let offset = offset
+            .map(PointId::try_from)
+            .transpose()
+            .map_err(|e| tonic::Status::invalid_argument(e.to_string()))?;
+
+        let collections = self.toc.collections.read().await;
+        let collection = collections.get(&collection_name).ok_or_else(|| {
+            tonic::Status::not_found(format!("Collection '{collection_name}' not found"))
+        })?;
+
+        let replica_holder = collection.replica_holder.read().await;
+        let replica_set = replica_holder
+            .get_replica_set(shard_id)
+            .await
+            .map_err(|e| tonic::Status::not_found(e.to_string()))?;
+
+        let points = replica_set
+            .local
+            .get_points_in_ranges(
+                ranges.into_iter().collect(),
+                num_ranges,
+                offset,
+                limit.map_or(usize::MAX, |limit| limit as usize),
+            )
+            .await
+            .map_err(|e| {
+                tonic::Status::internal(format!(
+                    "Failed to retrieve points of shard {shard_id} in collection '{collection_name}': {e}"
+                ))
+            })?;
+
+        Ok(Response::new(GetPointsResponse {
+            points: points_to_grpc(points),
+        }))
+    }
 }
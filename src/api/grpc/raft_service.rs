@@ -1,16 +1,24 @@
 use crate::{
-    api::grpc::p2p_grpc_schema::{
-        raft_server::Raft, AddPeerToKnownMessage, AllPeers, Peer, PeerId,
-        RaftMessage as RaftMessageBytes, Uri,
+    api::grpc::{
+        make_grpc_channel,
+        p2p_grpc_schema::{
+            raft_server::Raft, service_client::ServiceClient, AddPeerToKnownMessage, AllPeers,
+            Peer, PeerId, RaftMessage as RaftMessageBytes, RootApiRequest, Uri,
+        },
     },
     consensus::{self, ConsensusState},
     storage::toc::TableOfContent,
 };
 use prost_for_raft::Message as ProtocolBufferMessage; // this trait is required for .decode() to work
 use raft::eraftpb::Message as RaftMessageParsed;
-use std::sync::{mpsc::Sender, Arc};
+use std::{
+    sync::{mpsc::Sender, Arc},
+    time::Duration,
+};
 use tonic::{Request, Response, Status};
 
+const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(3);
+
 pub struct RaftService {
     sender: Sender<consensus::Msg>,
     toc: Arc<TableOfContent>,
@@ -50,21 +58,55 @@ impl Raft for RaftService {
         Ok(Response::new(()))
     }
 
-    async fn who_is(&self, _request: Request<PeerId>) -> Result<Response<Uri>, Status> {
-        // Here you would implement the logic to return the URI of a peer by its ID.
-        // For now, we return an empty URI.
-        let uri = Uri {
-            uri: "smoldb:9900".to_string(),
-        };
-        Ok(Response::new(uri))
+    async fn who_is(&self, request: Request<PeerId>) -> Result<Response<Uri>, Status> {
+        let peer_id = request.into_inner().id;
+
+        let consensus_state = self
+            .consensus_state
+            .as_ref()
+            .ok_or_else(|| Status::internal("Consensus state is not available in RaftService"))?;
+
+        let uri = consensus_state
+            .peer_address_by_id
+            .read()
+            .await
+            .get(&peer_id)
+            .cloned()
+            .ok_or_else(|| Status::not_found(format!("Peer {peer_id} is not known")))?;
+
+        Ok(Response::new(Uri {
+            uri: uri.to_string(),
+        }))
+    }
+
+    async fn remove_peer(&self, request: Request<PeerId>) -> Result<Response<()>, Status> {
+        let peer_id = request.into_inner().id;
+
+        let consensus_state = self
+            .consensus_state
+            .as_ref()
+            .ok_or_else(|| Status::internal("Consensus state is not available in RaftService"))?;
+
+        self.toc
+            .check_peer_removable(peer_id)
+            .await
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+
+        consensus_state
+            .remove_peer(peer_id)
+            .await
+            .map_err(|e| Status::failed_precondition(format!("Failed to remove peer: {e}")))?;
+        self.toc.forget_peer(peer_id);
+        println!("Removed drained peer {peer_id}");
+
+        Ok(Response::new(()))
     }
 
     async fn add_peer_to_known(
         &self,
         request: Request<AddPeerToKnownMessage>,
     ) -> Result<Response<AllPeers>, Status> {
-        // Here you would implement the logic to add a peer to the known peers list.
-        // For now, we return an empty AllPeers response.
+        let remote_addr = request.remote_addr();
         let request = request.into_inner();
 
         let consensus_state = self
@@ -72,13 +114,30 @@ impl Raft for RaftService {
             .as_ref()
             .ok_or_else(|| Status::internal("Consensus state is not available in RaftService"))?;
 
-        let uri = request
-            .uri
-            .map(|u| u.parse::<http::Uri>().unwrap())
-            .unwrap();
+        let uri = match (&request.uri, request.port, remote_addr) {
+            (Some(uri), _, _) => uri
+                .parse::<http::Uri>()
+                .map_err(|e| Status::invalid_argument(format!("Invalid peer uri '{uri}': {e}")))?,
+            // Peer didn't advertise an address, use the one it connected from
+            (None, Some(port), Some(remote_addr)) => format!("http://{}:{port}", remote_addr.ip())
+                .parse::<http::Uri>()
+                .map_err(|e| Status::invalid_argument(format!("Invalid peer address: {e}")))?,
+            (None, _, _) => {
+                return Err(Status::invalid_argument(
+                    "Peer must advertise its uri or p2p port",
+                ))
+            }
+        };
+
+        check_reachable(&uri).await.map_err(|e| {
+            Status::failed_precondition(format!(
+                "Advertised address {uri} of peer {} is not reachable: {e}",
+                request.id
+            ))
+        })?;
 
         consensus_state
-            .add_peer(request.id, uri)
+            .add_peer(request.id, uri, request.http_uri)
             .await
             .map_err(|e| Status::internal(format!("Failed to add peer: {e}")))?;
 
@@ -100,7 +159,11 @@ impl Raft for RaftService {
         let all_peers = persistent
             .peers
             .into_iter()
-            .map(|(id, uri)| Peer { id, uri })
+            .map(|(id, uri)| Peer {
+                id,
+                uri,
+                http_uri: persistent.peer_http_uris.get(&id).cloned(),
+            })
             .collect();
 
         let this_peer_id = persistent.peer_id;
@@ -113,3 +176,13 @@ impl Raft for RaftService {
         Ok(Response::new(all_peers))
     }
 }
+
+/// Calls the p2p `RootApi` of the peer to make sure other peers will be able to reach it.
+async fn check_reachable(uri: &http::Uri) -> Result<(), Box<dyn std::error::Error>> {
+    let channel =
+        make_grpc_channel(REACHABILITY_TIMEOUT, REACHABILITY_TIMEOUT, uri.clone()).await?;
+    ServiceClient::new(channel)
+        .root_api(RootApiRequest {})
+        .await?;
+    Ok(())
+}
@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::future::Future;
 use tokio::time::Instant;
 
-use crate::storage::error::CollectionResult;
+use crate::storage::error::{CollectionError, CollectionResult, StorageError};
 
 type ResponseTime = f64;
 
@@ -35,14 +35,22 @@ where
 
             actix_web::HttpResponse::Ok().json(res)
         }
-        Err(e) => {
-            let res = ApiErrorResponse {
-                error: e.to_string(),
-                time: instant.elapsed().as_secs_f64(),
-            };
+        Err(e) => error_response(e, instant),
+    }
+}
+
+/// Error response of [`time`], for handlers that don't answer with JSON on success
+pub fn error_response(error: CollectionError, instant: Instant) -> HttpResponse {
+    let res = ApiErrorResponse {
+        error: error.to_string(),
+        time: instant.elapsed().as_secs_f64(),
+    };
 
-            actix_web::HttpResponse::InternalServerError().json(res)
+    match error {
+        CollectionError::StorageError(StorageError::BadInput(_)) => {
+            actix_web::HttpResponse::BadRequest().json(res)
         }
+        _ => actix_web::HttpResponse::InternalServerError().json(res),
     }
 }
 
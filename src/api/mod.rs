@@ -1,6 +1,8 @@
+pub mod backups;
 pub mod cluster;
 pub mod collection;
 pub mod grpc;
 pub mod helpers;
 pub mod points;
 pub mod service;
+pub mod snapshots;
@@ -1,9 +1,11 @@
 use crate::{
     api::{collection::Dispatcher, helpers},
     storage::{
-        error::CollectionError,
+        error::{CollectionError, StorageError},
+        replicas::consistency::ReadConsistency,
         segment::{Point, PointId},
     },
+    types::ShardKey,
 };
 use actix_web::{
     web::{self, Json},
@@ -14,6 +16,9 @@ use serde::Deserialize;
 #[derive(Deserialize)]
 pub struct UpsertPoints {
     pub points: Vec<Point>,
+    /// Required by collections with custom sharding, the points go to the shards of this key
+    #[serde(default)]
+    pub shard_key: Option<ShardKey>,
 }
 
 pub enum PointsOperation {
@@ -48,6 +53,15 @@ async fn upsert_points(
     .await
 }
 
+#[derive(Deserialize)]
+pub struct ReadParams {
+    /// Number of replicas that have to answer, e.g. `majority` or `2`.
+    /// Single points are read from one replica by default and listings from all of them.
+    pub read_consistency: Option<ReadConsistency>,
+    /// Only reads the shards of this key, collections with custom sharding read all shards otherwise
+    pub shard_key: Option<ShardKey>,
+}
+
 #[derive(serde::Serialize)]
 pub struct GetPointResponse {
     pub point: Point,
@@ -56,19 +70,22 @@ pub struct GetPointResponse {
 #[actix_web::get("/collections/{collection_name}/points/{id}")]
 async fn get_point(
     path: web::Path<(String, String)>,
+    params: web::Query<ReadParams>,
     dispatcher: web::Data<Dispatcher>,
 ) -> impl Responder {
     helpers::time(async {
         let (collection_name, id) = path.into_inner();
 
-        let point_id = match id.parse::<u64>() {
-            Ok(id) => PointId::Id(id),
-            Err(_) => PointId::Uuid(id.clone()),
-        };
+        let point_id: PointId = id.parse()?;
 
         let result = dispatcher
             .toc
-            .retrieve_points(&collection_name, Some(vec![point_id]))
+            .retrieve_points(
+                &collection_name,
+                Some(vec![point_id]),
+                &params.shard_key.clone().into(),
+                params.read_consistency.unwrap_or_default(),
+            )
             .await;
 
         match result {
@@ -78,6 +95,7 @@ async fn get_point(
             Ok(points) => Ok(GetPointResponse {
                 point: points[0].clone(),
             }),
+            Err(e @ StorageError::BadInput(_)) => Err(e.into()),
             Err(e) => Err(CollectionError::ServiceError(format!(
                 "Error retrieving point with id '{id}' in collection '{collection_name}': {e}"
             ))),
@@ -94,11 +112,20 @@ pub struct ListPointsResponse {
 #[actix_web::get("/collections/{collection_name}/points")]
 async fn list_points(
     collection_name: web::Path<String>,
+    params: web::Query<ReadParams>,
     dispatcher: web::Data<Dispatcher>,
 ) -> impl Responder {
     helpers::time(async {
         let collection_name = collection_name.into_inner();
-        let result = dispatcher.toc.retrieve_points(&collection_name, None).await;
+        let result = dispatcher
+            .toc
+            .retrieve_points(
+                &collection_name,
+                None,
+                &params.shard_key.clone().into(),
+                params.read_consistency.unwrap_or(ReadConsistency::All),
+            )
+            .await;
         match result {
             Ok(points) => {
                 if points.is_empty() {
@@ -109,6 +136,7 @@ async fn list_points(
                     Ok(ListPointsResponse { points })
                 }
             }
+            Err(e @ StorageError::BadInput(_)) => Err(e.into()),
             Err(e) => Err(CollectionError::ServiceError(format!(
                 "Error listing points in collection '{collection_name}': {e}"
             ))),
@@ -1,4 +1,5 @@
-use actix_web::{HttpResponse, Responder};
+use crate::settings::Settings;
+use actix_web::{web, HttpResponse, Responder};
 use serde::Serialize;
 
 #[derive(Serialize)]
@@ -14,3 +15,9 @@ async fn index() -> impl Responder {
         version: env!("CARGO_PKG_VERSION").to_string(),
     })
 }
+
+/// Settings this peer was started with, after applying the config file, env vars and flags
+#[actix_web::get("/settings")]
+async fn get_settings(settings: web::Data<Settings>) -> impl Responder {
+    HttpResponse::Ok().json(settings.get_ref())
+}
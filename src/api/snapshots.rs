@@ -0,0 +1,166 @@
+use crate::{
+    api::{collection::Dispatcher, helpers},
+    storage::error::{CollectionError, StorageError},
+};
+use actix_files::NamedFile;
+use actix_web::{
+    web::{self, Json},
+    HttpRequest, HttpResponse, Responder,
+};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::{io::AsyncWriteExt, time::Instant};
+
+/// Archives every collection of this peer along with its consensus state
+#[actix_web::post("/snapshots")]
+async fn create_storage_snapshot(dispatcher: web::Data<Dispatcher>) -> impl Responder {
+    helpers::time(async {
+        let consensus_state = dispatcher.serialized_consensus_state().await?;
+        Ok(dispatcher
+            .toc
+            .create_storage_snapshot(consensus_state)
+            .await?)
+    })
+    .await
+}
+
+#[actix_web::get("/snapshots")]
+async fn list_storage_snapshots(dispatcher: web::Data<Dispatcher>) -> impl Responder {
+    helpers::time(async { Ok(dispatcher.toc.list_storage_snapshots()?) }).await
+}
+
+/// Can be restored on another peer with `smoldb --snapshot <file>`
+#[actix_web::get("/snapshots/{snapshot_name}")]
+async fn download_storage_snapshot(
+    snapshot_name: web::Path<String>,
+    dispatcher: web::Data<Dispatcher>,
+    request: HttpRequest,
+) -> HttpResponse {
+    let path = dispatcher.toc.storage_snapshot_path(&snapshot_name);
+    send_snapshot(path, &snapshot_name, &request)
+}
+
+/// Archives the local shards of the collection, reads and writes of it wait meanwhile
+#[actix_web::post("/collections/{collection_name}/snapshots")]
+async fn create_snapshot(
+    collection_name: web::Path<String>,
+    dispatcher: web::Data<Dispatcher>,
+) -> impl Responder {
+    helpers::time(async { Ok(dispatcher.toc.create_snapshot(&collection_name).await?) }).await
+}
+
+#[actix_web::get("/collections/{collection_name}/snapshots")]
+async fn list_snapshots(
+    collection_name: web::Path<String>,
+    dispatcher: web::Data<Dispatcher>,
+) -> impl Responder {
+    helpers::time(async { Ok(dispatcher.toc.list_snapshots(&collection_name)?) }).await
+}
+
+/// Sends the snapshot archive as is
+#[actix_web::get("/collections/{collection_name}/snapshots/{snapshot_name}")]
+async fn download_snapshot(
+    path: web::Path<(String, String)>,
+    dispatcher: web::Data<Dispatcher>,
+    request: HttpRequest,
+) -> HttpResponse {
+    let (collection_name, snapshot_name) = path.into_inner();
+    let path = dispatcher
+        .toc
+        .snapshot_path(&collection_name, &snapshot_name);
+    send_snapshot(path, &snapshot_name, &request)
+}
+
+fn send_snapshot(
+    path: Result<PathBuf, StorageError>,
+    snapshot_name: &str,
+    request: &HttpRequest,
+) -> HttpResponse {
+    let instant = Instant::now();
+    let path = match path {
+        Ok(path) => path,
+        Err(e) => return helpers::error_response(CollectionError::StorageError(e), instant),
+    };
+
+    let file = NamedFile::open(path).map_err(|e| {
+        CollectionError::ServiceError(format!("Failed to open snapshot '{snapshot_name}': {e}"))
+    });
+
+    match file {
+        Ok(file) => file.into_response(request),
+        Err(e) => helpers::error_response(e, instant),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RecoverSnapshot {
+    /// Path of the archive on this peer, e.g. a snapshot of another collection
+    pub location: PathBuf,
+}
+
+#[derive(Serialize)]
+pub struct RecoverSnapshotResponse {
+    /// True if an existing collection was replaced
+    pub replaced: bool,
+}
+
+/// Replaces the collection on this peer with the content of a snapshot archive
+#[actix_web::put("/collections/{collection_name}/snapshots/recover")]
+async fn recover_snapshot(
+    collection_name: web::Path<String>,
+    operation: Json<RecoverSnapshot>,
+    dispatcher: web::Data<Dispatcher>,
+) -> impl Responder {
+    helpers::time(async {
+        let replaced = dispatcher
+            .toc
+            .recover_snapshot(&collection_name, &operation.location)
+            .await?;
+        Ok(RecoverSnapshotResponse { replaced })
+    })
+    .await
+}
+
+/// Same as `recover`, with the archive as request body. The archive is kept with the
+/// snapshots of the collection.
+#[actix_web::post("/collections/{collection_name}/snapshots/upload")]
+async fn upload_snapshot(
+    collection_name: web::Path<String>,
+    mut payload: web::Payload,
+    dispatcher: web::Data<Dispatcher>,
+) -> impl Responder {
+    helpers::time(async {
+        let archive_path = dispatcher.toc.new_snapshot_path(&collection_name).await?;
+        let tmp_path = archive_path.with_extension("tmp");
+
+        let upload = async {
+            let mut file = tokio::fs::File::create(&tmp_path).await?;
+            while let Some(chunk) = payload.next().await {
+                let chunk = chunk.map_err(std::io::Error::other)?;
+                file.write_all(&chunk).await?;
+            }
+            file.sync_all().await?;
+            tokio::fs::rename(&tmp_path, &archive_path).await
+        };
+
+        if let Err(e) = upload.await {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            return Err(CollectionError::ServiceError(format!(
+                "Failed to receive snapshot: {e}"
+            )));
+        }
+
+        let recovered = dispatcher
+            .toc
+            .recover_snapshot(&collection_name, &archive_path)
+            .await;
+        if recovered.is_err() {
+            let _ = tokio::fs::remove_file(&archive_path).await;
+        }
+        Ok(RecoverSnapshotResponse {
+            replaced: recovered?,
+        })
+    })
+    .await
+}
@@ -1,5 +1,8 @@
 use clap::Parser;
 use http::Uri;
+use std::path::PathBuf;
+
+use crate::storage::rebalance::DEFAULT_REBALANCE_RATE;
 
 #[derive(Parser)]
 #[clap(version, about)]
@@ -7,15 +10,47 @@ pub struct Args {
     /// Url of the bootstrap node
     #[clap(short, long)]
     pub bootstrap: Option<Uri>,
-    /// Url of the node
-    #[clap(short, long, default_value = "http://0.0.0.0:9900")]
-    pub url: Uri,
-    /// Url of the node
-    #[clap(short, long, default_value = "http://0.0.0.0:9920")]
-    pub p2p_url: Uri,
+    /// Config file in YAML or TOML, see [`crate::settings::Settings`]
+    #[clap(long)]
+    pub config_path: Option<PathBuf>,
+    /// Url of the node, overrides `service.url` of the settings (`http://0.0.0.0:9900`)
+    #[clap(short, long)]
+    pub url: Option<Uri>,
+    /// Url of the p2p API, overrides `service.p2p_url` of the settings (`http://0.0.0.0:9920`)
+    #[clap(short, long)]
+    pub p2p_url: Option<Uri>,
+    /// Url other peers use to reach the p2p API, defaults to the p2p url.
+    /// Required when binding to 0.0.0.0 in a cluster, e.g. `http://smoldb-1:9920` in docker-compose
+    #[clap(long)]
+    pub p2p_advertise_url: Option<Uri>,
+    /// Url clients use to reach the HTTP API, defaults to the url
+    #[clap(long)]
+    pub advertise_url: Option<Uri>,
     /// Peer id
     #[clap(long)]
     pub peer_id: Option<u64>,
+    /// Copy shards to the peers that join the cluster, enabling it on a single peer is enough
+    #[clap(long)]
+    pub rebalance: bool,
+    /// Points per second sent by the rebalancer
+    #[clap(long, default_value_t = DEFAULT_REBALANCE_RATE)]
+    pub rebalance_rate: u64,
+    /// Restore the storage from a snapshot made with `POST /snapshots` before starting.
+    /// The storage must not hold any collection yet.
+    #[clap(long)]
+    pub snapshot: Option<PathBuf>,
+    /// Where `POST /backups` uploads incremental backups: `file:///path/to/dir`, or
+    /// `s3://bucket/prefix` with the credentials and endpoint in the `AWS_*` variables
+    #[clap(long)]
+    pub backup_url: Option<String>,
+    /// Restore the storage from a backup of `--backup-url` before starting, or `latest`.
+    /// The storage must not hold any collection yet.
+    #[clap(long, requires = "backup_url", conflicts_with = "snapshot")]
+    pub restore_backup: Option<String>,
+    /// Keep the other peers of the cluster when restoring a snapshot or backup. Without it
+    /// the restored peer starts alone, so that a copy doesn't contact the cluster it came from.
+    #[clap(long)]
+    pub keep_peers: bool,
 }
 
 pub fn parse_args() -> Args {
@@ -1,10 +1,19 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use http::Uri;
 use tokio::sync::RwLock;
 use tonic::transport::{Channel, Error as TonicError};
 
-use crate::{api::grpc::make_default_grpc_channel, types::PeerId};
+use crate::{
+    api::grpc::{
+        make_default_grpc_channel,
+        p2p_grpc_schema::{raft_client::RaftClient, PeerId as GrpcPeerId},
+    },
+    failure_detector::FailureDetector,
+    settings::ClusterSettings,
+    storage::error::{CollectionError, CollectionResult},
+    types::PeerId,
+};
 
 /// Holds a pool of channels established for a set of URIs.
 /// Channel are shared by cloning them.
@@ -21,12 +30,24 @@ impl TransportChannelPool {
             return Ok(uri_to_channel_guard.get(&uri).unwrap().clone());
         }
 
-        drop(uri_to_channel_guard); // Explicitly drop the read lock before acquiring a write lock
-        let mut uri_to_channel_guard = self.uri_to_channel.write().await;
+        drop(uri_to_channel_guard);
+
+        // Connect without holding the lock, so requests to other peers don't wait for it
         let channel = make_default_grpc_channel(uri.clone()).await?;
-        uri_to_channel_guard.insert(uri.clone(), channel.clone());
 
-        Ok(channel)
+        // Another request may have connected in the meantime, its channel is kept
+        let mut uri_to_channel_guard = self.uri_to_channel.write().await;
+        Ok(uri_to_channel_guard.entry(uri).or_insert(channel).clone())
+    }
+}
+
+impl Default for ChannelService {
+    fn default() -> Self {
+        Self::new(
+            Arc::default(),
+            PeerId::default(),
+            ClusterSettings::default().replica_timeout(),
+        )
     }
 }
 
@@ -38,18 +59,101 @@ impl Default for TransportChannelPool {
     }
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct ChannelService {
     /// Shared with consensus state
     pub id_to_address: Arc<RwLock<HashMap<PeerId, Uri>>>,
     pub channel_pool: Arc<TransportChannelPool>,
+    pub failure_detector: Arc<FailureDetector>,
+    pub this_peer_id: PeerId,
+    /// Time a remote replica gets to apply a write, see `cluster.replica_timeout_ms`
+    pub replica_timeout: Duration,
 }
 
 impl ChannelService {
-    pub fn new(id_to_address: Arc<RwLock<HashMap<PeerId, Uri>>>) -> Self {
+    pub fn new(
+        id_to_address: Arc<RwLock<HashMap<PeerId, Uri>>>,
+        this_peer_id: PeerId,
+        replica_timeout: Duration,
+    ) -> Self {
         Self {
             id_to_address,
             channel_pool: Arc::new(TransportChannelPool::default()),
+            failure_detector: Arc::new(FailureDetector::default()),
+            this_peer_id,
+            replica_timeout,
+        }
+    }
+
+    /// Known peers except this one, along with their addresses
+    pub async fn other_peers(&self) -> Vec<(PeerId, Uri)> {
+        self.id_to_address
+            .read()
+            .await
+            .iter()
+            .filter(|(peer_id, _)| **peer_id != self.this_peer_id)
+            .map(|(peer_id, uri)| (*peer_id, uri.clone()))
+            .collect()
+    }
+
+    /// Address of the peer, asking the other peers with `WhoIs` if it's not known yet.
+    pub async fn resolve_address(&self, peer_id: PeerId) -> CollectionResult<Uri> {
+        if let Some(uri) = self.id_to_address.read().await.get(&peer_id).cloned() {
+            return Ok(uri);
+        }
+
+        self.refresh_address(peer_id).await
+    }
+
+    /// Asks the other known peers for the current address of the peer and updates the address book.
+    /// Used when the address is missing or the peer became unreachable, e.g. after an IP change.
+    pub async fn refresh_address(&self, peer_id: PeerId) -> CollectionResult<Uri> {
+        let other_peers = self
+            .id_to_address
+            .read()
+            .await
+            .iter()
+            .filter(|(id, _)| **id != peer_id)
+            .map(|(_, uri)| uri.clone())
+            .collect::<Vec<_>>();
+
+        let mut errors = vec![];
+        for other_uri in other_peers {
+            match self.who_is(other_uri.clone(), peer_id).await {
+                Ok(uri) => {
+                    let previous = self
+                        .id_to_address
+                        .write()
+                        .await
+                        .insert(peer_id, uri.clone());
+                    if previous.as_ref() != Some(&uri) {
+                        println!("Address of peer {peer_id} is now {uri} (was {previous:?})");
+                    }
+                    return Ok(uri);
+                }
+                Err(e) => errors.push(format!("{other_uri}: {e}")),
+            }
         }
+
+        Err(CollectionError::ServiceError(format!(
+            "No known peer could resolve the address of peer {peer_id}: [{}]",
+            errors.join(", ")
+        )))
+    }
+
+    async fn who_is(&self, other_uri: Uri, peer_id: PeerId) -> CollectionResult<Uri> {
+        let channel = self.channel_pool.get_or_create_channel(other_uri).await?;
+
+        let response = RaftClient::new(channel)
+            .who_is(GrpcPeerId { id: peer_id })
+            .await?
+            .into_inner();
+
+        response.uri.parse::<Uri>().map_err(|e| {
+            CollectionError::ServiceError(format!(
+                "Invalid address '{}' for peer {peer_id}: {e}",
+                response.uri
+            ))
+        })
     }
 }
@@ -3,7 +3,8 @@ use crate::{
         make_grpc_channel,
         p2p_grpc_schema::{raft_client::RaftClient, AddPeerToKnownMessage},
     },
-    storage::toc::TableOfContent,
+    settings::ConsensusSettings,
+    storage::toc::{CollectionMetaOperation, TableOfContent},
     types::PeerId,
 };
 use http::Uri;
@@ -13,14 +14,14 @@ use raft::{
     Config, RawNode,
 };
 use rand::Rng;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use slog::{o, Drain};
 use std::{
     collections::{BTreeMap, HashMap},
     error::Error,
     sync::{
-        mpsc::{self, channel, Receiver, RecvTimeoutError, Sender},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
         Arc,
     },
     thread::{self},
@@ -28,13 +29,16 @@ use std::{
 };
 use tokio::{runtime::Handle, sync::RwLock, time::Instant};
 
-const RAFT_TICK_INTERVAL: Duration = Duration::from_millis(100);
+const BOOTSTRAP_ATTEMPTS: usize = 10;
+const BOOTSTRAP_RETRY_INTERVAL: Duration = Duration::from_secs(1);
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Persistent {
     pub peer_id: PeerId,
     // Using instead of HashMap to keep peers sorted (consistent) across the nodes
     pub peers: BTreeMap<PeerId, String>,
+    /// Advertised HTTP API of the peers, if they shared it
+    pub peer_http_uris: BTreeMap<PeerId, String>,
     pub raft_info: Value,
 }
 
@@ -46,7 +50,8 @@ pub struct ConsensusState {
 }
 
 impl ConsensusState {
-    pub fn dummy(p2p_uri: http::Uri, default_peer_id: Option<PeerId>) -> Self {
+    /// `p2p_uri` and `http_uri` are the addresses advertised to the other peers.
+    pub fn dummy(p2p_uri: http::Uri, http_uri: http::Uri, default_peer_id: Option<PeerId>) -> Self {
         let mut rng = rand::rng();
         // Do not generate too big peer ID, to avoid problems with serialization
         let peer_id = default_peer_id.unwrap_or_else(|| rng.random::<PeerId>() % (1 << 53));
@@ -54,6 +59,7 @@ impl ConsensusState {
         let p = Persistent {
             peer_id,
             peers: BTreeMap::from([(peer_id, p2p_uri.to_string())]),
+            peer_http_uris: BTreeMap::from([(peer_id, http_uri.to_string())]),
             raft_info: serde_json::json!({
                 "term": 1,
                 "commit_index": 1,
@@ -68,10 +74,78 @@ impl ConsensusState {
         }
     }
 
-    pub async fn add_peer(&self, peer_id: PeerId, uri: Uri) -> Result<(), Box<dyn Error>> {
+    /// State of a peer restored from a storage snapshot or backup, so that it keeps its id.
+    /// `default_peer_id` takes precedence over the restored id. The other peers of the cluster
+    /// are only kept with `keep_peers`, so that a copy of a peer doesn't contact its cluster.
+    pub fn restore(
+        serialized: &[u8],
+        p2p_uri: http::Uri,
+        http_uri: http::Uri,
+        default_peer_id: Option<PeerId>,
+        keep_peers: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut p: Persistent = serde_json::from_slice(serialized)?;
+
+        let peer_id = default_peer_id.unwrap_or(p.peer_id);
+        if peer_id != p.peer_id {
+            p.peers.remove(&p.peer_id);
+            p.peer_http_uris.remove(&p.peer_id);
+            p.peer_id = peer_id;
+        }
+        if !keep_peers {
+            p.peers.clear();
+            p.peer_http_uris.clear();
+        }
+        // This peer might be restored at another address
+        p.peers.insert(peer_id, p2p_uri.to_string());
+        p.peer_http_uris.insert(peer_id, http_uri.to_string());
+
+        let mut peer_address_by_id = HashMap::new();
+        for (peer_id, uri) in &p.peers {
+            match uri.parse::<Uri>() {
+                Ok(uri) => {
+                    peer_address_by_id.insert(*peer_id, uri);
+                }
+                Err(e) => eprintln!("Skipping restored peer {peer_id} with address {uri}: {e}"),
+            }
+        }
+
+        Ok(ConsensusState {
+            persistent: RwLock::new(p),
+            peer_address_by_id: Arc::new(RwLock::new(peer_address_by_id)),
+        })
+    }
+
+    pub async fn add_peer(
+        &self,
+        peer_id: PeerId,
+        uri: Uri,
+        http_uri: Option<String>,
+    ) -> Result<(), Box<dyn Error>> {
         // Add a new peer to the consensus state
         let mut persistent = self.persistent.write().await;
         persistent.peers.insert(peer_id, uri.to_string());
+        if let Some(http_uri) = http_uri {
+            persistent.peer_http_uris.insert(peer_id, http_uri);
+        }
+
+        // Remote shards resolve peers through this map
+        self.peer_address_by_id.write().await.insert(peer_id, uri);
+        Ok(())
+    }
+
+    /// Forgets the peer, it must not hold any replica anymore.
+    pub async fn remove_peer(&self, peer_id: PeerId) -> Result<(), Box<dyn Error>> {
+        let mut persistent = self.persistent.write().await;
+        if persistent.peer_id == peer_id {
+            return Err("Peer can't remove itself".into());
+        }
+        if persistent.peers.remove(&peer_id).is_none() {
+            return Err(format!("Peer {peer_id} is not known").into());
+        }
+        persistent.peer_http_uris.remove(&peer_id);
+
+        self.peer_address_by_id.write().await.remove(&peer_id);
         Ok(())
     }
 }
@@ -82,6 +156,7 @@ pub struct Consensus {
     raft_node: RawNode<MemStorage>,
     receiver: Receiver<Msg>,
     runtime: Handle,
+    settings: ConsensusSettings,
     // Probably don't keep it here since it mixes up abstraction levels
     pub toc: Arc<TableOfContent>,
 }
@@ -93,10 +168,9 @@ impl Consensus {
         cluster_uri: Uri,
         consensus_state: Arc<ConsensusState>,
     ) -> Result<(), Box<dyn Error>> {
-        let bootstrap_timeout_sec = 10;
         let channel = make_grpc_channel(
-            Duration::from_secs(bootstrap_timeout_sec),
-            Duration::from_secs(bootstrap_timeout_sec),
+            self.settings.bootstrap_timeout(),
+            self.settings.bootstrap_timeout(),
             cluster_uri,
         )
         .await?;
@@ -108,13 +182,15 @@ impl Consensus {
             .get(&peer_id)
             .cloned()
             .ok_or_else(|| format!("Peer with ID {peer_id} not found in persistent state"))?;
+        let port = peer_uri.parse::<Uri>()?.port_u16().map(u32::from);
 
         let mut client = RaftClient::new(channel);
         let all_peers = client
             .add_peer_to_known(tonic::Request::new(AddPeerToKnownMessage {
                 id: peer_id,
                 uri: Some(peer_uri),
-                port: Some(9920),
+                port,
+                http_uri: persistent.peer_http_uris.get(&peer_id).cloned(),
             }))
             .await?
             .into_inner();
@@ -127,7 +203,7 @@ impl Consensus {
             }
 
             consensus_state
-                .add_peer(peer.id, peer.uri.parse::<Uri>()?)
+                .add_peer(peer.id, peer.uri.parse::<Uri>()?, peer.http_uri)
                 .await?;
 
             let collections = self.toc.collections.read().await;
@@ -145,14 +221,39 @@ impl Consensus {
         Ok(())
     }
 
+    /// Bootstrap peer checks that our advertised address is reachable,
+    /// so retry while our own p2p server is still starting up.
+    async fn bootstrap_with_retries(
+        &self,
+        cluster_uri: Uri,
+        consensus_state: Arc<ConsensusState>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut attempt = 1;
+        loop {
+            match self
+                .bootstrap(cluster_uri.clone(), consensus_state.clone())
+                .await
+            {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < BOOTSTRAP_ATTEMPTS => {
+                    eprintln!("Bootstrap attempt {attempt} failed, retrying: {e}");
+                    tokio::time::sleep(BOOTSTRAP_RETRY_INTERVAL).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
     /// Initialize consensus and run in loop with a dedicated thread.
     pub fn start(
         bootstrap_uri: Option<Uri>,
         consensus_state: Arc<ConsensusState>,
         toc: Arc<TableOfContent>,
         runtime: Handle,
+        settings: ConsensusSettings,
     ) -> Result<Sender<Msg>, Box<dyn Error>> {
-        let (mut consensus, sender) = Self::new(runtime, toc)?;
+        let (mut consensus, sender) = Self::new(runtime, toc, settings)?;
 
         // Start a thread for consensus
         // Note: we don't need to preserve the thread handle,
@@ -166,9 +267,7 @@ impl Consensus {
                     println!("Bootstrapping consensus from {bootstrap_uri}");
                     consensus
                         .runtime
-                        .block_on(async {
-                            consensus.bootstrap(bootstrap_uri, consensus_state).await
-                        })
+                        .block_on(consensus.bootstrap_with_retries(bootstrap_uri, consensus_state))
                         .unwrap();
                 }
 
@@ -187,6 +286,7 @@ impl Consensus {
     fn new(
         runtime: Handle,
         toc: Arc<TableOfContent>,
+        settings: ConsensusSettings,
     ) -> Result<(Self, Sender<Msg>), Box<dyn Error>> {
         let storage = MemStorage::new_with_conf_state((vec![1], vec![]));
         let logger = slog::Logger::root(slog_stdlog::StdLog.fuse(), o!());
@@ -203,6 +303,7 @@ impl Consensus {
             raft_node: raft,
             receiver,
             runtime,
+            settings,
             toc,
         };
 
@@ -211,174 +312,193 @@ impl Consensus {
 
     /// Run the consensus loop at each tick.
     fn run_loop(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut t = Instant::now();
+        let tick_interval = self.settings.tick_interval();
+        let mut timeout = tick_interval;
+
+        let mut callbacks = HashMap::new();
+
         loop {
-            let Self {
-                raft_node,
-                receiver,
-                runtime: _,
-                toc: _,
-            } = self;
-
-            // Wait for messages from the receiver
-            match receiver.recv_timeout(RAFT_TICK_INTERVAL) {
-                Ok(msg) => {
-                    match msg {
-                        Msg::Propose {
-                            id,
-                            operation,
-                            callback: _,
-                        } => {
-                            println!(
-                                "Received proposal with ID: {id} and operation: {operation:?}"
-                            );
-                        }
-                        Msg::Raft(message) => {
-                            println!("Received Raft message: {message:?}");
-                            // Process the Raft message
-                            raft_node.step(*message)?;
+            // Wait for a message or timeout (whichever happens first) to proceed with Raft tick
+            match self.receiver.recv_timeout(timeout) {
+                Ok(Msg::Propose {
+                    id,
+                    operation,
+                    callback,
+                }) => {
+                    println!("Received proposal with ID: {id} and operation: {operation:?}");
+                    // ToDo: Data needs to be converted to CBOR format
+                    let data = serde_json::to_vec(&operation)?;
+
+                    // Note: this returns ProposalDropped when there is no leader yet
+                    match self.raft_node.propose(vec![id], data) {
+                        Ok(()) => {
+                            callbacks.insert(id, callback);
                         }
+                        Err(e) => eprintln!("Failed to propose entry with ID {id}: {e}"),
                     }
                 }
+                Ok(Msg::Raft(message)) => {
+                    println!("Received Raft message: {message:?}");
+                    // Process the Raft message
+                    self.raft_node.step(*message)?;
+                }
                 Err(RecvTimeoutError::Timeout) => {
-                    // Timeout occurred, we can tick the Raft node
-                    raft_node.tick();
+                    // Timeout occurred, checking Raft node...
                 }
                 Err(RecvTimeoutError::Disconnected) => {
                     println!("Receiver disconnected, exiting loop.");
                     return Ok(());
                 }
             }
-        }
-    }
-}
 
-#[derive(Debug)]
-pub enum ConsensusOperation {
-    AddPeer { peer_id: PeerId, uri: String },
-    UpdateData(u64),
-}
-
-pub enum Msg {
-    // Custom messages for consensus operations
-    Propose {
-        id: u8,
-        operation: ConsensusOperation,
-        callback: Box<dyn Fn() + Send>,
-    },
-    // Internal raft crate messages
-    Raft(Box<Message>),
-}
+            let d = t.elapsed();
+            t = Instant::now();
 
-/// ToDo: Integrate into  [`Consensus::run_loop`] to handle incoming messages
-pub async fn run_consensus_receiver_loop(
-    raft_node: &mut RawNode<MemStorage>,
-    receiver: mpsc::Receiver<Msg>,
-) {
-    println!("Starting Raft consensus receiver loop...");
-    let mut t = Instant::now();
-    let mut timeout = RAFT_TICK_INTERVAL;
-
-    let mut cbs = HashMap::new();
-
-    loop {
-        // Wait for a message or timeout (whichever happens first) to proceed with Raft tick
-        match receiver.recv_timeout(timeout) {
-            Ok(Msg::Propose {
-                id,
-                callback,
-                operation,
-            }) => {
-                // ToDo: Handle different consensus operations
-                println!("Received proposal with ID: {id} and operation {operation:?}");
-                cbs.insert(id, callback);
-                // ToDo: Data needs to be converted to CBOR format
-
-                // Note: this returns ProposalDropped when the ID is repeated.
-                raft_node
-                    .propose(vec![], vec![id])
-                    .unwrap_or_else(|_| panic!("failed to propose entry with {id}"));
-            }
-            Ok(Msg::Raft(m)) => {
-                raft_node.step(*m).unwrap();
-            }
-            Err(RecvTimeoutError::Timeout) => {
-                // Timeout occurred, checking Raft node...
-            }
-            Err(RecvTimeoutError::Disconnected) => {
-                println!("ERROR: Receiver disconnected, exiting loop.");
-                return;
+            if d >= timeout {
+                timeout = tick_interval;
+                // We drive Raft every tick, 100ms by default.
+                self.raft_node.tick();
+            } else {
+                timeout -= d;
             }
+
+            self.on_ready(&mut callbacks)?;
         }
+    }
 
-        let d = t.elapsed();
-        t = Instant::now();
+    fn on_ready(
+        &mut self,
+        callbacks: &mut HashMap<u8, Box<dyn Fn() + Send>>,
+    ) -> Result<(), Box<dyn Error>> {
+        loop {
+            if !self.raft_node.has_ready() {
+                return Ok(());
+            }
 
-        if d >= timeout {
-            timeout = RAFT_TICK_INTERVAL;
-            // We drive Raft every 100ms.
-            raft_node.tick();
-        } else {
-            timeout -= d;
-        }
+            let store = self.raft_node.raft.raft_log.store.clone();
 
-        on_ready(raft_node, &mut cbs);
-    }
-}
+            // The Raft is ready, we can do something now.
+            let mut ready = self.raft_node.ready();
 
-fn on_ready(raft_node: &mut RawNode<MemStorage>, _cbs: &mut HashMap<u8, Box<dyn Fn() + Send>>) {
-    loop {
-        if !raft_node.has_ready() {
-            return;
-        }
+            // ToDo: Consensus snapshots
 
-        println!("Raft node is ready, processing message...");
+            if !ready.messages().is_empty() {
+                send_messages(ready.take_messages());
+            }
 
-        let store = raft_node.raft.raft_log.store.clone();
+            self.handle_committed_entries(ready.take_committed_entries(), callbacks);
 
-        // The Raft is ready, we can do something now.
-        let mut ready = raft_node.ready();
+            if !ready.entries().is_empty() {
+                // Append entries to the Raft log.
+                store.wl().append(ready.entries())?;
+            }
 
-        // ToDo: Consensus snapshots
+            if let Some(hs) = ready.hs() {
+                // Raft HardState changed, and we need to persist it.
+                store.wl().set_hardstate(hs.clone());
+            }
 
-        if !ready.messages().is_empty() {
-            send_messages(ready.take_messages());
+            if !ready.persisted_messages().is_empty() {
+                // Send out the persisted messages come from the node.
+                send_messages(ready.take_persisted_messages());
+            }
+
+            // Advance the Raft.
+            let mut light_ready = self.raft_node.advance(ready);
+            // Update commit index.
+            if let Some(commit) = light_ready.commit_index() {
+                store.wl().mut_hard_state().set_commit(commit);
+            }
+            // Send out the messages to other peers.
+            send_messages(light_ready.take_messages());
+            // Apply all committed entries.
+            self.handle_committed_entries(light_ready.take_committed_entries(), callbacks);
+            // Advance the apply index.
+            self.raft_node.advance_apply();
         }
+    }
 
-        let mut last_apply_index = 0;
-        handle_committed_entries(ready.take_committed_entries(), &mut last_apply_index);
+    /// Handle committed entries
+    fn handle_committed_entries(
+        &self,
+        entries: Vec<Entry>,
+        callbacks: &mut HashMap<u8, Box<dyn Fn() + Send>>,
+    ) {
+        for entry in entries {
+            if entry.data.is_empty() {
+                // Empty entry, when the peer becomes Leader it will send an empty entry.
+                continue;
+            }
 
-        if !ready.entries().is_empty() {
-            // Append entries to the Raft log.
-            store.wl().append(ready.entries()).unwrap();
-        }
+            match entry.get_entry_type() {
+                EntryType::EntryNormal => {
+                    self.handle_normal(&entry);
 
-        if let Some(hs) = ready.hs() {
-            // Raft HardState changed, and we need to persist it.
-            store.wl().set_hardstate(hs.clone());
+                    // Only the peer that proposed the entry has a callback for it
+                    if let Some(callback) =
+                        entry.context.first().and_then(|id| callbacks.remove(id))
+                    {
+                        callback();
+                    }
+                }
+                // It's recommended to always use `EntryType::EntryConfChangeV2.
+                EntryType::EntryConfChange => handle_conf_change(entry),
+                EntryType::EntryConfChangeV2 => handle_conf_change_v2(entry),
+            }
         }
+    }
 
-        if !ready.persisted_messages().is_empty() {
-            // Send out the persisted messages come from the node.
-            send_messages(ready.take_persisted_messages());
-        }
+    /// Apply committed operation to the local state
+    fn handle_normal(&self, entry: &Entry) {
+        let operation: ConsensusOperation = match serde_json::from_slice(&entry.data) {
+            Ok(operation) => operation,
+            Err(e) => {
+                eprintln!(
+                    "Failed to parse consensus operation at {}: {e}",
+                    entry.index
+                );
+                return;
+            }
+        };
 
-        // Advance the Raft.
-        let mut light_ready = raft_node.advance(ready);
-        // Update commit index.
-        if let Some(commit) = light_ready.commit_index() {
-            store.wl().mut_hard_state().set_commit(commit);
+        println!("Applying consensus operation: {operation:?}");
+
+        match operation {
+            ConsensusOperation::CollectionMeta(operation) => {
+                let result = self
+                    .runtime
+                    .block_on(self.toc.perform_collection_meta_op(operation));
+
+                if let Err(e) = result {
+                    eprintln!("Failed to apply collection operation: {e}");
+                }
+            }
+            ConsensusOperation::AddPeer { .. } | ConsensusOperation::UpdateData(_) => {
+                // ToDo: Apply peer operations to the consensus state
+            }
         }
-        // Send out the messages to other peers.
-        send_messages(light_ready.take_messages());
-        // Apply all committed entries.
-        handle_committed_entries(light_ready.take_committed_entries(), &mut last_apply_index);
-        // Advance the apply index.
-        raft_node.advance_apply();
-
-        println!("Raft node processed a ready state.");
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ConsensusOperation {
+    AddPeer { peer_id: PeerId, uri: String },
+    UpdateData(u64),
+    CollectionMeta(CollectionMetaOperation),
+}
+
+pub enum Msg {
+    // Custom messages for consensus operations
+    Propose {
+        id: u8,
+        operation: ConsensusOperation,
+        callback: Box<dyn Fn() + Send>,
+    },
+    // Internal raft crate messages
+    Raft(Box<Message>),
+}
+
 /// Send out the messages to other peers
 fn send_messages(messages: Vec<Message>) {
     for msg in messages {
@@ -386,31 +506,6 @@ fn send_messages(messages: Vec<Message>) {
     }
 }
 
-/// Handle committed entries
-fn handle_committed_entries(entries: Vec<Entry>, last_apply_index: &mut u64) {
-    for entry in entries {
-        // Mostly, you need to save the last apply index to resume applying
-        // after restart. Here we just ignore this because we use a Memory storage.
-        *last_apply_index = entry.index;
-
-        if entry.data.is_empty() {
-            // Empty entry, when the peer becomes Leader it will send an empty entry.
-            continue;
-        }
-
-        match entry.get_entry_type() {
-            EntryType::EntryNormal => handle_normal(entry),
-            // It's recommended to always use `EntryType::EntryConfChangeV2.
-            EntryType::EntryConfChange => handle_conf_change(entry),
-            EntryType::EntryConfChangeV2 => handle_conf_change_v2(entry),
-        }
-    }
-}
-
-fn handle_normal(entry: Entry) {
-    println!("Handle normal entry: {entry:?}");
-}
-
 fn handle_conf_change(entry: Entry) {
     println!("Handle conf change entry: {entry:?}");
 }
@@ -418,3 +513,85 @@ fn handle_conf_change(entry: Entry) {
 fn handle_conf_change_v2(entry: Entry) {
     println!("Handle conf change v2 entry: {entry:?}");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_restore_state() {
+        let state = ConsensusState::dummy(
+            "http://127.0.0.1:5101".parse().unwrap(),
+            "http://127.0.0.1:9101".parse().unwrap(),
+            Some(101),
+        );
+        state
+            .add_peer(102, "http://127.0.0.1:5102".parse().unwrap(), None)
+            .await
+            .unwrap();
+        let serialized = serde_json::to_vec(&*state.persistent.read().await).unwrap();
+
+        // Same peer at a new address
+        let restored = ConsensusState::restore(
+            &serialized,
+            "http://10.0.0.1:5101".parse().unwrap(),
+            "http://10.0.0.1:9101".parse().unwrap(),
+            None,
+            true,
+        )
+        .unwrap();
+        let persistent = restored.persistent.read().await;
+        assert_eq!(persistent.peer_id, 101);
+        assert_eq!(persistent.peers[&101], "http://10.0.0.1:5101/");
+        assert_eq!(persistent.peers[&102], "http://127.0.0.1:5102/");
+        assert_eq!(restored.peer_address_by_id.read().await.len(), 2);
+
+        // Restored as another peer
+        let restored = ConsensusState::restore(
+            &serialized,
+            "http://10.0.0.1:5103".parse().unwrap(),
+            "http://10.0.0.1:9103".parse().unwrap(),
+            Some(103),
+            true,
+        )
+        .unwrap();
+        let persistent = restored.persistent.read().await;
+        assert_eq!(
+            persistent.peers.keys().copied().collect::<Vec<_>>(),
+            vec![102, 103]
+        );
+
+        // Only this peer without keep_peers
+        let restored = ConsensusState::restore(
+            &serialized,
+            "http://10.0.0.1:5101".parse().unwrap(),
+            "http://10.0.0.1:9101".parse().unwrap(),
+            None,
+            false,
+        )
+        .unwrap();
+        let persistent = restored.persistent.read().await;
+        assert_eq!(
+            persistent.peers.keys().copied().collect::<Vec<_>>(),
+            vec![101]
+        );
+        assert_eq!(
+            persistent
+                .peer_http_uris
+                .keys()
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![101]
+        );
+        assert_eq!(restored.peer_address_by_id.read().await.len(), 1);
+
+        assert!(ConsensusState::restore(
+            b"{}",
+            "http://a".parse().unwrap(),
+            "http://b".parse().unwrap(),
+            None,
+            false
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,317 @@
+use crate::{
+    api::grpc::p2p_grpc_schema::{service_client::ServiceClient, RootApiRequest},
+    channel_service::ChannelService,
+    types::PeerId,
+};
+use futures::future::join_all;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Default of `cluster.heartbeat_interval_ms`, expected interval before any was measured
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Peers are considered dead above this suspicion level. With regular heartbeats it's reached
+/// after about 7 intervals without any, i.e. 7s by default instead of the former 10s timeout.
+pub const PHI_THRESHOLD: f64 = 3.0;
+
+/// Number of heartbeat intervals used to estimate the expected interval
+const MAX_SAMPLES: usize = 100;
+
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerStatus {
+    /// Not probed successfully yet, treated as alive
+    Unknown,
+    Alive,
+    Dead,
+}
+
+/// Heartbeat history of a peer
+#[derive(Default)]
+struct PeerHeartbeats {
+    intervals: VecDeque<Duration>,
+    last_seen: Option<Instant>,
+    /// Milliseconds since the unix epoch, only for display
+    last_seen_at: Option<u64>,
+    round_trip: Option<Duration>,
+    status: Option<PeerStatus>,
+}
+
+impl PeerHeartbeats {
+    fn record(&mut self, at: Instant, round_trip: Duration) {
+        if let Some(last_seen) = self.last_seen {
+            if self.intervals.len() == MAX_SAMPLES {
+                self.intervals.pop_front();
+            }
+            self.intervals
+                .push_back(at.saturating_duration_since(last_seen));
+        }
+
+        self.last_seen = Some(at);
+        self.last_seen_at = Some(now_millis());
+        self.round_trip = Some(round_trip);
+    }
+
+    /// Suspicion level that the peer is down, assuming exponentially distributed heartbeat
+    /// intervals as in "The φ Accrual Failure Detector" (Hayashibara et al.).
+    fn phi(&self, now: Instant) -> Option<f64> {
+        let last_seen = self.last_seen?;
+
+        let mean = if self.intervals.is_empty() {
+            HEARTBEAT_INTERVAL.as_secs_f64()
+        } else {
+            self.intervals
+                .iter()
+                .map(Duration::as_secs_f64)
+                .sum::<f64>()
+                / self.intervals.len() as f64
+        };
+
+        let elapsed = now.saturating_duration_since(last_seen).as_secs_f64();
+        Some(elapsed / mean.max(f64::EPSILON) * std::f64::consts::LOG10_E)
+    }
+
+    fn status(&self, now: Instant) -> PeerStatus {
+        match self.phi(now) {
+            None => PeerStatus::Unknown,
+            Some(phi) if phi < PHI_THRESHOLD => PeerStatus::Alive,
+            Some(_) => PeerStatus::Dead,
+        }
+    }
+}
+
+/// Liveness of a peer, as shown by the API
+#[derive(Serialize, Clone, Debug)]
+pub struct PeerHealth {
+    pub status: PeerStatus,
+    pub phi: Option<f64>,
+    /// Milliseconds since the unix epoch
+    pub last_seen: Option<u64>,
+    pub round_trip_ms: Option<f64>,
+}
+
+/// Tracks which peers are reachable by probing them in the background.
+#[derive(Default)]
+pub struct FailureDetector {
+    peers: Mutex<HashMap<PeerId, PeerHeartbeats>>,
+}
+
+impl FailureDetector {
+    pub fn record_heartbeat(&self, peer_id: PeerId, round_trip: Duration) {
+        self.record_heartbeat_at(peer_id, Instant::now(), round_trip);
+    }
+
+    fn record_heartbeat_at(&self, peer_id: PeerId, at: Instant, round_trip: Duration) {
+        let mut peers = self
+            .peers
+            .lock()
+            .expect("Failure detector lock is poisoned");
+        peers.entry(peer_id).or_default().record(at, round_trip);
+    }
+
+    pub fn status(&self, peer_id: PeerId) -> PeerStatus {
+        let peers = self
+            .peers
+            .lock()
+            .expect("Failure detector lock is poisoned");
+        peers
+            .get(&peer_id)
+            .map_or(PeerStatus::Unknown, |peer| peer.status(Instant::now()))
+    }
+
+    /// Dead peers are skipped by replica operations instead of waiting for a timeout.
+    pub fn is_alive(&self, peer_id: PeerId) -> bool {
+        self.status(peer_id) != PeerStatus::Dead
+    }
+
+    /// Drops the heartbeat history of a peer that left the cluster
+    pub fn forget(&self, peer_id: PeerId) {
+        let mut peers = self
+            .peers
+            .lock()
+            .expect("Failure detector lock is poisoned");
+        peers.remove(&peer_id);
+    }
+
+    pub fn dead_peers(&self) -> Vec<PeerId> {
+        let now = Instant::now();
+        let peers = self
+            .peers
+            .lock()
+            .expect("Failure detector lock is poisoned");
+        peers
+            .iter()
+            .filter(|(_, peer)| peer.status(now) == PeerStatus::Dead)
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+
+    pub fn health(&self) -> BTreeMap<PeerId, PeerHealth> {
+        let now = Instant::now();
+        let peers = self
+            .peers
+            .lock()
+            .expect("Failure detector lock is poisoned");
+        peers
+            .iter()
+            .map(|(peer_id, peer)| {
+                let health = PeerHealth {
+                    status: peer.status(now),
+                    phi: peer.phi(now),
+                    last_seen: peer.last_seen_at,
+                    round_trip_ms: peer
+                        .round_trip
+                        .map(|round_trip| round_trip.as_secs_f64() * 1000.0),
+                };
+                (*peer_id, health)
+            })
+            .collect()
+    }
+
+    /// Logs peers that went up or down since the last call.
+    fn log_transitions(&self) {
+        let now = Instant::now();
+        let mut peers = self
+            .peers
+            .lock()
+            .expect("Failure detector lock is poisoned");
+        for (peer_id, peer) in peers.iter_mut() {
+            let status = peer.status(now);
+            if peer.status.replace(status) != Some(status) {
+                println!("Peer {peer_id} is now {status:?}");
+            }
+        }
+    }
+
+    /// Probes every other known peer with the p2p `RootApi` call at a fixed interval.
+    /// Probes that take longer than `timeout` count as missed heartbeats.
+    pub async fn run_heartbeats(
+        channel_service: ChannelService,
+        interval: Duration,
+        timeout: Duration,
+    ) {
+        let mut interval = tokio::time::interval(interval);
+
+        loop {
+            interval.tick().await;
+
+            let peers = channel_service.other_peers().await;
+
+            let probes = peers.into_iter().map(|(peer_id, uri)| {
+                let channel_service = channel_service.clone();
+                async move {
+                    let started = Instant::now();
+                    let probe = async {
+                        let channel = channel_service
+                            .channel_pool
+                            .get_or_create_channel(uri)
+                            .await
+                            .map_err(|e| e.to_string())?;
+                        ServiceClient::new(channel)
+                            .root_api(RootApiRequest {})
+                            .await
+                            .map_err(|e| e.to_string())
+                    };
+
+                    if let Ok(Ok(_)) = tokio::time::timeout(timeout, probe).await {
+                        channel_service
+                            .failure_detector
+                            .record_heartbeat(peer_id, started.elapsed());
+                    }
+                }
+            });
+
+            join_all(probes).await;
+            channel_service.failure_detector.log_transitions();
+
+            // The peer might be alive on another address, e.g. after a restart with a new IP.
+            // Refreshes run in the background and are bounded by the interval, so unreachable
+            // peers neither delay the next heartbeats nor pile up refreshes.
+            for peer_id in channel_service.failure_detector.dead_peers() {
+                let channel_service = channel_service.clone();
+                let refresh_timeout = interval.period();
+                tokio::spawn(async move {
+                    let refresh = channel_service.refresh_address(peer_id);
+                    match tokio::time::timeout(refresh_timeout, refresh).await {
+                        Ok(Ok(_)) => {}
+                        Ok(Err(e)) => {
+                            eprintln!("Failed to refresh address of peer {peer_id}: {e}")
+                        }
+                        Err(_) => eprintln!(
+                            "Refreshing address of peer {peer_id} timed out after {refresh_timeout:?}"
+                        ),
+                    }
+                });
+            }
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phi_accrual() {
+        let detector = FailureDetector::default();
+        assert_eq!(detector.status(101), PeerStatus::Unknown);
+        assert!(detector.is_alive(101));
+
+        let start = Instant::now();
+        for i in 0..10 {
+            detector.record_heartbeat_at(101, start + HEARTBEAT_INTERVAL * i, Duration::ZERO);
+        }
+        let last_seen = start + HEARTBEAT_INTERVAL * 9;
+
+        let peers = detector.peers.lock().unwrap();
+        let peer = &peers[&101];
+
+        // Suspicion grows with the time since the last heartbeat
+        let phi_soon = peer.phi(last_seen + HEARTBEAT_INTERVAL).unwrap();
+        let phi_later = peer.phi(last_seen + HEARTBEAT_INTERVAL * 5).unwrap();
+        assert!(phi_soon < phi_later);
+
+        assert_eq!(
+            peer.status(last_seen + HEARTBEAT_INTERVAL * 2),
+            PeerStatus::Alive
+        );
+        assert_eq!(
+            peer.status(last_seen + HEARTBEAT_INTERVAL * 30),
+            PeerStatus::Dead
+        );
+    }
+
+    #[test]
+    fn test_detection_latency() {
+        let detector = FailureDetector::default();
+        let start = Instant::now();
+        for i in 0..20 {
+            detector.record_heartbeat_at(101, start + HEARTBEAT_INTERVAL * i, Duration::ZERO);
+        }
+        let last_seen = start + HEARTBEAT_INTERVAL * 19;
+
+        let peers = detector.peers.lock().unwrap();
+        let peer = &peers[&101];
+
+        let step = Duration::from_millis(100);
+        let latency = (1..)
+            .map(|i| step * i)
+            .find(|elapsed| peer.status(last_seen + *elapsed) == PeerStatus::Dead)
+            .unwrap();
+
+        // Faster than the former 10s timeout, without flagging a peer that missed a few heartbeats
+        assert!(latency < Duration::from_secs(10), "{latency:?}");
+        assert!(latency > HEARTBEAT_INTERVAL * 5, "{latency:?}");
+    }
+}
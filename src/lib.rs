@@ -2,5 +2,7 @@ pub mod api;
 pub mod args;
 pub mod channel_service;
 pub mod consensus;
+pub mod failure_detector;
+pub mod settings;
 pub mod storage;
 pub mod types;
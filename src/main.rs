@@ -2,31 +2,47 @@ pub mod api;
 pub mod args;
 pub mod channel_service;
 pub mod consensus;
+pub mod failure_detector;
+pub mod settings;
 pub mod storage;
 pub mod types;
 
+use crate::api::collection::create_shard_key;
 use crate::api::collection::delete_collection;
 use crate::api::collection::get_collection_cluster_info;
+use crate::api::collection::get_collection_distribution;
+use crate::api::collection::list_shard_keys;
+use crate::api::collection::update_shard_count;
 use crate::channel_service::ChannelService;
 use crate::consensus::Consensus;
 use crate::consensus::ConsensusState;
+use crate::failure_detector::FailureDetector;
 use crate::{
     api::{
-        cluster::{add_peer, get_cluster, ConsensusAppData},
+        backups::{create_backup, list_backups},
+        cluster::{
+            add_peer, drain_peer, get_cluster, get_drain_progress, get_hints, get_rebalance,
+            pause_rebalance, rebalance, remove_peer, resume_rebalance, ConsensusAppData,
+        },
         collection::{create_collection, get_collection, get_collections, Dispatcher},
         points::{get_point, list_points, upsert_points},
+        snapshots::{
+            create_snapshot, create_storage_snapshot, download_snapshot, download_storage_snapshot,
+            list_snapshots, list_storage_snapshots, recover_snapshot, upload_snapshot,
+        },
     },
     consensus::Msg,
-    storage::toc::TableOfContent,
+    storage::{backup::BackupStore, snapshots, toc::TableOfContent},
 };
 use actix_web::{
     middleware,
     web::{self, Data},
     App, HttpServer,
 };
-use api::service::index;
+use api::service::{get_settings, index};
 use args::parse_args;
 use http::Uri;
+use settings::Settings;
 use std::sync::{mpsc::Sender, Arc};
 
 // Function to start the Actix Web server
@@ -34,6 +50,7 @@ async fn start_http_server(
     url: Uri,
     consensus_app_data: Data<ConsensusAppData>,
     dispatcher_app_data: Data<Dispatcher>,
+    settings_app_data: Data<Settings>,
 ) -> std::io::Result<()> {
     println!("Starting Actix Web server on {url}");
 
@@ -43,18 +60,42 @@ async fn start_http_server(
         App::new()
             .wrap(middleware::NormalizePath::trim())
             .service(index)
+            .service(get_settings)
             .service(get_cluster)
             .service(add_peer)
+            .service(get_hints)
+            .service(drain_peer)
+            .service(get_drain_progress)
+            .service(remove_peer)
+            .service(get_rebalance)
+            .service(rebalance)
+            .service(pause_rebalance)
+            .service(resume_rebalance)
             .service(get_collections)
             .service(get_collection_cluster_info)
+            .service(get_collection_distribution)
             .service(get_collection)
             .service(delete_collection)
             .service(create_collection)
+            .service(update_shard_count)
+            .service(create_shard_key)
+            .service(list_shard_keys)
+            .service(create_snapshot)
+            .service(list_snapshots)
+            .service(recover_snapshot)
+            .service(upload_snapshot)
+            .service(download_snapshot)
+            .service(create_storage_snapshot)
+            .service(list_storage_snapshots)
+            .service(download_storage_snapshot)
+            .service(create_backup)
+            .service(list_backups)
             .service(upsert_points)
             .service(get_point)
             .service(list_points)
             .app_data(consensus_app_data.clone())
             .app_data(dispatcher_app_data.clone())
+            .app_data(settings_app_data.clone())
     })
     .bind((host, port))?
     .run()
@@ -83,11 +124,17 @@ async fn start_p2p_server(
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     let args = parse_args();
+    let settings = Settings::new(&args).expect("Failed to load settings");
+    println!(
+        "Settings: {}",
+        serde_json::to_string_pretty(&settings).expect("Settings are serializable")
+    );
 
     // Create a dedicated thread for internal gRPC service while we also run Actix Web server
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
-        .worker_threads(8)
+        .worker_threads(settings.runtime.worker_threads)
+        .max_blocking_threads(settings.runtime.max_blocking_threads)
         .thread_name("general")
         .build()
         .expect("Failed to create Tokio runtime");
@@ -95,17 +142,88 @@ async fn main() -> std::io::Result<()> {
     let consensus_async_runtime = rt.handle().clone();
 
     // Sharing the Arc<RwLock<HashMap<PeerId, Uri>>>
-    let consensus_state = Arc::new(ConsensusState::dummy(args.p2p_url.clone(), args.peer_id));
-    let channel_service = ChannelService::new(consensus_state.peer_address_by_id.clone());
+    let service = &settings.service;
+    let p2p_advertise_url = service
+        .p2p_advertise_url
+        .clone()
+        .unwrap_or_else(|| service.p2p_url.clone());
+    let advertise_url = service
+        .advertise_url
+        .clone()
+        .unwrap_or_else(|| service.url.clone());
+    if p2p_advertise_url.host() == Some("0.0.0.0") && args.bootstrap.is_some() {
+        eprintln!(
+            "Advertising {p2p_advertise_url} to other peers, set --p2p-advertise-url if they can't reach it"
+        );
+    }
+
+    let backup_store = args
+        .backup_url
+        .as_deref()
+        .map(|url| Arc::new(BackupStore::from_url(url).expect("Failed to open the backup store")));
+
+    let mut restored_consensus_state = None;
+    if let Some(snapshot) = &args.snapshot {
+        println!("Restoring storage from snapshot {}", snapshot.display());
+        restored_consensus_state = snapshots::restore_storage(snapshot, &settings.storage.path)
+            .expect("Failed to restore storage snapshot");
+    }
+    if let (Some(store), Some(backup_id)) = (&backup_store, &args.restore_backup) {
+        let (backup, consensus_state) = store
+            .restore_storage(backup_id, &settings.storage.path)
+            .await
+            .expect("Failed to restore backup");
+        println!("Restored storage from backup {}", backup.id);
+        restored_consensus_state = consensus_state;
+    }
 
-    let toc = TableOfContent::load(channel_service);
+    let restored_consensus_state = restored_consensus_state.and_then(|serialized| {
+        ConsensusState::restore(
+            &serialized,
+            p2p_advertise_url.clone(),
+            advertise_url.clone(),
+            args.peer_id,
+            args.keep_peers,
+        )
+        .map_err(|e| {
+            eprintln!("Starting with a new consensus state, the restored one is invalid: {e}")
+        })
+        .ok()
+    });
+    let consensus_state =
+        Arc::new(restored_consensus_state.unwrap_or_else(|| {
+            ConsensusState::dummy(p2p_advertise_url, advertise_url, args.peer_id)
+        }));
+    let channel_service = ChannelService::new(
+        consensus_state.peer_address_by_id.clone(),
+        consensus_state.persistent.read().await.peer_id,
+        settings.cluster.replica_timeout(),
+    );
+
+    let toc = TableOfContent::load(&settings.storage.path, channel_service);
     let toc_arc = Arc::new(toc);
 
+    rt.spawn(FailureDetector::run_heartbeats(
+        toc_arc.channel_service.clone(),
+        settings.cluster.heartbeat_interval(),
+        settings.cluster.heartbeat_timeout(),
+    ));
+    rt.spawn(
+        toc_arc
+            .clone()
+            .run_anti_entropy(settings.cluster.anti_entropy_interval()),
+    );
+    rt.spawn(toc_arc.clone().run_hint_handoff());
+    if args.rebalance {
+        rt.spawn(toc_arc.clone().run_rebalancer(args.rebalance_rate));
+    }
+
     let sender = Consensus::start(
         args.bootstrap.clone(),
         consensus_state.clone(),
         toc_arc.clone(),
         consensus_async_runtime,
+        settings.consensus.clone(),
     )
     .expect("Failed to start consensus");
 
@@ -114,13 +232,25 @@ async fn main() -> std::io::Result<()> {
     let dispatcher_app_data = web::Data::from(Arc::new(Dispatcher::from(
         toc_arc.clone(),
         Some(consensus_state.clone()),
+        backup_store,
     )));
 
+    let (url, p2p_url) = (
+        settings.service.url.clone(),
+        settings.service.p2p_url.clone(),
+    );
+    let settings_app_data = web::Data::new(settings);
+
     let rt_http = rt.handle().clone();
     let http_handle = std::thread::spawn(move || {
         rt_http.block_on(async {
-            if let Err(e) =
-                start_http_server(args.url, consensus_app_data, dispatcher_app_data).await
+            if let Err(e) = start_http_server(
+                url,
+                consensus_app_data,
+                dispatcher_app_data,
+                settings_app_data,
+            )
+            .await
             {
                 eprintln!("HTTP Server error: {e}");
             }
@@ -133,7 +263,7 @@ async fn main() -> std::io::Result<()> {
     let p2p_handle = std::thread::spawn(move || {
         rt_p2p.block_on(async {
             if let Err(e) =
-                start_p2p_server(args.p2p_url, toc_arc, sender_to_move, Some(consensus_state)).await
+                start_p2p_server(p2p_url, toc_arc, sender_to_move, Some(consensus_state)).await
             {
                 eprintln!("gRPC Server error: {e}");
             }
@@ -0,0 +1,314 @@
+use crate::args::Args;
+use config::{Config, ConfigError, Environment, File};
+use http::Uri;
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, time::Duration};
+
+/// Prefix of the environment variables overriding the settings, e.g. `SMOLDB__STORAGE__PATH`
+const ENV_PREFIX: &str = "SMOLDB";
+
+/// Resolved from, by increasing priority: the defaults, the config file of `--config-path`
+/// (YAML or TOML), the `SMOLDB__<SECTION>__<KEY>` environment variables and the CLI flags.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Settings {
+    pub storage: StorageSettings,
+    pub service: ServiceSettings,
+    pub cluster: ClusterSettings,
+    pub consensus: ConsensusSettings,
+    pub runtime: RuntimeSettings,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct StorageSettings {
+    /// Collections, hints and snapshots are stored in it
+    pub path: PathBuf,
+}
+
+impl Default for StorageSettings {
+    fn default() -> Self {
+        StorageSettings {
+            path: PathBuf::from("storage"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ServiceSettings {
+    /// Url of the HTTP API
+    #[serde(with = "uri")]
+    pub url: Uri,
+    /// Url of the p2p gRPC API
+    #[serde(with = "uri")]
+    pub p2p_url: Uri,
+    /// Url clients use to reach the HTTP API, defaults to `url`
+    #[serde(with = "optional_uri")]
+    pub advertise_url: Option<Uri>,
+    /// Url other peers use to reach the p2p API, defaults to `p2p_url`
+    #[serde(with = "optional_uri")]
+    pub p2p_advertise_url: Option<Uri>,
+}
+
+impl Default for ServiceSettings {
+    fn default() -> Self {
+        ServiceSettings {
+            url: Uri::from_static("http://0.0.0.0:9900"),
+            p2p_url: Uri::from_static("http://0.0.0.0:9920"),
+            advertise_url: None,
+            p2p_advertise_url: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ClusterSettings {
+    /// Default `timeout` of the requests waiting for the other peers, e.g. `?wait=true`
+    pub wait_timeout_sec: u64,
+    /// How often every known peer is probed
+    pub heartbeat_interval_ms: u64,
+    /// Probes that take longer than this count as missed heartbeats
+    pub heartbeat_timeout_ms: u64,
+    /// Time a remote replica gets to apply a write before it's considered failed
+    pub replica_timeout_ms: u64,
+    /// How often local shards are compared with their remote replicas
+    pub anti_entropy_interval_sec: u64,
+}
+
+impl Default for ClusterSettings {
+    fn default() -> Self {
+        ClusterSettings {
+            wait_timeout_sec: 30,
+            heartbeat_interval_ms: 1_000,
+            heartbeat_timeout_ms: 500,
+            replica_timeout_ms: 5_000,
+            anti_entropy_interval_sec: 30,
+        }
+    }
+}
+
+impl ClusterSettings {
+    pub fn wait_timeout(&self) -> Duration {
+        Duration::from_secs(self.wait_timeout_sec)
+    }
+
+    pub fn heartbeat_interval(&self) -> Duration {
+        Duration::from_millis(self.heartbeat_interval_ms)
+    }
+
+    pub fn heartbeat_timeout(&self) -> Duration {
+        Duration::from_millis(self.heartbeat_timeout_ms)
+    }
+
+    pub fn replica_timeout(&self) -> Duration {
+        Duration::from_millis(self.replica_timeout_ms)
+    }
+
+    pub fn anti_entropy_interval(&self) -> Duration {
+        Duration::from_secs(self.anti_entropy_interval_sec)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ConsensusSettings {
+    /// Raft is driven once per tick
+    pub tick_interval_ms: u64,
+    /// Timeout of the requests to the bootstrap peer when joining a cluster
+    pub bootstrap_timeout_sec: u64,
+}
+
+impl Default for ConsensusSettings {
+    fn default() -> Self {
+        ConsensusSettings {
+            tick_interval_ms: 100,
+            bootstrap_timeout_sec: 10,
+        }
+    }
+}
+
+impl ConsensusSettings {
+    pub fn tick_interval(&self) -> Duration {
+        Duration::from_millis(self.tick_interval_ms)
+    }
+
+    pub fn bootstrap_timeout(&self) -> Duration {
+        Duration::from_secs(self.bootstrap_timeout_sec)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RuntimeSettings {
+    /// Threads of the runtime serving the APIs and running the background tasks
+    pub worker_threads: usize,
+    /// Threads for blocking work like flushing segments and archiving snapshots
+    pub max_blocking_threads: usize,
+}
+
+impl Default for RuntimeSettings {
+    fn default() -> Self {
+        RuntimeSettings {
+            worker_threads: 8,
+            max_blocking_threads: 512,
+        }
+    }
+}
+
+impl Settings {
+    pub fn new(args: &Args) -> Result<Self, ConfigError> {
+        let mut builder = Config::builder();
+
+        if let Some(config_path) = &args.config_path {
+            builder = builder.add_source(File::from(config_path.as_path()));
+        }
+
+        let settings: Settings = builder
+            .add_source(
+                Environment::with_prefix(ENV_PREFIX)
+                    .prefix_separator("__")
+                    .separator("__")
+                    .try_parsing(true),
+            )
+            .set_override_option("service.url", args.url.as_ref().map(Uri::to_string))?
+            .set_override_option("service.p2p_url", args.p2p_url.as_ref().map(Uri::to_string))?
+            .set_override_option(
+                "service.advertise_url",
+                args.advertise_url.as_ref().map(Uri::to_string),
+            )?
+            .set_override_option(
+                "service.p2p_advertise_url",
+                args.p2p_advertise_url.as_ref().map(Uri::to_string),
+            )?
+            .build()?
+            .try_deserialize()?;
+
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let service = &self.service;
+        for url in [&service.url, &service.p2p_url] {
+            if url.host().is_none() || url.port_u16().is_none() {
+                return Err(ConfigError::Message(format!(
+                    "Url {url} needs a host and a port"
+                )));
+            }
+        }
+
+        if self.runtime.worker_threads == 0 || self.runtime.max_blocking_threads == 0 {
+            return Err(ConfigError::Message(
+                "Runtime needs at least one worker and one blocking thread".to_string(),
+            ));
+        }
+        if self.consensus.tick_interval_ms == 0
+            || self.cluster.heartbeat_interval_ms == 0
+            || self.cluster.anti_entropy_interval_sec == 0
+        {
+            return Err(ConfigError::Message(
+                "Consensus tick, heartbeat and anti-entropy intervals can't be zero".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+mod uri {
+    use http::Uri;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(uri: &Uri, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(uri)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Uri, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+mod optional_uri {
+    use http::Uri;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(uri: &Option<Uri>, serializer: S) -> Result<S::Ok, S::Error> {
+        match uri {
+            Some(uri) => serializer.collect_str(uri),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Uri>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|uri| uri.parse().map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_settings_sources() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let config_path = tmp_dir.path().join("config.yaml");
+        std::fs::write(
+            &config_path,
+            "storage:\n  path: /data/smoldb\nservice:\n  url: http://0.0.0.0:9000\nconsensus:\n  tick_interval_ms: 50\n",
+        )
+        .unwrap();
+
+        let args = Args::parse_from([
+            "smoldb",
+            "--config-path",
+            config_path.to_str().unwrap(),
+            "--p2p-url",
+            "http://127.0.0.1:9021",
+        ]);
+        let settings = Settings::new(&args).unwrap();
+
+        assert_eq!(settings.storage.path, PathBuf::from("/data/smoldb"));
+        assert_eq!(settings.service.url, "http://0.0.0.0:9000");
+        assert_eq!(settings.service.p2p_url, "http://127.0.0.1:9021");
+        assert_eq!(
+            settings.consensus.tick_interval(),
+            Duration::from_millis(50)
+        );
+        // Sections missing from the file keep their defaults
+        assert_eq!(settings.cluster.wait_timeout(), Duration::from_secs(30));
+        assert_eq!(settings.runtime.worker_threads, 8);
+
+        // Env vars override both the file and the defaults
+        std::fs::write(
+            &config_path,
+            "cluster:\n  replica_timeout_ms: 1000\n  heartbeat_interval_ms: 200\n",
+        )
+        .unwrap();
+        std::env::set_var("SMOLDB__CLUSTER__REPLICA_TIMEOUT_MS", "2000");
+        std::env::set_var("SMOLDB__CLUSTER__ANTI_ENTROPY_INTERVAL_SEC", "5");
+        let settings = Settings::new(&args);
+        std::env::remove_var("SMOLDB__CLUSTER__REPLICA_TIMEOUT_MS");
+        std::env::remove_var("SMOLDB__CLUSTER__ANTI_ENTROPY_INTERVAL_SEC");
+        let settings = settings.unwrap();
+        assert_eq!(settings.cluster.replica_timeout(), Duration::from_secs(2));
+        assert_eq!(
+            settings.cluster.anti_entropy_interval(),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            settings.cluster.heartbeat_interval(),
+            Duration::from_millis(200)
+        );
+
+        std::fs::write(&config_path, "service:\n  url: not a url\n").unwrap();
+        assert!(Settings::new(&args).is_err());
+    }
+}
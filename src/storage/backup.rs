@@ -0,0 +1,661 @@
+use crate::storage::{
+    error::StorageError,
+    snapshots::{self, CONSENSUS_FILE},
+    toc::COLLECTIONS_DIR,
+};
+use futures::StreamExt;
+use object_store::{
+    aws::AmazonS3Builder, buffered::BufWriter, local::LocalFileSystem, path::Path as StorePath,
+    prefix::PrefixStore, ObjectStore,
+};
+use serde::{Deserialize, Serialize};
+use siphasher::sip128::{Hasher128, SipHasher13};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    hash::Hasher,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{io::AsyncWriteExt, sync::Mutex};
+
+/// Restores the most recent backup of the chain
+pub const LATEST_BACKUP: &str = "latest";
+
+/// Files are split into chunks of this size, only the changed chunks of a file are uploaded
+pub const BACKUP_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+const MANIFESTS_DIR: &str = "manifests";
+const FILES_DIR: &str = "files";
+/// Staged chunks are kept apart from the files staged as a whole, like the consensus state
+const STAGED_CHUNKS_DIR: &str = "chunks";
+
+/// Files of the storage in a backup, keyed by their path relative to the storage directory
+pub type BackupFiles = BTreeMap<String, BackupFile>;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BackupFile {
+    pub size: u64,
+    /// Nanoseconds since the Unix epoch, files with the same size and time aren't read again
+    pub modified: u128,
+    /// SipHash-1-3 of the content, in hex
+    pub hash: String,
+    /// Consecutive [`BACKUP_CHUNK_SIZE`] ranges of the content
+    pub chunks: Vec<BackupChunk>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BackupChunk {
+    /// SipHash-1-3 of the range, in hex
+    pub hash: String,
+    /// Backup holding the range, this one or one of its ancestors
+    pub backup_id: String,
+}
+
+impl BackupFile {
+    /// Size of the ranges of the file uploaded by the backup
+    fn uploaded_size(&self, backup_id: &str) -> u64 {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.backup_id == backup_id)
+            .map(|(index, _)| chunk_size(self.size, index))
+            .sum()
+    }
+}
+
+fn chunk_size(file_size: u64, index: usize) -> u64 {
+    let start = index as u64 * BACKUP_CHUNK_SIZE;
+    file_size.saturating_sub(start).min(BACKUP_CHUNK_SIZE)
+}
+
+/// Stored as `manifests/<id>.json` once every file of the backup is uploaded.
+/// It lists all the files of the storage, restoring a backup only needs its manifest.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BackupManifest {
+    pub id: String,
+    /// Previous backup of the chain, the unchanged files are stored in it or its ancestors
+    pub parent: Option<String>,
+    /// Seconds since the Unix epoch
+    pub creation_time: u64,
+    pub files: BackupFiles,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BackupDescription {
+    pub id: String,
+    pub parent: Option<String>,
+    pub creation_time: u64,
+    pub file_count: usize,
+    /// Size of the storage in bytes
+    pub size: u64,
+    /// Files with content uploaded by this backup, in full or only their changed chunks
+    pub uploaded_files: usize,
+    pub uploaded_size: u64,
+}
+
+impl From<&BackupManifest> for BackupDescription {
+    fn from(manifest: &BackupManifest) -> Self {
+        let uploaded_sizes = manifest
+            .files
+            .values()
+            .map(|file| file.uploaded_size(&manifest.id))
+            .filter(|size| *size > 0)
+            .collect::<Vec<_>>();
+
+        BackupDescription {
+            id: manifest.id.clone(),
+            parent: manifest.parent.clone(),
+            creation_time: manifest.creation_time,
+            file_count: manifest.files.len(),
+            size: manifest.files.values().map(|file| file.size).sum(),
+            uploaded_files: uploaded_sizes.len(),
+            uploaded_size: uploaded_sizes.iter().sum(),
+        }
+    }
+}
+
+/// Files of a backup waiting to be uploaded, see [`stage_backup`]
+pub struct StagedBackup {
+    pub manifest: BackupManifest,
+    /// Holds the new and changed chunks of the files, see [`staged_chunk_path`]
+    pub dir: PathBuf,
+}
+
+/// Chain of incremental backups of the storage in an object store.
+/// Only the chunks of the files that changed since the previous backup are uploaded,
+/// e.g. the pages written to the sled database of a shard rather than the whole database.
+pub struct BackupStore {
+    store: Arc<dyn ObjectStore>,
+    /// Backups are taken one at a time, each one is the parent of the next
+    pub(crate) in_progress: Mutex<()>,
+}
+
+fn store_error(e: object_store::Error) -> StorageError {
+    StorageError::ServiceError(format!("Backup store error: {e}"))
+}
+
+impl BackupStore {
+    pub fn new(store: Arc<dyn ObjectStore>) -> Self {
+        BackupStore {
+            store,
+            in_progress: Mutex::new(()),
+        }
+    }
+
+    /// `file:///path/to/dir` for a local directory, or `s3://bucket/prefix` for S3 and compatible
+    /// stores like MinIO. S3 credentials and endpoint are read from the `AWS_*` variables, e.g.
+    /// `AWS_ENDPOINT=http://localhost:9000 AWS_ALLOW_HTTP=true` for a local MinIO.
+    pub fn from_url(url: &str) -> Result<Self, StorageError> {
+        let store: Arc<dyn ObjectStore> = if let Some(path) = url.strip_prefix("file://") {
+            std::fs::create_dir_all(path).map_err(|e| {
+                StorageError::BadInput(format!("Can't create backup directory {path}: {e}"))
+            })?;
+            Arc::new(LocalFileSystem::new_with_prefix(path).map_err(store_error)?)
+        } else if let Some(location) = url.strip_prefix("s3://") {
+            let s3 = AmazonS3Builder::from_env()
+                .with_url(url)
+                .build()
+                .map_err(|e| StorageError::BadInput(format!("Invalid S3 backup url: {e}")))?;
+
+            match location.split_once('/') {
+                Some((_, prefix)) if !prefix.trim_matches('/').is_empty() => {
+                    Arc::new(PrefixStore::new(s3, prefix.trim_matches('/')))
+                }
+                _ => Arc::new(s3),
+            }
+        } else {
+            return Err(StorageError::BadInput(format!(
+                "Unsupported backup url '{url}', expected file:// or s3://"
+            )));
+        };
+
+        Ok(BackupStore::new(store))
+    }
+
+    /// Backups of the chain, oldest first
+    pub async fn list_backups(&self) -> Result<Vec<BackupManifest>, StorageError> {
+        let mut manifests = vec![];
+        for id in self.backup_ids().await? {
+            manifests.push(self.get_manifest(&id).await?);
+        }
+        Ok(manifests)
+    }
+
+    pub async fn latest(&self) -> Result<Option<BackupManifest>, StorageError> {
+        match self.backup_ids().await?.pop() {
+            Some(id) => Ok(Some(self.get_manifest(&id).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Ids sort by creation time, see [`stage_backup`]
+    async fn backup_ids(&self) -> Result<Vec<String>, StorageError> {
+        let mut listing = self.store.list(Some(&StorePath::from(MANIFESTS_DIR)));
+
+        let mut ids = vec![];
+        while let Some(meta) = listing.next().await {
+            let meta = meta.map_err(store_error)?;
+            if let Some(id) = meta
+                .location
+                .filename()
+                .and_then(|name| name.strip_suffix(".json"))
+            {
+                ids.push(id.to_string());
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    pub async fn get_manifest(&self, id: &str) -> Result<BackupManifest, StorageError> {
+        let location = StorePath::from(MANIFESTS_DIR).child(format!("{id}.json"));
+        let bytes = match self.store.get(&location).await {
+            Ok(result) => result.bytes().await.map_err(store_error)?,
+            Err(object_store::Error::NotFound { .. }) => {
+                return Err(StorageError::BadInput(format!(
+                    "Backup '{id}' does not exist"
+                )))
+            }
+            Err(e) => return Err(store_error(e)),
+        };
+
+        serde_json::from_slice(&bytes).map_err(|e| {
+            StorageError::ServiceError(format!("Invalid manifest of backup '{id}': {e}"))
+        })
+    }
+
+    /// Uploads the staged files, then the manifest which adds the backup to the chain.
+    /// A failed upload leaves the chain untouched.
+    pub async fn upload(&self, staged: &StagedBackup) -> Result<BackupDescription, StorageError> {
+        let manifest = &staged.manifest;
+        for (path, file) in &manifest.files {
+            for (index, chunk) in file.chunks.iter().enumerate() {
+                if chunk.backup_id != manifest.id {
+                    continue;
+                }
+
+                let mut source = tokio::fs::File::open(staged_chunk_path(&staged.dir, path, index))
+                    .await
+                    .map_err(|e| {
+                        StorageError::ServiceError(format!(
+                            "Failed to read staged chunk {index} of {path}: {e}"
+                        ))
+                    })?;
+                let mut writer = BufWriter::new(
+                    self.store.clone(),
+                    chunk_location(&manifest.id, path, index),
+                );
+
+                let upload = async {
+                    tokio::io::copy(&mut source, &mut writer).await?;
+                    writer.shutdown().await
+                };
+                upload.await.map_err(|e| {
+                    StorageError::ServiceError(format!(
+                        "Failed to upload chunk {index} of {path}: {e}"
+                    ))
+                })?;
+            }
+        }
+
+        let manifest_bytes = serde_json::to_vec_pretty(manifest).map_err(|e| {
+            StorageError::ServiceError(format!("Failed to serialize backup manifest: {e}"))
+        })?;
+        self.store
+            .put(
+                &StorePath::from(MANIFESTS_DIR).child(format!("{}.json", manifest.id)),
+                manifest_bytes.into(),
+            )
+            .await
+            .map_err(store_error)?;
+
+        Ok(BackupDescription::from(manifest))
+    }
+
+    /// Downloads the files of a backup, or of the [`LATEST_BACKUP`], into `storage_dir`.
+    /// Like snapshots, backups can only be restored into a storage without collections.
+    /// Returns the restored backup along with its consensus state.
+    pub async fn restore_storage(
+        &self,
+        backup_id: &str,
+        storage_dir: &Path,
+    ) -> Result<(BackupManifest, Option<Vec<u8>>), StorageError> {
+        let manifest = if backup_id == LATEST_BACKUP {
+            self.latest()
+                .await?
+                .ok_or_else(|| StorageError::BadInput("There are no backups".to_string()))?
+        } else {
+            self.get_manifest(backup_id).await?
+        };
+
+        let unpack_dir = snapshots::restoring_dir(storage_dir)?;
+        if let Err(e) = self.download(&manifest, &unpack_dir).await {
+            let _ = tokio::fs::remove_dir_all(&unpack_dir).await;
+            return Err(e);
+        }
+        let consensus_state = snapshots::restore_unpacked_storage(&unpack_dir, storage_dir)?;
+
+        Ok((manifest, consensus_state))
+    }
+
+    async fn download(
+        &self,
+        manifest: &BackupManifest,
+        target_dir: &Path,
+    ) -> Result<(), StorageError> {
+        for (path, file) in &manifest.files {
+            let target = target_dir.join(path);
+
+            // The chunks of a file can come from different backups of the chain
+            let download = async {
+                if let Some(parent) = target.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                let mut output = tokio::fs::File::create(&target).await?;
+                for (index, chunk) in file.chunks.iter().enumerate() {
+                    let mut stream = self
+                        .store
+                        .get(&chunk_location(&chunk.backup_id, path, index))
+                        .await
+                        .map_err(std::io::Error::other)?
+                        .into_stream();
+                    while let Some(bytes) = stream.next().await {
+                        output
+                            .write_all(&bytes.map_err(std::io::Error::other)?)
+                            .await?;
+                    }
+                }
+                output.sync_all().await
+            };
+            download.await.map_err(|e| {
+                StorageError::ServiceError(format!(
+                    "Failed to download {path} of backup {}: {e}",
+                    manifest.id
+                ))
+            })?;
+
+            let target_clone = target.clone();
+            let hash = tokio::task::spawn_blocking(move || hash_file(&target_clone))
+                .await
+                .map_err(|e| StorageError::ServiceError(format!("Hash task failed: {e}")))?
+                .map_err(|e| StorageError::ServiceError(format!("Failed to hash {path}: {e}")))?;
+            if hash != file.hash {
+                return Err(StorageError::ServiceError(format!(
+                    "Content of {path} in backup {} is corrupted",
+                    manifest.id
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn chunk_location(backup_id: &str, path: &str, index: usize) -> StorePath {
+    let mut location = StorePath::from(FILES_DIR).child(backup_id);
+    for part in path.split('/') {
+        location = location.child(part);
+    }
+    location.child(index.to_string())
+}
+
+fn staged_chunk_path(staging_dir: &Path, path: &str, index: usize) -> PathBuf {
+    staging_dir
+        .join(STAGED_CHUNKS_DIR)
+        .join(path)
+        .join(index.to_string())
+}
+
+fn format_hash(hasher: &SipHasher13) -> String {
+    format!("{:032x}", hasher.finish128().as_u128())
+}
+
+fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = SipHasher13::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.write(&buffer[..read]);
+    }
+    Ok(format_hash(&hasher))
+}
+
+/// Hashes of the whole file and of each of its [`BACKUP_CHUNK_SIZE`] chunks, read in one pass
+fn hash_chunks(path: &Path) -> std::io::Result<(String, Vec<String>)> {
+    let mut file = File::open(path)?;
+    let mut file_hasher = SipHasher13::new();
+    let mut chunk_hashes = vec![];
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let mut chunk_hasher = SipHasher13::new();
+        let mut chunk = (&mut file).take(BACKUP_CHUNK_SIZE);
+        let mut chunk_len = 0;
+        loop {
+            let read = chunk.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            file_hasher.write(&buffer[..read]);
+            chunk_hasher.write(&buffer[..read]);
+            chunk_len += read;
+        }
+        if chunk_len == 0 {
+            break;
+        }
+        chunk_hashes.push(format_hash(&chunk_hasher));
+    }
+    Ok((format_hash(&file_hasher), chunk_hashes))
+}
+
+/// Copies the chunk of the file to the staging directory
+fn stage_chunk(path: &Path, index: usize, staged: &Path) -> std::io::Result<()> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(index as u64 * BACKUP_CHUNK_SIZE))?;
+    std::fs::create_dir_all(staged.parent().expect("Staged chunks have a parent"))?;
+    let mut output = File::create(staged)?;
+    std::io::copy(&mut file.take(BACKUP_CHUNK_SIZE), &mut output)?;
+    Ok(())
+}
+
+/// Files under `dir`, with their path relative to `root` joined with `/`
+fn walk_files(root: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk_files(root, &path, files)?;
+        } else if path.is_file() {
+            let relative = path
+                .strip_prefix(root)
+                .expect("Walked files are under the root")
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push((relative, path));
+        }
+    }
+    Ok(())
+}
+
+/// Copies the chunks of the files of the storage that changed since the `previous` backup
+/// into `staging_dir`. Files are compared by size and modification time first, then chunk by chunk.
+/// Writes to the storage must be blocked meanwhile, like for [`snapshots::archive_storage`].
+pub fn stage_backup(
+    storage_dir: &Path,
+    consensus_state: &[u8],
+    previous: Option<&BackupManifest>,
+    staging_dir: &Path,
+) -> Result<StagedBackup, StorageError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time is before the Unix epoch");
+    let id = format!("backup-{}", now.as_millis());
+    let empty = BackupFiles::new();
+    let previous_files = previous.map_or(&empty, |previous| &previous.files);
+
+    let stage = || -> std::io::Result<BackupFiles> {
+        std::fs::create_dir_all(staging_dir)?;
+        // Staged like any other file so it's only uploaded when it changes
+        std::fs::write(staging_dir.join(CONSENSUS_FILE), consensus_state)?;
+
+        let mut paths = vec![(CONSENSUS_FILE.to_string(), staging_dir.join(CONSENSUS_FILE))];
+        let collections_dir = storage_dir.join(COLLECTIONS_DIR);
+        if collections_dir.exists() {
+            walk_files(storage_dir, &collections_dir, &mut paths)?;
+        }
+
+        let mut files = BackupFiles::new();
+        for (relative, path) in paths {
+            let metadata = std::fs::metadata(&path)?;
+            let modified = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_nanos());
+            let previous_file = previous_files.get(&relative);
+
+            if let Some(file) = previous_file
+                .filter(|file| file.size == metadata.len() && file.modified == modified)
+            {
+                files.insert(relative, file.clone());
+                continue;
+            }
+
+            // Chunks are compared by position, e.g. pages rewritten in place or appended
+            let (hash, chunk_hashes) = hash_chunks(&path)?;
+            let mut chunks = Vec::with_capacity(chunk_hashes.len());
+            for (index, chunk_hash) in chunk_hashes.into_iter().enumerate() {
+                let previous_chunk = previous_file
+                    .and_then(|file| file.chunks.get(index))
+                    .filter(|chunk| chunk.hash == chunk_hash);
+
+                let backup_id = match previous_chunk {
+                    Some(chunk) => chunk.backup_id.clone(),
+                    None => {
+                        stage_chunk(
+                            &path,
+                            index,
+                            &staged_chunk_path(staging_dir, &relative, index),
+                        )?;
+                        id.clone()
+                    }
+                };
+                chunks.push(BackupChunk {
+                    hash: chunk_hash,
+                    backup_id,
+                });
+            }
+
+            files.insert(
+                relative,
+                BackupFile {
+                    size: metadata.len(),
+                    modified,
+                    hash,
+                    chunks,
+                },
+            );
+        }
+        Ok(files)
+    };
+
+    let files =
+        stage().map_err(|e| StorageError::ServiceError(format!("Failed to stage backup: {e}")))?;
+
+    Ok(StagedBackup {
+        manifest: BackupManifest {
+            id,
+            parent: previous.map(|previous| previous.id.clone()),
+            creation_time: now.as_secs(),
+            files,
+        },
+        dir: staging_dir.to_path_buf(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    fn stage(storage: &Path, previous: Option<&BackupManifest>) -> StagedBackup {
+        let staging = storage.join("staging");
+        let _ = std::fs::remove_dir_all(&staging);
+        // Ids have a millisecond resolution
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        stage_backup(storage, b"{}", previous, &staging).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_incremental_backups() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = tmp_dir.path().join("storage");
+        let shard = storage.join(COLLECTIONS_DIR).join("c1/0");
+        std::fs::create_dir_all(&shard).unwrap();
+        std::fs::write(storage.join(COLLECTIONS_DIR).join("c1/config.json"), "{}").unwrap();
+        std::fs::write(shard.join("db"), "points").unwrap();
+        std::fs::write(shard.join("conf"), "segment").unwrap();
+
+        let store = BackupStore::new(Arc::new(InMemory::new()));
+        let first = store.upload(&stage(&storage, None)).await.unwrap();
+        assert_eq!(first.parent, None);
+        assert_eq!(first.uploaded_files, 4);
+
+        // Only the changed and new files are uploaded, removed files leave the manifest
+        std::fs::write(shard.join("db"), "more points").unwrap();
+        std::fs::write(shard.join("blob"), "new").unwrap();
+        std::fs::remove_file(shard.join("conf")).unwrap();
+        let latest = store.latest().await.unwrap();
+        let second = store
+            .upload(&stage(&storage, latest.as_ref()))
+            .await
+            .unwrap();
+        assert_eq!(second.parent, Some(first.id.clone()));
+        assert_eq!(second.file_count, 4);
+        assert_eq!(second.uploaded_files, 2);
+
+        let backups = store.list_backups().await.unwrap();
+        assert_eq!(
+            backups.iter().map(|b| b.id.clone()).collect::<Vec<_>>(),
+            vec![first.id.clone(), second.id.clone()]
+        );
+
+        let restored = tmp_dir.path().join("restored");
+        let (_, consensus_state) = store
+            .restore_storage(LATEST_BACKUP, &restored)
+            .await
+            .unwrap();
+        assert_eq!(consensus_state.as_deref(), Some(b"{}".as_slice()));
+        let restored_shard = restored.join(COLLECTIONS_DIR).join("c1/0");
+        assert_eq!(
+            std::fs::read_to_string(restored_shard.join("db")).unwrap(),
+            "more points"
+        );
+        assert!(restored_shard.join("blob").exists());
+        assert!(!restored_shard.join("conf").exists());
+        assert!(restored
+            .join(COLLECTIONS_DIR)
+            .join("c1/config.json")
+            .exists());
+
+        // Any backup of the chain can be restored, but never over existing collections
+        assert!(store.restore_storage(&first.id, &restored).await.is_err());
+        let older = tmp_dir.path().join("older");
+        store.restore_storage(&first.id, &older).await.unwrap();
+        let older_shard = older.join(COLLECTIONS_DIR).join("c1/0");
+        assert_eq!(
+            std::fs::read_to_string(older_shard.join("db")).unwrap(),
+            "points"
+        );
+        assert!(older_shard.join("conf").exists());
+
+        assert!(store
+            .restore_storage("missing", &tmp_dir.path().join("missing"))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_changed_chunks() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = tmp_dir.path().join("storage");
+        let shard = storage.join(COLLECTIONS_DIR).join("c1/0");
+        std::fs::create_dir_all(&shard).unwrap();
+
+        let chunk_size = BACKUP_CHUNK_SIZE as usize;
+        let mut content = vec![1u8; chunk_size * 5 / 2];
+        std::fs::write(shard.join("db"), &content).unwrap();
+
+        let store = BackupStore::new(Arc::new(InMemory::new()));
+        let first = store.upload(&stage(&storage, None)).await.unwrap();
+        let consensus_size = b"{}".len() as u64;
+        assert_eq!(first.uploaded_size, content.len() as u64 + consensus_size);
+
+        // Only the chunk written in place and the appended one are uploaded again
+        content[chunk_size + 10] = 2;
+        content.extend_from_slice(&[3; 100]);
+        std::fs::write(shard.join("db"), &content).unwrap();
+        let latest = store.latest().await.unwrap();
+        let second = store
+            .upload(&stage(&storage, latest.as_ref()))
+            .await
+            .unwrap();
+        assert_eq!(second.uploaded_files, 1);
+        assert_eq!(
+            second.uploaded_size,
+            BACKUP_CHUNK_SIZE + BACKUP_CHUNK_SIZE / 2 + 100
+        );
+
+        let restored = tmp_dir.path().join("restored");
+        store.restore_storage(&second.id, &restored).await.unwrap();
+        assert_eq!(
+            std::fs::read(restored.join(COLLECTIONS_DIR).join("c1/0/db")).unwrap(),
+            content
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Bits reserved for the logical counter in the lower part of a version.
+const LOGICAL_BITS: u32 = 16;
+
+/// Hybrid logical clock used to version points.
+///
+/// Versions hold the wall clock milliseconds in the high bits and a logical counter in the low
+/// bits. They are comparable across peers (close to the wall clock) while staying strictly
+/// monotonic on each peer, even if its wall clock goes backwards. Collections observe the
+/// highest stored version when they are loaded, so that it also holds across restarts.
+#[derive(Default)]
+pub struct HybridLogicalClock {
+    last: AtomicU64,
+}
+
+impl HybridLogicalClock {
+    /// Returns a new version, greater than any version issued or observed before.
+    pub fn tick(&self) -> u64 {
+        let physical = Self::physical_now() << LOGICAL_BITS;
+
+        let previous = self
+            .last
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+                Some(physical.max(last + 1))
+            })
+            .expect("Closure never returns None");
+
+        physical.max(previous + 1)
+    }
+
+    /// Moves the clock forward when a version generated by another peer is received.
+    pub fn observe(&self, version: u64) {
+        self.last.fetch_max(version, Ordering::SeqCst);
+    }
+
+    fn physical_now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default()
+    }
+}
@@ -1,18 +1,29 @@
 use crate::{
+    channel_service::ChannelService,
     storage::{
+        clock::HybridLogicalClock,
         error::{CollectionError, CollectionResult, StorageError},
-        replicas::{local_shard::LocalShard, ReplicaHolder, ReplicaSet, ShardOperationTrait},
-        segment::{Point, PointId},
+        hints::{Hint, HintStore},
+        replicas::{
+            anti_entropy::SyncStats,
+            consistency::ReadConsistency,
+            local_shard::{LocalShard, RELEASE_POLL_INTERVAL, RELEASE_TIMEOUT},
+            ring::RingConfig,
+            OnRemoteFailure, ReplicaHolder, ReplicaId, ReplicaSet, ShardOperationTrait,
+            ShardSelector, ShardingMethod,
+        },
+        segment::{Point, PointId, StorageType},
+        snapshots,
     },
-    types::ShardId,
+    types::{PeerId, ShardId, ShardKey},
 };
-use futures::FutureExt;
+use futures::{future::try_join_all, FutureExt};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{btree_map::Entry, BTreeMap, HashMap},
+    collections::{btree_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet},
     io::Write,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{atomic::Ordering, Arc},
 };
 use tokio::sync::RwLock;
 
@@ -20,6 +31,12 @@ pub const COLLECTION_CONFIG_FILE: &str = "config.json";
 
 pub const DEFAULT_CONSISTENCY_FACTOR: usize = 2;
 
+pub const DEFAULT_SHARD_COUNT: u32 = 2;
+
+/// Number of points scanned and moved between shards at once while resharding.
+/// Writes to the collection are blocked while a batch is being moved.
+const RESHARDING_BATCH_SIZE: usize = 1_000;
+
 pub type CollectionName = String;
 
 pub struct Collection {
@@ -27,6 +44,9 @@ pub struct Collection {
     pub config: CollectionConfig,
     pub replica_holder: Arc<RwLock<ReplicaHolder>>,
     pub path: PathBuf,
+    /// Issues versions of the points written through this peer
+    pub clock: HybridLogicalClock,
+    channel_service: ChannelService,
 }
 
 impl Collection {
@@ -34,24 +54,26 @@ impl Collection {
         id: CollectionName,
         config: CollectionConfig,
         path: &Path,
+        channel_service: ChannelService,
     ) -> Result<Self, StorageError> {
         // ToDo: Create ShardHolder & ShardReplicaSet
 
         config.save(path)?;
 
-        // ToDo: Initialize shards == num_cpus for max parallelism
-        let s0_path = path.join("0");
-        let s1_path = path.join("1");
+        // Shards of a collection with custom sharding are created per shard key
+        let shard_count = match config.sharding_method {
+            ShardingMethod::Auto => config.shard_count,
+            ShardingMethod::Custom => 0,
+        };
 
-        let shards = [s0_path, s1_path]
-            .into_iter()
-            .enumerate()
-            .map(|(shard_id, shard_path)| {
-                let shard_id = shard_id as ShardId;
+        // ToDo: Initialize shards == num_cpus for max parallelism
+        let shards = (0..shard_count)
+            .map(|shard_id| {
                 let replica_set = ReplicaSet::new(
-                    LocalShard::init(shard_path, shard_id),
+                    LocalShard::init(path.join(shard_id.to_string()), shard_id, config.storage),
                     vec![], // No remote shards for now
                     id.clone(),
+                    channel_service.clone(),
                 );
 
                 Ok((shard_id, replica_set))
@@ -59,12 +81,20 @@ impl Collection {
             .collect::<Result<HashMap<_, _>, StorageError>>()?;
 
         // ToDo: Add remote shards to replica holder while creating a new collection?
+        let replica_holder = match config.sharding_method {
+            ShardingMethod::Auto => ReplicaHolder::new(shards, config.ring.clone()),
+            ShardingMethod::Custom => {
+                ReplicaHolder::with_shard_keys(shards, &config.shard_keys, config.ring.clone())
+            }
+        };
 
         Ok(Collection {
             id,
             config,
-            replica_holder: Arc::new(RwLock::new(ReplicaHolder::new(shards))),
+            replica_holder: Arc::new(RwLock::new(replica_holder)),
             path: path.to_owned(),
+            clock: HybridLogicalClock::default(),
+            channel_service,
         })
     }
 
@@ -83,7 +113,38 @@ impl Collection {
         Ok(())
     }
 
-    pub fn load(id: CollectionName, path: &Path) -> Result<Self, StorageError> {
+    /// Waits until background tasks dropped their references to the shards of the collection,
+    /// so that dropping the collection closes their files. Has to be removed from the collections first.
+    pub async fn wait_released(&self) -> Result<(), StorageError> {
+        let deadline = tokio::time::Instant::now() + RELEASE_TIMEOUT;
+        loop {
+            let released = Arc::strong_count(&self.replica_holder) == 1
+                && self
+                    .replica_holder
+                    .read()
+                    .await
+                    .shards
+                    .values()
+                    .all(|replica_set| Arc::strong_count(&replica_set.local) == 1);
+            if released {
+                return Ok(());
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                return Err(StorageError::ServiceError(format!(
+                    "Collection {} is still in use after {RELEASE_TIMEOUT:?}",
+                    self.id
+                )));
+            }
+            tokio::time::sleep(RELEASE_POLL_INTERVAL).await;
+        }
+    }
+
+    pub fn load(
+        id: CollectionName,
+        path: &Path,
+        channel_service: ChannelService,
+    ) -> Result<Self, StorageError> {
         let config_path = path.join(COLLECTION_CONFIG_FILE);
         if !config_path.exists() {
             return Err(StorageError::BadInput(format!(
@@ -92,55 +153,411 @@ impl Collection {
             )));
         }
 
-        let config: CollectionConfig = {
-            let config_file = std::fs::File::open(&config_path).map_err(|e| {
-                StorageError::ServiceError(format!("Failed to open collection config file: {e}"))
-            })?;
+        let config = CollectionConfig::load(path)?;
+
+        let shards = match config.storage {
+            StorageType::Sled | StorageType::Log => {
+                let dir_contents = std::fs::read_dir(path).map_err(|e| {
+                    StorageError::ServiceError(format!("Failed to read collection directory: {e}"))
+                })?;
 
-            let config_file_buf = std::io::BufReader::new(&config_file);
-            serde_json::from_reader(config_file_buf).map_err(|e| {
-                StorageError::BadInput(format!("Failed to parse collection config JSON: {e}"))
-            })?
+                let mut shards = vec![];
+                for entry in dir_contents {
+                    let path = entry.expect("Can't read directory entry").path();
+                    if path.is_dir() {
+                        shards.push(LocalShard::load(&path, config.storage)?);
+                    }
+                }
+                shards
+            }
+            // Points in memory didn't survive the restart, the shards start empty
+            StorageType::Memory => config
+                .shard_ids()
+                .into_iter()
+                .map(|shard_id| {
+                    LocalShard::init(path.join(shard_id.to_string()), shard_id, config.storage)
+                })
+                .collect(),
         };
 
-        // ToDo: Load shards
+        // Versions issued before the restart stay lower than the new ones,
+        // even if the wall clock went backwards meanwhile
+        let clock = HybridLogicalClock::default();
+        for shard in &shards {
+            clock.observe(shard.max_version()?);
+        }
+
         let mut replicas = HashMap::new();
-        let dir_contents = std::fs::read_dir(path).map_err(|e| {
-            StorageError::ServiceError(format!("Failed to read collection directory: {e}"))
-        })?;
+        let mut resharding_replicas = HashMap::new();
+        for shard in shards {
+            let shard_id = shard.id;
+
+            // ToDo: Load remote shards if any
+            let replica_set = ReplicaSet::new(shard, vec![], id.clone(), channel_service.clone());
 
-        for entry in dir_contents {
-            let path = entry.expect("Can't read directory entry").path();
-            if !path.is_dir() {
-                continue; // Skip non-directory entries
+            if config.sharding_method == ShardingMethod::Custom {
+                if config
+                    .shard_keys
+                    .values()
+                    .flatten()
+                    .any(|id| *id == shard_id)
+                {
+                    replicas.insert(shard_id, replica_set);
+                } else {
+                    println!("Skipping shard {shard_id} of collection {id} as it has no shard key");
+                }
+            } else if shard_id < config.shard_count {
+                replicas.insert(shard_id, replica_set);
+            } else if config
+                .resharding_shard_count
+                .is_some_and(|shard_count| shard_id < shard_count)
+            {
+                resharding_replicas.insert(shard_id, replica_set);
+            } else {
+                println!(
+                    "Skipping shard {shard_id} of collection {id} as it's not part of the ring"
+                );
             }
+        }
 
-            let shard = LocalShard::load(&path)?;
-            let shard_id = shard.id;
+        let mut replica_holder = match config.sharding_method {
+            ShardingMethod::Auto => ReplicaHolder::new(replicas, config.ring.clone()),
+            ShardingMethod::Custom => {
+                ReplicaHolder::with_shard_keys(replicas, &config.shard_keys, config.ring.clone())
+            }
+        };
 
-            replicas.insert(
-                shard_id,
-                // ToDo: Load remote shards if any
-                ReplicaSet::new(shard, vec![], id.clone()),
-            );
+        // Resume the resharding that was in progress, migration is idempotent
+        if let Some(shard_count) = config.resharding_shard_count {
+            replica_holder.start_resharding(shard_count, resharding_replicas)?;
         }
 
         Ok(Collection {
             id,
             config,
-            replica_holder: Arc::new(RwLock::new(ReplicaHolder::new(replicas))),
+            replica_holder: Arc::new(RwLock::new(replica_holder)),
             path: path.to_path_buf(),
+            clock,
+            channel_service,
         })
     }
 
+    /// Archives the config and the local shards of the collection to `archive_path`.
+    ///
+    /// The replica holder is write locked while the shards are flushed and archived, so the
+    /// archive holds a consistent state of every shard. Reads and writes of the collection
+    /// wait for the archive meanwhile. Remote replicas are not part of the snapshot.
+    // ToDo: Include the WAL once shards have one
+    pub async fn create_snapshot(&self, archive_path: &Path) -> Result<(), StorageError> {
+        if !self.config.storage.is_persistent() {
+            return Err(StorageError::BadInput(format!(
+                "Collection '{}' is stored in memory, it can't be snapshotted",
+                self.id
+            )));
+        }
+
+        // Writes and transfers hold the read lock
+        let replica_holder = self.replica_holder.write().await;
+
+        for replica_set in replica_holder.shards.values() {
+            replica_set.local.flush()?;
+        }
+
+        let source_dir = self.path.clone();
+        let archive_path = archive_path.to_path_buf();
+        tokio::task::spawn_blocking(move || snapshots::archive_dir(&source_dir, &archive_path))
+            .await
+            .map_err(|e| StorageError::ServiceError(format!("Snapshot task failed: {e}")))??;
+
+        drop(replica_holder);
+        Ok(())
+    }
+
+    /// Starts changing the number of shards of the collection.
+    ///
+    /// New shards are created right away and start receiving writes for the points they own.
+    /// Existing points are moved with [`Self::migrate_resharded_points`] and routing is switched
+    /// for good with [`Self::finish_resharding`].
+    pub async fn start_resharding(&mut self, shard_count: u32) -> Result<(), StorageError> {
+        if self.config.sharding_method == ShardingMethod::Custom {
+            return Err(StorageError::BadInput(
+                "Collections with custom sharding get more shards by adding shard keys".to_string(),
+            ));
+        }
+
+        let mut replica_holder = self.replica_holder.write().await;
+
+        let new_shards = (0..shard_count)
+            .filter(|shard_id| !replica_holder.shards.contains_key(shard_id))
+            .map(|shard_id| {
+                let replica_set = ReplicaSet::new(
+                    LocalShard::init(
+                        self.path.join(shard_id.to_string()),
+                        shard_id,
+                        self.config.storage,
+                    ),
+                    vec![],
+                    self.id.clone(),
+                    self.channel_service.clone(),
+                );
+                (shard_id, replica_set)
+            })
+            .collect::<HashMap<_, _>>();
+
+        // Keep remotes of the new shards in sync with the rest of the collection
+        let peer_ids = replica_holder
+            .shards
+            .values()
+            .flat_map(|replica_set| replica_set.remotes.iter().map(|remote| remote.peer_id))
+            .collect::<BTreeSet<_>>();
+
+        replica_holder.start_resharding(shard_count, new_shards)?;
+
+        for peer_id in peer_ids {
+            replica_holder
+                .add_remote_shards(peer_id, self.id.clone())
+                .await?;
+        }
+
+        self.config.resharding_shard_count = Some(shard_count);
+        self.config.save(&self.path)?;
+
+        Ok(())
+    }
+
+    /// Ids for `count` new shards, after the ones used by the collection so far
+    pub fn next_shard_ids(&self, count: u32) -> Vec<ShardId> {
+        let first = self
+            .config
+            .shard_keys
+            .values()
+            .flatten()
+            .max()
+            .map_or(0, |shard_id| shard_id + 1);
+        (first..first + count).collect()
+    }
+
+    /// Creates the shards of a new key for a collection with custom sharding,
+    /// with remote replicas on `peer_ids`. Returns false if the key already exists.
+    pub async fn create_shard_key(
+        &mut self,
+        shard_key: ShardKey,
+        shard_ids: Vec<ShardId>,
+        peer_ids: Vec<PeerId>,
+    ) -> Result<bool, StorageError> {
+        if let Some(existing) = self.config.shard_keys.get(&shard_key) {
+            let mut sorted_ids = shard_ids.clone();
+            sorted_ids.sort();
+            if *existing == sorted_ids {
+                return Ok(false);
+            }
+            return Err(StorageError::BadInput(format!(
+                "Shard key '{shard_key}' already exists with shards {existing:?}"
+            )));
+        }
+
+        let mut replica_holder = self.replica_holder.write().await;
+
+        // Checked before creating the shard directories, which might hold another key's data
+        if let Some(shard_id) = shard_ids
+            .iter()
+            .find(|shard_id| replica_holder.shards.contains_key(shard_id))
+        {
+            return Err(StorageError::BadInput(format!(
+                "Shard {shard_id} already belongs to another key"
+            )));
+        }
+
+        let new_shards = shard_ids
+            .iter()
+            .map(|shard_id| {
+                let replica_set = ReplicaSet::new(
+                    LocalShard::init(
+                        self.path.join(shard_id.to_string()),
+                        *shard_id,
+                        self.config.storage,
+                    ),
+                    peer_ids.clone(),
+                    self.id.clone(),
+                    self.channel_service.clone(),
+                );
+                (*shard_id, replica_set)
+            })
+            .collect::<HashMap<_, _>>();
+
+        replica_holder.add_shard_key(shard_key.clone(), new_shards)?;
+
+        let mut sorted_ids = shard_ids;
+        sorted_ids.sort();
+        self.config.shard_keys.insert(shard_key, sorted_ids);
+        self.config.save(&self.path)?;
+
+        Ok(true)
+    }
+
+    /// Moves the points of local shards to the shard that owns them in the target ring.
+    ///
+    /// Each node holds its own copy of every shard, so migration only touches local shards.
+    /// Returns the number of moved points.
+    pub async fn migrate_resharded_points(&self) -> CollectionResult<usize> {
+        Self::migrate_replica_points(&self.replica_holder).await
+    }
+
+    /// Same as [`Self::migrate_resharded_points`], for callers that only keep the replica holder
+    /// so they don't block the other collections meanwhile.
+    pub async fn migrate_replica_points(
+        replica_holder: &RwLock<ReplicaHolder>,
+    ) -> CollectionResult<usize> {
+        let shard_ids = {
+            let replica_holder = replica_holder.read().await;
+            if replica_holder.resharding().is_none() {
+                return Ok(0);
+            }
+            replica_holder.shards.keys().copied().collect::<Vec<_>>()
+        };
+
+        let mut moved_points = 0;
+        for shard_id in shard_ids {
+            // Writes are routed with the target ring, so points that have to leave
+            // this shard can't be updated here anymore and a single scan is enough.
+            // The shard is scanned a page at a time so that it's never loaded whole.
+            let mut offset = None;
+            loop {
+                let (page_len, points_to_move) = {
+                    let replica_holder = replica_holder.read().await;
+                    let source = &replica_holder.get_replica_set(shard_id).await?.local;
+
+                    let page = source
+                        .get_points_page(offset.clone(), RESHARDING_BATCH_SIZE)
+                        .await?;
+                    offset = page.last().map(|point| point.id.clone());
+
+                    let mut points_to_move = vec![];
+                    for point in &page {
+                        let target_shard_id = replica_holder.target_shard(&point.id)?;
+                        if target_shard_id != shard_id {
+                            points_to_move.push((target_shard_id, point.clone()));
+                        }
+                    }
+                    (page.len(), points_to_move)
+                };
+
+                if !points_to_move.is_empty() {
+                    moved_points +=
+                        Self::move_points(replica_holder, shard_id, points_to_move).await?;
+                }
+
+                if page_len < RESHARDING_BATCH_SIZE {
+                    break;
+                }
+            }
+        }
+
+        Ok(moved_points)
+    }
+
+    /// Moves a batch of points of the local shard `shard_id` to their local target shards
+    async fn move_points(
+        replica_holder: &RwLock<ReplicaHolder>,
+        shard_id: ShardId,
+        points_to_move: Vec<(ShardId, Point)>,
+    ) -> CollectionResult<usize> {
+        // Block writes while moving a batch so that a newer write to
+        // the target shard doesn't get overwritten by the migrated point.
+        let replica_holder = replica_holder.write().await;
+        let source = &replica_holder.get_replica_set(shard_id).await?.local;
+
+        let moved_points = points_to_move.len();
+        let mut points_by_target: HashMap<ShardId, Vec<Point>> = HashMap::new();
+        for (target_shard_id, point) in points_to_move {
+            points_by_target
+                .entry(target_shard_id)
+                .or_default()
+                .push(point);
+        }
+
+        for (target_shard_id, points) in points_by_target {
+            let target = &replica_holder.get_replica_set(target_shard_id).await?.local;
+            let point_ids = points.iter().map(|p| p.id.clone()).collect::<Vec<_>>();
+
+            // Points written after resharding started are already in the target shard
+            let existing_ids = target
+                .get_points(Some(point_ids.clone()))
+                .await?
+                .into_iter()
+                .map(|p| p.id)
+                .collect::<HashSet<_>>();
+
+            let missing_points = points
+                .into_iter()
+                .filter(|p| !existing_ids.contains(&p.id))
+                .collect::<Vec<_>>();
+
+            if !missing_points.is_empty() {
+                target.upsert_points(missing_points).await?;
+            }
+            source.delete_points(&point_ids)?;
+        }
+
+        Ok(moved_points)
+    }
+
+    /// Completes the migration if needed and switches routing to the target ring.
+    /// Shards that are not part of the new ring are deleted.
+    pub async fn finish_resharding(&mut self) -> CollectionResult<()> {
+        self.migrate_resharded_points().await?;
+
+        let dropped_shards = {
+            let mut replica_holder = self.replica_holder.write().await;
+            let shard_count = replica_holder
+                .resharding()
+                .map(|resharding| resharding.shard_count)
+                .ok_or_else(|| {
+                    StorageError::BadInput("Resharding is not in progress".to_string())
+                })?;
+
+            let dropped_shards = replica_holder.finish_resharding()?;
+
+            self.config.shard_count = shard_count;
+            self.config.resharding_shard_count = None;
+            self.config.save(&self.path)?;
+
+            dropped_shards
+        };
+
+        for replica_set in dropped_shards {
+            replica_set.local.delete().await?;
+        }
+
+        Ok(())
+    }
+
     /// Upserts points into the collection.
     ///
+    /// Points get a new version unless `local_only` is set, in which case this peer is only a
+    /// replica of the write and keeps the versions assigned by the coordinating peer.
+    ///
+    /// Writes that fail on remote replicas are queued in `hints` to be replayed later.
+    ///
     /// This is not cancel safe at the moment.
     pub async fn upsert_points(
         &self,
-        points: Vec<Point>,
+        mut points: Vec<Point>,
+        shard_selector: &ShardSelector,
         local_only: bool,
+        hints: Option<Arc<HintStore>>,
     ) -> CollectionResult<()> {
+        if local_only {
+            if let Some(max_version) = points.iter().map(|point| point.version).max() {
+                self.clock.observe(max_version);
+            }
+        } else {
+            let version = self.clock.tick();
+            for point in points.iter_mut() {
+                point.version = version;
+            }
+        }
+
         let shard_holder = &self.replica_holder.read().await;
 
         let point_ids: Vec<_> = points.iter().map(|point| point.id.clone()).collect();
@@ -150,89 +567,164 @@ impl Collection {
             .map(|point| (point.id.clone(), point))
             .collect();
 
-        // ToDo: Run this operation concurrently for each shard
-        for (shard_id, shard_point_ids) in shard_holder.select_shards(&point_ids)? {
-            let replica_set = shard_holder
-                .shards
-                .get(&shard_id)
-                .ok_or_else(|| StorageError::BadInput(format!("Shard {shard_id} not found")))?;
+        let shard_writes = shard_holder
+            .select_shards(&point_ids, shard_selector)?
+            .into_iter()
+            .map(|(shard_id, shard_point_ids)| {
+                let points = shard_point_ids
+                    .iter()
+                    .filter_map(|id| points_map.get(id).cloned())
+                    .collect::<Vec<_>>();
 
-            let points = shard_point_ids
-                .iter()
-                .filter_map(|id| points_map.get(id).cloned())
-                .collect::<Vec<_>>();
+                self.upsert_shard_points(shard_holder, shard_id, points, local_only, hints.clone())
+            });
 
-            let results = replica_set
-                .execute_cluster_operation(
-                    |shard| {
-                        let points_cloned = points.clone();
-                        async move { shard.upsert_points(points_cloned).await }.boxed()
-                    },
-                    local_only,
-                )
-                .await;
+        try_join_all(shard_writes).await?;
 
-            let total_success = results.iter().filter(|r| r.is_ok()).count();
+        Ok(())
+    }
+
+    async fn upsert_shard_points(
+        &self,
+        shard_holder: &ReplicaHolder,
+        shard_id: ShardId,
+        points: Vec<Point>,
+        local_only: bool,
+        hints: Option<Arc<HintStore>>,
+    ) -> CollectionResult<()> {
+        let replica_set = shard_holder
+            .shards
+            .get(&shard_id)
+            .ok_or_else(|| StorageError::BadInput(format!("Shard {shard_id} not found")))?;
 
-            let (local_result, _remote_results) = results.split_first().unwrap();
-            if let Err(e) = local_result {
+        // ToDo: Both should have collection level config
+        let write_consistency_factor = DEFAULT_CONSISTENCY_FACTOR;
+        let num_replicas = replica_set.num_replicas();
+
+        let min_desired_success = if local_only {
+            1
+        } else {
+            num_replicas.min(write_consistency_factor)
+        };
+
+        let on_remote_failure = hints.map(|hints| {
+            let collection_name = self.id.clone();
+            let points = points.clone();
+
+            Arc::new(move |peer_id, _: &CollectionError| {
+                let hint = Hint::new(collection_name.clone(), shard_id, points.clone());
+                match hints.store(peer_id, &hint) {
+                    Ok(true) => {}
+                    Ok(false) => println!(
+                        "Dropping hint for shard {shard_id} on peer {peer_id}: too many pending hints"
+                    ),
+                    Err(e) => eprintln!("Failed to store hint for peer {peer_id}: {e}"),
+                }
+            }) as OnRemoteFailure
+        });
+
+        let results = replica_set
+            .execute_cluster_operation(
+                |shard| {
+                    let points_cloned = points.clone();
+                    async move { shard.upsert_points(points_cloned).await }.boxed()
+                },
+                min_desired_success,
+                local_only,
+                on_remote_failure,
+            )
+            .await;
+
+        match results
+            .iter()
+            .find(|(replica_id, _)| *replica_id == ReplicaId::Local)
+        {
+            Some((_, Ok(()))) => {}
+            Some((_, Err(e))) => {
                 return Err(CollectionError::ServiceError(format!(
                     "Failed to upsert points in local shard {shard_id}: {e}"
                 )));
             }
-
-            // ToDo: Both should have collection level config
-            let write_consistency_factor = DEFAULT_CONSISTENCY_FACTOR;
-            let num_replicas = replica_set.num_replicas();
-
-            let min_desired_success = if local_only {
-                1
-            } else {
-                num_replicas.min(write_consistency_factor)
-            };
-
-            if total_success < min_desired_success {
+            None => {
                 return Err(CollectionError::ServiceError(format!(
-                    "Failed to upsert points in shard {shard_id}: only {total_success} out of {num_replicas} replicas succeeded"
+                    "Local shard {shard_id} didn't answer"
                 )));
             }
         }
 
+        let total_success = results.iter().filter(|(_, r)| r.is_ok()).count();
+        if total_success < min_desired_success {
+            return Err(CollectionError::ServiceError(format!(
+                "Failed to upsert points in shard {shard_id}: only {total_success} out of {num_replicas} replicas succeeded"
+            )));
+        }
+
         Ok(())
     }
 
+    /// Syncs every local shard with its remote replicas, see [`ReplicaSet::sync_replicas`].
+    /// The replica holder isn't locked while replicas are called.
+    pub async fn sync_replicas(
+        replica_holder: &RwLock<ReplicaHolder>,
+    ) -> CollectionResult<SyncStats> {
+        let mut total = SyncStats::default();
+        let (replica_sets, ring) = {
+            let replica_holder = replica_holder.read().await;
+            // Replicas may legitimately differ while points are moved between shards
+            if replica_holder.resharding().is_some() {
+                return Ok(total);
+            }
+            (
+                replica_holder.shards.values().cloned().collect::<Vec<_>>(),
+                replica_holder.placement_ring(),
+            )
+        };
+
+        for replica_set in replica_sets {
+            match replica_set.sync_replicas(ring.as_ref()).await {
+                Ok(stats) => {
+                    total.differing_ranges += stats.differing_ranges;
+                    total.sent_points += stats.sent_points;
+                    total.received_points += stats.received_points;
+                }
+                Err(e) => eprintln!("Failed to sync shard {}: {e}", replica_set.local.id),
+            }
+        }
+
+        Ok(total)
+    }
+
     pub async fn get_points(
         &self,
         ids: Option<Vec<PointId>>,
-        shard_id: Option<ShardId>,
+        shard_selector: &ShardSelector,
+        read_consistency: ReadConsistency,
         local_only: bool,
     ) -> CollectionResult<Vec<Point>> {
         let replica_holder = self.replica_holder.read().await;
 
+        // Replicas of a shard may legitimately differ while points are moved between shards
+        let read_repair = replica_holder.resharding().is_none();
+
         let Some(ids) = ids else {
             // If no ids are provided, return all points from all shards
             let mut all_points = BTreeMap::new();
-            for (current_shard_id, replica_set) in replica_holder.shards.iter() {
-                if let Some(desired_shard) = shard_id {
-                    if *current_shard_id != desired_shard {
-                        continue; // Skip shards that are not the desired shard
-                    }
-                }
+            for current_shard_id in replica_holder.selected_shard_ids(shard_selector)? {
+                let replica_set = replica_holder.get_replica_set(current_shard_id).await?;
 
-                let replica_results = replica_set
-                    .execute_cluster_operation(
-                        |shard| {
-                            let ids_cloned = ids.clone();
-                            async move { shard.get_points(ids_cloned).await }.boxed()
-                        },
-                        local_only,
-                    )
-                    .await
-                    .into_iter()
-                    .collect::<Result<Vec<_>, _>>()?;
+                let shard_points = replica_set
+                    .get_points(None, read_consistency, local_only, read_repair)
+                    .await?;
+
+                for point in shard_points {
+                    // While resharding, the copy in the target shard is the latest one
+                    if replica_holder.resharding().is_some()
+                        && replica_holder.target_shard(&point.id)? == current_shard_id
+                    {
+                        all_points.insert(point.id.clone(), point);
+                        continue;
+                    }
 
-                for point in replica_results.into_iter().flatten() {
-                    // We only insert if the point is not already present so that local shard takes precedence
                     if let Entry::Vacant(e) = all_points.entry(point.id.clone()) {
                         e.insert(point);
                     }
@@ -241,20 +733,41 @@ impl Collection {
             return Ok(all_points.into_values().collect());
         };
 
-        if let Some(shard_id) = shard_id {
-            let replica_set = replica_holder.get_replica_set(shard_id).await?;
-            Ok(replica_set.local.get_points(Some(ids)).await?)
+        if let ShardSelector::Shard(shard_id) = shard_selector {
+            let replica_set = replica_holder.get_replica_set(*shard_id).await?;
+            replica_set
+                .get_points(Some(ids), read_consistency, local_only, read_repair)
+                .await
         } else {
-            let mut points = vec![];
+            let mut points = HashMap::new();
 
-            for (shard_id, shard_point_ids) in replica_holder.select_shards(&ids)? {
+            // While resharding, a point may still be in its previous shard
+            for (shard_id, shard_point_ids) in
+                replica_holder.select_read_shards(&ids, shard_selector)?
+            {
                 let replica_set = replica_holder.get_replica_set(shard_id).await?;
 
-                let collected_points = replica_set.local.get_points(Some(shard_point_ids)).await?;
-                points.extend(collected_points);
+                let collected_points = replica_set
+                    .get_points(
+                        Some(shard_point_ids),
+                        read_consistency,
+                        local_only,
+                        read_repair,
+                    )
+                    .await?;
+
+                for point in collected_points {
+                    if replica_holder.resharding().is_some()
+                        && replica_holder.target_shard(&point.id)? == shard_id
+                    {
+                        points.insert(point.id.clone(), point);
+                    } else {
+                        points.entry(point.id.clone()).or_insert(point);
+                    }
+                }
             }
 
-            Ok(points)
+            Ok(points.into_values().collect())
         }
     }
 }
@@ -262,9 +775,93 @@ impl Collection {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct CollectionConfig {
     pub params: String,
+    #[serde(default = "default_shard_count")]
+    pub shard_count: u32,
+    /// Target shard count while resharding is in progress
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resharding_shard_count: Option<u32>,
+    #[serde(default)]
+    pub sharding_method: ShardingMethod,
+    /// Shards of each key with custom sharding
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub shard_keys: BTreeMap<ShardKey, Vec<ShardId>>,
+    /// Collections created before virtual nodes existed keep their single node per shard
+    #[serde(default = "RingConfig::legacy")]
+    pub ring: RingConfig,
+    #[serde(default)]
+    pub storage: StorageType,
+}
+
+fn default_shard_count() -> u32 {
+    DEFAULT_SHARD_COUNT
 }
 
 impl CollectionConfig {
+    pub fn new(
+        params: String,
+        shard_count: Option<u32>,
+        sharding_method: ShardingMethod,
+        ring: RingConfig,
+        storage: StorageType,
+    ) -> Self {
+        CollectionConfig {
+            params,
+            shard_count: shard_count.unwrap_or(DEFAULT_SHARD_COUNT),
+            resharding_shard_count: None,
+            sharding_method,
+            shard_keys: BTreeMap::new(),
+            ring,
+            storage,
+        }
+    }
+
+    /// Fails if an existing collection wasn't created with the same parameters as `requested`,
+    /// e.g. when the collection is created again on a peer that already has it.
+    pub fn check_matches(&self, requested: &CollectionConfig) -> Result<(), StorageError> {
+        let mismatch = if self.params != requested.params {
+            "params"
+        } else if self.sharding_method != requested.sharding_method {
+            "sharding method"
+        } else if self.shard_count != requested.shard_count {
+            "shard count"
+        } else if self.ring != requested.ring {
+            "ring"
+        } else if self.storage != requested.storage {
+            "storage"
+        } else {
+            return Ok(());
+        };
+
+        Err(StorageError::BadInput(format!(
+            "Collection already exists with a different {mismatch}"
+        )))
+    }
+
+    /// Shards of the collection, including the ones being added by resharding
+    pub fn shard_ids(&self) -> Vec<ShardId> {
+        match self.sharding_method {
+            ShardingMethod::Auto => {
+                let shard_count = self
+                    .resharding_shard_count
+                    .map_or(self.shard_count, |target| target.max(self.shard_count));
+                (0..shard_count).collect()
+            }
+            ShardingMethod::Custom => self.shard_keys.values().flatten().copied().collect(),
+        }
+    }
+
+    pub fn load(collection_dir: &Path) -> Result<Self, StorageError> {
+        let config_path = collection_dir.join(COLLECTION_CONFIG_FILE);
+        let config_file = std::fs::File::open(&config_path).map_err(|e| {
+            StorageError::ServiceError(format!("Failed to open collection config file: {e}"))
+        })?;
+
+        let config_file_buf = std::io::BufReader::new(&config_file);
+        serde_json::from_reader(config_file_buf).map_err(|e| {
+            StorageError::BadInput(format!("Failed to parse collection config JSON: {e}"))
+        })
+    }
+
     pub fn save(&self, collection_dir: &Path) -> Result<(), StorageError> {
         let config_path = collection_dir.join(COLLECTION_CONFIG_FILE);
         let serde_json_bytes = serde_json::to_vec(self).map_err(|e| {
@@ -291,9 +888,19 @@ impl CollectionConfig {
 #[derive(Serialize)]
 pub struct CollectionInfo {
     pub id: CollectionName,
+    /// False for collections stored in memory, their points are lost when the peer restarts
+    pub persistent: bool,
     pub config: CollectionConfig,
     pub shard_count: usize,
     pub segment_count: usize,
+    pub read_repairs: ReadRepairInfo,
+}
+
+/// Points pushed to stale replicas since the collection was loaded
+#[derive(Serialize)]
+pub struct ReadRepairInfo {
+    pub repaired_points: u64,
+    pub failed_points: u64,
 }
 
 impl CollectionInfo {
@@ -301,6 +908,7 @@ impl CollectionInfo {
         let shard_holder = collection.replica_holder.read().await;
         CollectionInfo {
             id: collection.id.clone(),
+            persistent: collection.config.storage.is_persistent(),
             config: collection.config.clone(),
             shard_count: shard_holder.shards.len(),
             segment_count: shard_holder
@@ -308,6 +916,353 @@ impl CollectionInfo {
                 .values()
                 .map(|shard| shard.local.segments.len())
                 .sum(),
+            read_repairs: ReadRepairInfo {
+                repaired_points: shard_holder
+                    .shards
+                    .values()
+                    .map(|shard| shard.read_repairs.repaired_points.load(Ordering::Relaxed))
+                    .sum(),
+                failed_points: shard_holder
+                    .shards
+                    .values()
+                    .map(|shard| shard.read_repairs.failed_points.load(Ordering::Relaxed))
+                    .sum(),
+            },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_config_mismatch() {
+        let config = |shard_count, storage| {
+            CollectionConfig::new(
+                "...".to_string(),
+                Some(shard_count),
+                ShardingMethod::Auto,
+                RingConfig::default(),
+                storage,
+            )
+        };
+
+        let existing = config(2, StorageType::Sled);
+        assert!(existing
+            .check_matches(&config(2, StorageType::Sled))
+            .is_ok());
+        assert!(existing
+            .check_matches(&config(3, StorageType::Sled))
+            .is_err());
+        assert!(existing
+            .check_matches(&config(2, StorageType::Log))
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resharding() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let config = CollectionConfig {
+            params: "...".to_string(),
+            shard_count: 2,
+            resharding_shard_count: None,
+            sharding_method: ShardingMethod::Auto,
+            shard_keys: BTreeMap::new(),
+            ring: RingConfig::default(),
+            storage: StorageType::Sled,
+        };
+        let mut collection = Collection::init(
+            "c1".to_string(),
+            config,
+            tmp_dir.path(),
+            ChannelService::default(),
+        )
+        .await
+        .unwrap();
+
+        // Shards span several pages of the migration
+        let points = (0..2 * RESHARDING_BATCH_SIZE as u64 + 500)
+            .map(|i| Point {
+                id: PointId::Id(i),
+                payload: json!({ "i": i }),
+                version: 0,
+            })
+            .collect::<Vec<_>>();
+        let point_ids = points.iter().map(|p| p.id.clone()).collect::<Vec<_>>();
+        collection
+            .upsert_points(points, &ShardSelector::Auto, true, None)
+            .await
+            .unwrap();
+
+        for shard_count in [4, 1] {
+            collection.start_resharding(shard_count).await.unwrap();
+
+            // Points written during resharding go to the new owner right away
+            let updated_point = Point {
+                id: PointId::Id(0),
+                payload: json!({ "i": shard_count }),
+                version: 0,
+            };
+            collection
+                .upsert_points(vec![updated_point], &ShardSelector::Auto, true, None)
+                .await
+                .unwrap();
+
+            // Reads are served from both the old and the new owners before migration
+            let found = collection
+                .get_points(
+                    Some(point_ids.clone()),
+                    &ShardSelector::Auto,
+                    ReadConsistency::One,
+                    true,
+                )
+                .await
+                .unwrap();
+            assert_eq!(found.len(), point_ids.len());
+
+            collection.migrate_resharded_points().await.unwrap();
+            collection.finish_resharding().await.unwrap();
+
+            let replica_holder = collection.replica_holder.read().await;
+            assert_eq!(replica_holder.shards.len(), shard_count as usize);
+            for (shard_id, replica_set) in replica_holder.shards.iter() {
+                for point in replica_set.local.get_points(None).await.unwrap() {
+                    assert_eq!(replica_holder.target_shard(&point.id).unwrap(), *shard_id);
+                }
+            }
+            drop(replica_holder);
+
+            let found = collection
+                .get_points(
+                    Some(vec![PointId::Id(0)]),
+                    &ShardSelector::Auto,
+                    ReadConsistency::One,
+                    true,
+                )
+                .await
+                .unwrap();
+            assert_eq!(found[0].payload, json!({ "i": shard_count }));
+
+            let all_points = collection
+                .get_points(None, &ShardSelector::Auto, ReadConsistency::One, true)
+                .await
+                .unwrap();
+            assert_eq!(all_points.len(), point_ids.len());
+        }
+
+        assert_eq!(collection.config.shard_count, 1);
+        assert!(!tmp_dir.path().join("3").exists());
+    }
+
+    #[tokio::test]
+    async fn test_shard_keys() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let config = CollectionConfig::new(
+            "...".to_string(),
+            None,
+            ShardingMethod::Custom,
+            RingConfig::default(),
+            StorageType::Memory,
+        );
+        let mut collection = Collection::init(
+            "c1".to_string(),
+            config,
+            tmp_dir.path(),
+            ChannelService::default(),
+        )
+        .await
+        .unwrap();
+
+        let shard_ids = collection.next_shard_ids(2);
+        assert_eq!(shard_ids, vec![0, 1]);
+        assert!(collection
+            .create_shard_key("k1".to_string(), shard_ids, vec![])
+            .await
+            .unwrap());
+        assert_eq!(collection.next_shard_ids(1), vec![2]);
+
+        let point = Point {
+            id: PointId::Id(1),
+            payload: json!({}),
+            version: 0,
+        };
+        collection
+            .upsert_points(
+                vec![point.clone()],
+                &ShardSelector::Key("k1".to_string()),
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        // Reported as a bad request rather than an internal error
+        let result = collection
+            .upsert_points(
+                vec![point],
+                &ShardSelector::Key("k2".to_string()),
+                false,
+                None,
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(CollectionError::StorageError(StorageError::BadInput(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_wait_released() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let config = CollectionConfig::new(
+            "...".to_string(),
+            Some(2),
+            ShardingMethod::Auto,
+            RingConfig::default(),
+            StorageType::Sled,
+        );
+        let collection = Collection::init(
+            "c1".to_string(),
+            config,
+            tmp_dir.path(),
+            ChannelService::default(),
+        )
+        .await
+        .unwrap();
+
+        // Same as a background sync holding a replica set
+        let replica_set = collection.replica_holder.read().await.shards[&0].clone();
+        let started = tokio::time::Instant::now();
+        let background = tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            drop(replica_set);
+        });
+
+        collection.wait_released().await.unwrap();
+        assert!(started.elapsed() >= std::time::Duration::from_millis(100));
+        background.await.unwrap();
+
+        // The files are closed, so the shard can be opened again
+        drop(collection);
+        let collection =
+            Collection::load("c1".to_string(), tmp_dir.path(), ChannelService::default()).unwrap();
+        assert_eq!(collection.replica_holder.read().await.shards.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let config = CollectionConfig {
+            params: "...".to_string(),
+            shard_count: 2,
+            resharding_shard_count: None,
+            sharding_method: ShardingMethod::Auto,
+            shard_keys: BTreeMap::new(),
+            ring: RingConfig::default(),
+            storage: StorageType::Memory,
+        };
+        let collection = Collection::init(
+            "c1".to_string(),
+            config,
+            tmp_dir.path(),
+            ChannelService::default(),
+        )
+        .await
+        .unwrap();
+
+        let points = (0..10)
+            .map(|i| Point {
+                id: PointId::Id(i),
+                payload: json!({ "i": i }),
+                version: 0,
+            })
+            .collect::<Vec<_>>();
+        collection
+            .upsert_points(points, &ShardSelector::Auto, true, None)
+            .await
+            .unwrap();
+        let found = collection
+            .get_points(None, &ShardSelector::Auto, ReadConsistency::One, true)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 10);
+        assert!(!CollectionInfo::from(&collection).await.persistent);
+
+        // Only the config is written to disk, so there is nothing to snapshot
+        let entries = std::fs::read_dir(tmp_dir.path()).unwrap().count();
+        assert_eq!(entries, 1);
+        let archive_path = tmp_dir.path().join("c1.snapshot");
+        assert!(collection.create_snapshot(&archive_path).await.is_err());
+
+        // Shards start empty once loaded again
+        drop(collection);
+        let collection =
+            Collection::load("c1".to_string(), tmp_dir.path(), ChannelService::default()).unwrap();
+        assert_eq!(collection.replica_holder.read().await.shards.len(), 2);
+        let found = collection
+            .get_points(None, &ShardSelector::Auto, ReadConsistency::One, true)
+            .await
+            .unwrap();
+        assert!(found.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_clock_after_restart() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let config = CollectionConfig::new(
+            "...".to_string(),
+            Some(2),
+            ShardingMethod::Auto,
+            RingConfig::default(),
+            StorageType::Log,
+        );
+        let collection = Collection::init(
+            "c1".to_string(),
+            config,
+            tmp_dir.path(),
+            ChannelService::default(),
+        )
+        .await
+        .unwrap();
+
+        // Written by a peer whose wall clock is ahead of this one
+        let future_version = HybridLogicalClock::default().tick() + (3_600_000 << 16);
+        let point = Point {
+            id: PointId::Id(1),
+            payload: json!({ "i": 1 }),
+            version: future_version,
+        };
+        collection
+            .upsert_points(vec![point], &ShardSelector::Auto, true, None)
+            .await
+            .unwrap();
+
+        drop(collection);
+        let collection =
+            Collection::load("c1".to_string(), tmp_dir.path(), ChannelService::default()).unwrap();
+
+        // A new write isn't dropped as older than the stored point
+        let point = Point {
+            id: PointId::Id(1),
+            payload: json!({ "i": 2 }),
+            version: 0,
+        };
+        collection
+            .upsert_points(vec![point], &ShardSelector::Auto, false, None)
+            .await
+            .unwrap();
+        let found = collection
+            .get_points(
+                Some(vec![PointId::Id(1)]),
+                &ShardSelector::Auto,
+                ReadConsistency::One,
+                true,
+            )
+            .await
+            .unwrap();
+        assert_eq!(found[0].payload, json!({ "i": 2 }));
+        assert!(found[0].version > future_version);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{storage::error::StorageError, types::PeerId};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex,
+};
+
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DrainState {
+    /// Shards of the peer are being copied to the other replicas
+    Transferring,
+    /// Other peers are dropping the replicas of the peer
+    Detaching,
+    /// The peer holds nothing anymore and can be removed
+    Drained,
+    Failed,
+}
+
+/// Progress of a drain coordinated by this peer, as shown by the API
+#[derive(Serialize, Clone, Debug)]
+pub struct DrainProgress {
+    pub state: DrainState,
+    pub total_shards: usize,
+    pub transferred_shards: usize,
+    pub transferred_points: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct DrainsInner {
+    /// Peers that must not get new replicas, whichever peer coordinates their drain
+    draining: BTreeSet<PeerId>,
+    /// Drains coordinated by this peer
+    progress: BTreeMap<PeerId, DrainProgress>,
+}
+
+/// Peers being emptied before maintenance, see `POST /cluster/peer/{id}/drain`.
+// ToDo: Persist draining peers, they are eligible for placements again after a restart
+#[derive(Default)]
+pub struct Drains {
+    inner: Mutex<DrainsInner>,
+}
+
+impl Drains {
+    /// Excludes the peer from new placements
+    pub fn mark(&self, peer_id: PeerId) {
+        let mut inner = self.inner.lock().expect("Drains lock is poisoned");
+        inner.draining.insert(peer_id);
+    }
+
+    pub fn is_draining(&self, peer_id: PeerId) -> bool {
+        let inner = self.inner.lock().expect("Drains lock is poisoned");
+        inner.draining.contains(&peer_id)
+    }
+
+    pub fn draining_peers(&self) -> Vec<PeerId> {
+        let inner = self.inner.lock().expect("Drains lock is poisoned");
+        inner.draining.iter().copied().collect()
+    }
+
+    /// Starts tracking a drain coordinated by this peer. A failed or finished drain can be restarted.
+    pub fn start(
+        &self,
+        peer_id: PeerId,
+        total_shards: usize,
+    ) -> Result<DrainProgress, StorageError> {
+        let mut inner = self.inner.lock().expect("Drains lock is poisoned");
+
+        if let Some(progress) = inner.progress.get(&peer_id) {
+            if matches!(
+                progress.state,
+                DrainState::Transferring | DrainState::Detaching
+            ) {
+                return Err(StorageError::BadInput(format!(
+                    "Peer {peer_id} is already being drained"
+                )));
+            }
+        }
+
+        let progress = DrainProgress {
+            state: DrainState::Transferring,
+            total_shards,
+            transferred_shards: 0,
+            transferred_points: 0,
+            error: None,
+        };
+        inner.draining.insert(peer_id);
+        inner.progress.insert(peer_id, progress.clone());
+        Ok(progress)
+    }
+
+    pub fn update(&self, peer_id: PeerId, f: impl FnOnce(&mut DrainProgress)) {
+        let mut inner = self.inner.lock().expect("Drains lock is poisoned");
+        if let Some(progress) = inner.progress.get_mut(&peer_id) {
+            f(progress);
+        }
+    }
+
+    pub fn progress(&self, peer_id: PeerId) -> Option<DrainProgress> {
+        let inner = self.inner.lock().expect("Drains lock is poisoned");
+        inner.progress.get(&peer_id).cloned()
+    }
+
+    /// Called once the peer is removed from the cluster
+    pub fn forget(&self, peer_id: PeerId) {
+        let mut inner = self.inner.lock().expect("Drains lock is poisoned");
+        inner.draining.remove(&peer_id);
+        inner.progress.remove(&peer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drain_progress() {
+        let drains = Drains::default();
+        assert!(!drains.is_draining(101));
+        assert!(drains.progress(101).is_none());
+
+        // Peers marked by another coordinator have no progress here
+        drains.mark(102);
+        assert!(drains.is_draining(102));
+        assert!(drains.progress(102).is_none());
+
+        drains.start(101, 4).unwrap();
+        assert!(drains.is_draining(101));
+        assert_eq!(drains.draining_peers(), vec![101, 102]);
+        assert!(drains.start(101, 4).is_err());
+
+        drains.update(101, |progress| {
+            progress.transferred_shards += 1;
+            progress.state = DrainState::Failed;
+        });
+        let progress = drains.progress(101).unwrap();
+        assert_eq!(progress.transferred_shards, 1);
+        assert_eq!(progress.state, DrainState::Failed);
+
+        // Failed drains can be retried from scratch
+        let progress = drains.start(101, 4).unwrap();
+        assert_eq!(progress.transferred_shards, 0);
+
+        drains.forget(101);
+        assert!(!drains.is_draining(101));
+        assert!(drains.progress(101).is_none());
+    }
+}
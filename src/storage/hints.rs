@@ -0,0 +1,251 @@
+use crate::{
+    storage::{collection::CollectionName, error::StorageError, segment::Point},
+    types::{PeerId, ShardId},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+pub const HINTS_DIR: &str = "hints";
+
+/// Hints of a peer are dropped once they take more space than this,
+/// anti-entropy is responsible for syncing the peer after that.
+pub const MAX_HINTS_BYTES_PER_PEER: u64 = 64 * 1024 * 1024;
+
+/// Hints older than this are not replayed anymore
+pub const MAX_HINT_AGE: Duration = Duration::from_secs(3 * 60 * 60);
+
+/// Write that couldn't be applied on a remote replica, kept by the coordinating peer
+/// until the replica is reachable again.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hint {
+    pub collection_name: CollectionName,
+    pub shard_id: ShardId,
+    pub points: Vec<Point>,
+    /// Milliseconds since the unix epoch
+    pub created_at: u64,
+}
+
+impl Hint {
+    pub fn new(collection_name: CollectionName, shard_id: ShardId, points: Vec<Point>) -> Self {
+        Hint {
+            collection_name,
+            shard_id,
+            points,
+            created_at: now_millis(),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        now_millis().saturating_sub(self.created_at) > MAX_HINT_AGE.as_millis() as u64
+    }
+}
+
+/// Pending hints of a peer, as shown by the API
+#[derive(Serialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct PeerHints {
+    pub pending_hints: usize,
+    pub pending_points: usize,
+    pub size_bytes: u64,
+    /// Milliseconds since the unix epoch
+    pub oldest_hint: Option<u64>,
+}
+
+/// Durable queue of hints per peer, keyed by peer id followed by an increasing sequence number
+/// so that the hints of a peer are replayed in the order they were written.
+pub struct HintStore {
+    db: sled::Db,
+    /// Bytes stored per peer, used to enforce [`MAX_HINTS_BYTES_PER_PEER`] without scanning
+    sizes: Mutex<HashMap<PeerId, u64>>,
+}
+
+impl HintStore {
+    pub fn open(path: &Path) -> Result<Self, StorageError> {
+        let db = sled::open(path).map_err(|e| {
+            StorageError::ServiceError(format!("Failed to open hints database: {e}"))
+        })?;
+
+        let mut sizes = HashMap::new();
+        for result in db.iter() {
+            let (key, value) = result.map_err(|e| {
+                StorageError::ServiceError(format!("Failed to iterate over hints db: {e}"))
+            })?;
+            *sizes.entry(Self::peer_of(&key)).or_default() += value.len() as u64;
+        }
+
+        Ok(HintStore {
+            db,
+            sizes: Mutex::new(sizes),
+        })
+    }
+
+    fn key(peer_id: PeerId, seq: u64) -> Vec<u8> {
+        let mut key = peer_id.to_be_bytes().to_vec();
+        key.extend_from_slice(&seq.to_be_bytes());
+        key
+    }
+
+    fn peer_of(key: &[u8]) -> PeerId {
+        let mut peer_id = [0u8; 8];
+        peer_id.copy_from_slice(&key[..8]);
+        PeerId::from_be_bytes(peer_id)
+    }
+
+    /// Queues a hint for the peer. Returns false if the hint was dropped
+    /// because the peer already has too many pending hints.
+    pub fn store(&self, peer_id: PeerId, hint: &Hint) -> Result<bool, StorageError> {
+        let value = serde_json::to_vec(hint)
+            .map_err(|e| StorageError::ServiceError(format!("Failed to serialize hint: {e}")))?;
+
+        let mut sizes = self.sizes.lock().expect("Hint sizes lock is poisoned");
+        let size = sizes.entry(peer_id).or_default();
+        if *size + value.len() as u64 > MAX_HINTS_BYTES_PER_PEER {
+            return Ok(false);
+        }
+
+        let seq = self
+            .db
+            .generate_id()
+            .map_err(|e| StorageError::ServiceError(format!("Failed to generate hint id: {e}")))?;
+
+        self.db
+            .insert(Self::key(peer_id, seq), value.as_slice())
+            .map_err(|e| StorageError::ServiceError(format!("Failed to store hint: {e}")))?;
+        self.db
+            .flush()
+            .map_err(|e| StorageError::ServiceError(format!("Failed to flush hints db: {e}")))?;
+
+        *size += value.len() as u64;
+        Ok(true)
+    }
+
+    /// Pending hints of the peer in the order they were stored, along with their keys.
+    pub fn hints(&self, peer_id: PeerId) -> Result<Vec<(Vec<u8>, Hint)>, StorageError> {
+        let mut hints = vec![];
+        for result in self.db.scan_prefix(peer_id.to_be_bytes()) {
+            let (key, value) = result.map_err(|e| {
+                StorageError::ServiceError(format!("Failed to iterate over hints db: {e}"))
+            })?;
+            let hint: Hint = serde_json::from_slice(&value).map_err(|e| {
+                StorageError::ServiceError(format!("Failed to deserialize hint: {e}"))
+            })?;
+            hints.push((key.to_vec(), hint));
+        }
+        Ok(hints)
+    }
+
+    /// Removes a hint once it's replayed or expired.
+    pub fn remove(&self, key: &[u8]) -> Result<(), StorageError> {
+        let removed = self
+            .db
+            .remove(key)
+            .map_err(|e| StorageError::ServiceError(format!("Failed to remove hint: {e}")))?;
+
+        if let Some(value) = removed {
+            let mut sizes = self.sizes.lock().expect("Hint sizes lock is poisoned");
+            if let Some(size) = sizes.get_mut(&Self::peer_of(key)) {
+                *size = size.saturating_sub(value.len() as u64);
+            }
+        }
+        Ok(())
+    }
+
+    fn size(&self, peer_id: PeerId) -> u64 {
+        let sizes = self.sizes.lock().expect("Hint sizes lock is poisoned");
+        sizes.get(&peer_id).copied().unwrap_or_default()
+    }
+
+    /// Peers that have pending hints
+    pub fn peers(&self) -> Vec<PeerId> {
+        let sizes = self.sizes.lock().expect("Hint sizes lock is poisoned");
+        let mut peers = sizes
+            .iter()
+            .filter(|(_, size)| **size > 0)
+            .map(|(peer_id, _)| *peer_id)
+            .collect::<Vec<_>>();
+        peers.sort();
+        peers
+    }
+
+    pub fn info(&self) -> Result<HashMap<PeerId, PeerHints>, StorageError> {
+        let mut info = HashMap::new();
+        for peer_id in self.peers() {
+            let mut peer_hints = PeerHints {
+                size_bytes: self.size(peer_id),
+                ..Default::default()
+            };
+            for (_, hint) in self.hints(peer_id)? {
+                peer_hints.pending_hints += 1;
+                peer_hints.pending_points += hint.points.len();
+                peer_hints.oldest_hint = Some(
+                    peer_hints
+                        .oldest_hint
+                        .map_or(hint.created_at, |oldest| oldest.min(hint.created_at)),
+                );
+            }
+            info.insert(peer_id, peer_hints);
+        }
+        Ok(info)
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::segment::PointId;
+    use serde_json::json;
+
+    #[test]
+    fn test_hint_store() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+
+        let point = |id| Point {
+            id: PointId::Id(id),
+            payload: json!({}),
+            version: 1,
+        };
+
+        {
+            let store = HintStore::open(tmp_dir.path()).unwrap();
+            for id in 0..3 {
+                let hint = Hint::new("c1".to_string(), 0, vec![point(id)]);
+                assert!(store.store(101, &hint).unwrap());
+            }
+            assert!(store
+                .store(102, &Hint::new("c1".to_string(), 1, vec![point(9)]))
+                .unwrap());
+        }
+
+        // Hints survive a restart and are kept in order
+        let store = HintStore::open(tmp_dir.path()).unwrap();
+        assert_eq!(store.peers(), vec![101, 102]);
+
+        let hints = store.hints(101).unwrap();
+        let ids = hints
+            .iter()
+            .map(|(_, hint)| hint.points[0].id.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![PointId::Id(0), PointId::Id(1), PointId::Id(2)]);
+
+        for (key, _) in hints {
+            store.remove(&key).unwrap();
+        }
+        assert_eq!(store.peers(), vec![102]);
+        assert_eq!(store.info().unwrap()[&102].pending_points, 1);
+
+        let mut expired = Hint::new("c1".to_string(), 0, vec![]);
+        expired.created_at -= MAX_HINT_AGE.as_millis() as u64 + 1;
+        assert!(expired.is_expired());
+    }
+}
@@ -1,5 +1,13 @@
+pub mod backup;
+pub mod clock;
 pub mod collection;
+pub mod drain;
 pub mod error;
+pub mod hints;
+pub mod point_encoding;
+pub mod rebalance;
 pub mod replicas;
 pub mod segment;
+pub mod segment_storage;
+pub mod snapshots;
 pub mod toc;
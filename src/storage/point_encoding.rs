@@ -0,0 +1,163 @@
+use crate::storage::{
+    error::StorageError,
+    segment::{Point, PointId},
+};
+
+/// First byte of the records of the current format. Records written before are JSON objects,
+/// so they start with `{`.
+pub const FORMAT_V1: u8 = 1;
+
+/// Format (1) + version (8)
+const HEADER_LEN: usize = 9;
+
+/// Legacy JSON record, its `id` is left out: the key of the record is the id.
+#[derive(serde::Deserialize)]
+struct LegacyPoint {
+    payload: serde_json::Value,
+    #[serde(default)]
+    version: u64,
+}
+
+/// Only reads the version of a legacy JSON record without parsing its payload.
+#[derive(serde::Deserialize)]
+struct LegacyVersion {
+    #[serde(default)]
+    version: u64,
+}
+
+/// Encodes a stored point as `[format][version][payload]`, with the version in little endian
+/// and the payload in MessagePack. The id isn't stored, it's already the key.
+pub fn encode(point: &Point) -> Result<Vec<u8>, StorageError> {
+    let mut value = Vec::with_capacity(HEADER_LEN + 64);
+    value.push(FORMAT_V1);
+    value.extend_from_slice(&point.version.to_le_bytes());
+    rmp_serde::encode::write(&mut value, &point.payload)
+        .map_err(|e| StorageError::ServiceError(format!("Failed to serialize point: {e}")))?;
+    Ok(value)
+}
+
+/// Decodes a point stored under `id`, in the current format or as legacy JSON
+pub fn decode(id: PointId, value: &[u8]) -> Result<Point, StorageError> {
+    if is_legacy(value) {
+        let legacy: LegacyPoint = serde_json::from_slice(value).map_err(|e| {
+            StorageError::ServiceError(format!("Failed to deserialize legacy point: {e}"))
+        })?;
+        return Ok(Point {
+            id,
+            payload: legacy.payload,
+            version: legacy.version,
+        });
+    }
+
+    let (version, payload) = split_header(value)?;
+    let payload = rmp_serde::from_slice(payload)
+        .map_err(|e| StorageError::ServiceError(format!("Failed to deserialize point: {e}")))?;
+    Ok(Point {
+        id,
+        payload,
+        version,
+    })
+}
+
+/// Version of a stored point without decoding its payload, 0 if the record is malformed
+pub fn stored_version(value: &[u8]) -> u64 {
+    if is_legacy(value) {
+        return serde_json::from_slice::<LegacyVersion>(value)
+            .map(|stored| stored.version)
+            .unwrap_or_default();
+    }
+    split_header(value)
+        .map(|(version, _)| version)
+        .unwrap_or_default()
+}
+
+/// Whether the record was written as JSON, before the binary encoding
+pub fn is_legacy(value: &[u8]) -> bool {
+    value.first() == Some(&b'{')
+}
+
+fn split_header(value: &[u8]) -> Result<(u64, &[u8]), StorageError> {
+    match value.first() {
+        Some(&FORMAT_V1) if value.len() >= HEADER_LEN => {
+            let version = u64::from_le_bytes(value[1..HEADER_LEN].try_into().unwrap());
+            Ok((version, &value[HEADER_LEN..]))
+        }
+        Some(format) => Err(StorageError::ServiceError(format!(
+            "Unknown point format {format} or truncated point"
+        ))),
+        None => Err(StorageError::ServiceError("Empty point record".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::segment::{Segment, StorageType, FORMAT_MARKER_KEY};
+    use serde_json::json;
+
+    #[test]
+    fn test_point_encoding() {
+        let point = Point {
+            id: PointId::Id(7),
+            payload: json!({ "msg": "hello", "n": -3, "f": 0.5, "tags": ["a", null, true] }),
+            version: 42,
+        };
+
+        let value = encode(&point).unwrap();
+        assert_eq!(value[0], FORMAT_V1);
+        assert!(!is_legacy(&value));
+        assert_eq!(stored_version(&value), 42);
+
+        let decoded = decode(PointId::Id(7), &value).unwrap();
+        assert_eq!(decoded.id, point.id);
+        assert_eq!(decoded.payload, point.payload);
+        assert_eq!(decoded.version, 42);
+
+        // Records written as JSON are still read
+        let legacy = serde_json::to_vec(&point).unwrap();
+        assert!(is_legacy(&legacy));
+        assert_eq!(stored_version(&legacy), 42);
+        assert_eq!(
+            decode(PointId::Id(7), &legacy).unwrap().payload,
+            point.payload
+        );
+
+        assert!(decode(PointId::Id(7), &[FORMAT_V1, 1, 2]).is_err());
+        assert!(decode(PointId::Id(7), &[0xff; 16]).is_err());
+    }
+
+    #[test]
+    fn test_legacy_migration() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let segment = Segment::create(tmp_dir.path(), StorageType::Log).unwrap();
+        // Segments written before the format marker
+        segment.storage.delete(FORMAT_MARKER_KEY).unwrap();
+        let point = Point {
+            id: PointId::Id(3),
+            payload: json!({ "msg": "written as json" }),
+            version: 5,
+        };
+        // Written as JSON under the id as a string key
+        segment
+            .storage
+            .put(b"3", &serde_json::to_vec(&point).unwrap())
+            .unwrap();
+        let path = segment.path.clone();
+        drop(segment);
+
+        let segment = Segment::load(&path, StorageType::Log).unwrap();
+        assert_eq!(segment.storage.get(b"3").unwrap(), None);
+        let stored = segment.storage.get(&point.id.to_key()).unwrap().unwrap();
+        assert_eq!(stored[0], FORMAT_V1);
+
+        let points = segment.get_points(None).unwrap();
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].id, point.id);
+        assert_eq!(points[0].payload, point.payload);
+        assert_eq!(points[0].version, 5);
+        assert_eq!(
+            segment.storage.get(FORMAT_MARKER_KEY).unwrap(),
+            Some(vec![FORMAT_V1])
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use crate::{
+    storage::{collection::CollectionName, error::StorageError},
+    types::{PeerId, ShardId},
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex,
+    time::Duration,
+};
+
+/// How often the rebalancer checks whether peers joined or left
+pub const REBALANCE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Points sent per second by the rebalancer unless `--rebalance-rate` is set
+pub const DEFAULT_REBALANCE_RATE: u64 = 10_000;
+
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MoveState {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+/// Copy of a local shard to a peer that doesn't hold it yet
+#[derive(Serialize, Clone, Debug)]
+pub struct ShardMove {
+    pub collection_name: CollectionName,
+    pub shard_id: ShardId,
+    pub to_peer_id: PeerId,
+    pub state: MoveState,
+    pub transferred_points: usize,
+    pub error: Option<String>,
+}
+
+/// State of the rebalancer, as shown by the API
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct RebalanceStatus {
+    pub enabled: bool,
+    pub paused: bool,
+    /// Points sent per second
+    pub rate_limit: u64,
+    /// Peers eligible for replicas when the current plan was computed
+    pub peers: BTreeSet<PeerId>,
+    pub moves: Vec<ShardMove>,
+}
+
+#[derive(Default)]
+struct RebalancerInner {
+    status: RebalanceStatus,
+    /// Plan again on the next tick, even if no peer joined or left
+    replan: bool,
+    /// Shards created empty on a peer by the rebalancer that weren't copied completely yet,
+    /// so that they are moved again even though the peer reports holding them
+    unfilled: BTreeSet<(PeerId, CollectionName, ShardId)>,
+}
+
+/// Copies shards to the peers that joined the cluster, see [`TableOfContent::run_rebalancer`].
+///
+/// [`TableOfContent::run_rebalancer`]: crate::storage::toc::TableOfContent::run_rebalancer
+#[derive(Default)]
+pub struct Rebalancer {
+    inner: Mutex<RebalancerInner>,
+}
+
+impl Rebalancer {
+    pub fn enable(&self, rate_limit: u64) {
+        let mut inner = self.inner.lock().expect("Rebalancer lock is poisoned");
+        inner.status.enabled = true;
+        inner.status.rate_limit = rate_limit;
+    }
+
+    pub fn status(&self) -> RebalanceStatus {
+        let inner = self.inner.lock().expect("Rebalancer lock is poisoned");
+        inner.status.clone()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        let inner = self.inner.lock().expect("Rebalancer lock is poisoned");
+        inner.status.paused
+    }
+
+    /// Pausing lets the running move finish, the pending ones resume once unpaused.
+    pub fn set_paused(&self, paused: bool) -> Result<RebalanceStatus, StorageError> {
+        let mut inner = self.inner.lock().expect("Rebalancer lock is poisoned");
+        if !inner.status.enabled {
+            return Err(StorageError::BadInput(
+                "Rebalancer is disabled, start the peer with --rebalance".to_string(),
+            ));
+        }
+        inner.status.paused = paused;
+        Ok(inner.status.clone())
+    }
+
+    /// Requests a new plan on the next tick
+    pub fn replan(&self) -> Result<(), StorageError> {
+        let mut inner = self.inner.lock().expect("Rebalancer lock is poisoned");
+        if !inner.status.enabled {
+            return Err(StorageError::BadInput(
+                "Rebalancer is disabled, start the peer with --rebalance".to_string(),
+            ));
+        }
+        inner.replan = true;
+        Ok(())
+    }
+
+    /// True if the plan is outdated because peers changed or a new plan was requested
+    pub fn needs_plan(&self, peers: &BTreeSet<PeerId>) -> bool {
+        let mut inner = self.inner.lock().expect("Rebalancer lock is poisoned");
+        std::mem::take(&mut inner.replan) || inner.status.peers != *peers
+    }
+
+    /// Replaces the previous plan, its pending moves are dropped
+    pub fn set_plan(&self, peers: BTreeSet<PeerId>, moves: Vec<ShardMove>) {
+        let mut inner = self.inner.lock().expect("Rebalancer lock is poisoned");
+        inner.status.peers = peers;
+        inner.status.moves = moves;
+    }
+
+    /// Records shards the rebalancer just created on the peer, they hold no points until moved
+    pub fn track_created(
+        &self,
+        peer_id: PeerId,
+        collection_name: &CollectionName,
+        shard_ids: impl IntoIterator<Item = ShardId>,
+    ) {
+        let mut inner = self.inner.lock().expect("Rebalancer lock is poisoned");
+        for shard_id in shard_ids {
+            inner
+                .unfilled
+                .insert((peer_id, collection_name.clone(), shard_id));
+        }
+    }
+
+    /// False if the shard was created by the rebalancer and no move to the peer completed yet
+    pub fn is_filled(&self, peer_id: PeerId, collection_name: &str, shard_id: ShardId) -> bool {
+        let inner = self.inner.lock().expect("Rebalancer lock is poisoned");
+        !inner
+            .unfilled
+            .contains(&(peer_id, collection_name.to_string(), shard_id))
+    }
+
+    /// Fails the pending moves to a peer that left, they are planned again if it comes back
+    pub fn fail_moves_to(&self, peer_id: PeerId, error: &str) {
+        let mut inner = self.inner.lock().expect("Rebalancer lock is poisoned");
+        for shard_move in inner.status.moves.iter_mut() {
+            if shard_move.to_peer_id == peer_id && shard_move.state == MoveState::Pending {
+                shard_move.state = MoveState::Failed;
+                shard_move.error = Some(error.to_string());
+            }
+        }
+        inner.replan = true;
+    }
+
+    /// Marks the next pending move as running and returns it with its index
+    pub fn next_move(&self) -> Option<(usize, ShardMove)> {
+        let mut inner = self.inner.lock().expect("Rebalancer lock is poisoned");
+        if inner.status.paused {
+            return None;
+        }
+
+        let (index, shard_move) = inner
+            .status
+            .moves
+            .iter_mut()
+            .enumerate()
+            .find(|(_, shard_move)| shard_move.state == MoveState::Pending)?;
+        shard_move.state = MoveState::Running;
+        Some((index, shard_move.clone()))
+    }
+
+    pub fn finish_move(&self, index: usize, result: Result<usize, String>) {
+        let mut inner = self.inner.lock().expect("Rebalancer lock is poisoned");
+        let Some(shard_move) = inner.status.moves.get_mut(index) else {
+            return;
+        };
+
+        match result {
+            Ok(transferred_points) => {
+                shard_move.state = MoveState::Done;
+                shard_move.transferred_points = transferred_points;
+                let key = (
+                    shard_move.to_peer_id,
+                    shard_move.collection_name.clone(),
+                    shard_move.shard_id,
+                );
+                inner.unfilled.remove(&key);
+            }
+            Err(e) => {
+                shard_move.state = MoveState::Failed;
+                shard_move.error = Some(e);
+                // Planned again on the next tick, which spaces out retries of a failing peer
+                inner.replan = true;
+            }
+        }
+    }
+}
+
+/// Every peer holds every shard, so the only moves needed are the shards a peer is missing.
+/// Shards a peer already holds are left to anti-entropy instead of being copied again.
+pub fn plan_moves(
+    local_shards: &BTreeMap<CollectionName, Vec<ShardId>>,
+    peer_shards: &BTreeMap<PeerId, BTreeMap<CollectionName, BTreeSet<ShardId>>>,
+) -> Vec<ShardMove> {
+    let mut moves = vec![];
+    for (peer_id, held) in peer_shards {
+        for (collection_name, shard_ids) in local_shards {
+            let held_shards = held.get(collection_name);
+            for shard_id in shard_ids {
+                if held_shards.is_some_and(|held_shards| held_shards.contains(shard_id)) {
+                    continue;
+                }
+
+                moves.push(ShardMove {
+                    collection_name: collection_name.clone(),
+                    shard_id: *shard_id,
+                    to_peer_id: *peer_id,
+                    state: MoveState::Pending,
+                    transferred_points: 0,
+                    error: None,
+                });
+            }
+        }
+    }
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_moves() {
+        let local_shards =
+            BTreeMap::from([("c1".to_string(), vec![0, 1]), ("c2".to_string(), vec![0])]);
+
+        let peer_shards = BTreeMap::from([
+            // Already holds everything
+            (
+                101,
+                BTreeMap::from([
+                    ("c1".to_string(), BTreeSet::from([0, 1])),
+                    ("c2".to_string(), BTreeSet::from([0])),
+                ]),
+            ),
+            // Just joined
+            (102, BTreeMap::new()),
+            // Misses a single shard
+            (
+                103,
+                BTreeMap::from([("c1".to_string(), BTreeSet::from([0]))]),
+            ),
+        ]);
+
+        let moves = plan_moves(&local_shards, &peer_shards)
+            .into_iter()
+            .map(|shard_move| {
+                (
+                    shard_move.to_peer_id,
+                    shard_move.collection_name,
+                    shard_move.shard_id,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            moves,
+            vec![
+                (102, "c1".to_string(), 0),
+                (102, "c1".to_string(), 1),
+                (102, "c2".to_string(), 0),
+                (103, "c1".to_string(), 1),
+                (103, "c2".to_string(), 0),
+            ]
+        );
+
+        let rebalancer = Rebalancer::default();
+        assert!(rebalancer.set_paused(true).is_err());
+        rebalancer.enable(DEFAULT_REBALANCE_RATE);
+
+        let peers = BTreeSet::from([101, 102, 103]);
+        assert!(rebalancer.needs_plan(&peers));
+        rebalancer.set_plan(peers.clone(), plan_moves(&local_shards, &peer_shards));
+        assert!(!rebalancer.needs_plan(&peers));
+
+        // Paused rebalancer doesn't start new moves
+        rebalancer.set_paused(true).unwrap();
+        assert!(rebalancer.next_move().is_none());
+        rebalancer.set_paused(false).unwrap();
+
+        let (index, _) = rebalancer.next_move().unwrap();
+        rebalancer.finish_move(index, Ok(10));
+        let status = rebalancer.status();
+        assert_eq!(status.moves[0].state, MoveState::Done);
+        assert_eq!(status.moves[1].state, MoveState::Pending);
+    }
+
+    #[test]
+    fn test_unfilled_shards() {
+        let rebalancer = Rebalancer::default();
+        rebalancer.enable(DEFAULT_REBALANCE_RATE);
+        let collection_name = "c1".to_string();
+
+        rebalancer.track_created(102, &collection_name, [0, 1]);
+        assert!(!rebalancer.is_filled(102, "c1", 0));
+        assert!(rebalancer.is_filled(103, "c1", 0));
+
+        let shard_move = |shard_id| ShardMove {
+            collection_name: collection_name.clone(),
+            shard_id,
+            to_peer_id: 102,
+            state: MoveState::Pending,
+            transferred_points: 0,
+            error: None,
+        };
+        rebalancer.set_plan(BTreeSet::from([102]), vec![shard_move(0), shard_move(1)]);
+
+        // Only the completed move fills the shard, the failed one is planned again
+        let peers = BTreeSet::from([102]);
+        let (index, _) = rebalancer.next_move().unwrap();
+        rebalancer.finish_move(index, Ok(10));
+        assert!(!rebalancer.needs_plan(&peers));
+        let (index, _) = rebalancer.next_move().unwrap();
+        rebalancer.finish_move(index, Err("Connection reset".to_string()));
+        assert!(rebalancer.is_filled(102, "c1", 0));
+        assert!(!rebalancer.is_filled(102, "c1", 1));
+        assert!(rebalancer.needs_plan(&peers));
+        assert!(!rebalancer.needs_plan(&peers));
+    }
+
+    #[test]
+    fn test_peer_left() {
+        let rebalancer = Rebalancer::default();
+        rebalancer.enable(DEFAULT_REBALANCE_RATE);
+
+        let local_shards = BTreeMap::from([("c1".to_string(), vec![0, 1])]);
+        let peer_shards = BTreeMap::from([(102, BTreeMap::new()), (103, BTreeMap::new())]);
+        let peers = BTreeSet::from([102, 103]);
+        assert!(rebalancer.needs_plan(&peers));
+        rebalancer.set_plan(peers.clone(), plan_moves(&local_shards, &peer_shards));
+
+        rebalancer.fail_moves_to(102, "Peer left the cluster");
+        let status = rebalancer.status();
+        for shard_move in &status.moves {
+            let expected = if shard_move.to_peer_id == 102 {
+                MoveState::Failed
+            } else {
+                MoveState::Pending
+            };
+            assert_eq!(shard_move.state, expected);
+        }
+
+        // Only moves to the remaining peer are started, and a new plan is requested
+        let (_, shard_move) = rebalancer.next_move().unwrap();
+        assert_eq!(shard_move.to_peer_id, 103);
+        assert!(rebalancer.needs_plan(&peers));
+    }
+}
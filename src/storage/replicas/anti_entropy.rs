@@ -0,0 +1,299 @@
+use crate::storage::{
+    error::{CollectionResult, StorageError},
+    replicas::{
+        remote_shard::RemoteShard,
+        ring::{sip_hash, HashRing},
+        ReplicaSet, ShardOperationTrait,
+    },
+    segment::{Point, PointId},
+};
+use std::collections::{HashMap, HashSet};
+
+/// Number of ranges the points of a shard are split into when comparing replicas.
+/// Only the points of the ranges with different hashes are transferred.
+pub const NUM_RANGES: u32 = 256;
+
+/// Number of points of the differing ranges read and sent at once when syncing replicas
+pub const SYNC_BATCH_SIZE: usize = 1_000;
+
+/// Range the point belongs to, out of `num_ranges`.
+pub fn point_range(id: &PointId, num_ranges: u32) -> u32 {
+    (sip_hash(&[id.into_string().as_bytes()]) % num_ranges as u64) as u32
+}
+
+/// Hash of each range computed over the ids and versions of its points.
+/// Points are combined with a wrapping sum so that the iteration order doesn't matter.
+pub fn range_hashes(
+    versions: impl IntoIterator<Item = Result<(PointId, u64), StorageError>>,
+    num_ranges: u32,
+) -> Result<Vec<u64>, StorageError> {
+    let mut hashes = vec![0u64; num_ranges as usize];
+    for result in versions {
+        let (id, version) = result?;
+        let range = point_range(&id, num_ranges) as usize;
+        let point_hash = sip_hash(&[id.into_string().as_bytes(), &version.to_le_bytes()]);
+        hashes[range] = hashes[range].wrapping_add(point_hash);
+    }
+    Ok(hashes)
+}
+
+/// Points that are missing or outdated in `other`.
+fn newer_points(points: &[Point], other: &[Point]) -> Vec<Point> {
+    let other_versions = other
+        .iter()
+        .map(|point| (&point.id, point.version))
+        .collect::<HashMap<_, _>>();
+
+    points
+        .iter()
+        .filter(|point| {
+            other_versions
+                .get(&point.id)
+                .is_none_or(|version| *version < point.version)
+        })
+        .cloned()
+        .collect()
+}
+
+#[derive(Default, Debug)]
+pub struct SyncStats {
+    pub differing_ranges: usize,
+    pub sent_points: usize,
+    pub received_points: usize,
+}
+
+impl ReplicaSet {
+    /// Compares the local shard with each remote replica range by range and exchanges the
+    /// points of the ranges that differ, so that replicas which missed writes converge.
+    /// Remotes that fail are skipped until the next round.
+    ///
+    /// With a `ring`, only the points it places on this shard are exchanged: others were moved
+    /// away by a resharding and must not be copied back from a replica that didn't move them yet.
+    pub async fn sync_replicas(&self, ring: Option<&HashRing>) -> CollectionResult<SyncStats> {
+        // Computed once per round, points received from a remote reach the others next round
+        let local_hashes = self.local.range_hashes(NUM_RANGES).await?;
+
+        let mut stats = SyncStats::default();
+        for remote in &self.remotes {
+            match self.sync_remote(remote, &local_hashes, ring).await {
+                Ok(remote_stats) => {
+                    stats.differing_ranges += remote_stats.differing_ranges;
+                    stats.sent_points += remote_stats.sent_points;
+                    stats.received_points += remote_stats.received_points;
+                }
+                Err(e) => eprintln!(
+                    "Skipping sync of shard {} with peer {}: {e}",
+                    remote.id, remote.peer_id
+                ),
+            }
+        }
+
+        Ok(stats)
+    }
+
+    async fn sync_remote(
+        &self,
+        remote: &RemoteShard,
+        local_hashes: &[u64],
+        ring: Option<&HashRing>,
+    ) -> CollectionResult<SyncStats> {
+        let remote_hashes = remote.range_hashes(NUM_RANGES).await?;
+
+        let differing_ranges = local_hashes
+            .iter()
+            .zip(remote_hashes.iter())
+            .enumerate()
+            .filter(|(_, (local, remote))| local != remote)
+            .map(|(range, _)| range as u32)
+            .collect::<HashSet<_>>();
+
+        if differing_ranges.is_empty() {
+            return Ok(SyncStats::default());
+        }
+
+        // Points newer here are pushed first, then points newer on the remote are pulled
+        let sent_points = self
+            .send_newer_points(self.local.as_ref(), remote, &differing_ranges, ring)
+            .await?;
+        let received_points = self
+            .send_newer_points(remote, self.local.as_ref(), &differing_ranges, ring)
+            .await?;
+
+        Ok(SyncStats {
+            differing_ranges: differing_ranges.len(),
+            sent_points,
+            received_points,
+        })
+    }
+
+    /// Upserts the points of the ranges that are missing or outdated in `to`, reading `from`
+    /// a page at a time so that a replica that is far behind doesn't get the whole shard at once.
+    /// Returns the number of sent points.
+    async fn send_newer_points(
+        &self,
+        from: &(dyn ShardOperationTrait + Send + Sync),
+        to: &(dyn ShardOperationTrait + Send + Sync),
+        ranges: &HashSet<u32>,
+        ring: Option<&HashRing>,
+    ) -> CollectionResult<usize> {
+        let shard_id = self.local.id;
+        let mut offset = None;
+        let mut sent_points = 0;
+        loop {
+            let page = from
+                .get_points_in_ranges(ranges.clone(), NUM_RANGES, offset.clone(), SYNC_BATCH_SIZE)
+                .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            offset = Some(last.id.clone());
+            let page_len = page.len();
+
+            let points = page
+                .into_iter()
+                .filter(|point| ring.is_none_or(|ring| ring.get(&point.id) == Some(shard_id)))
+                .collect::<Vec<_>>();
+            if !points.is_empty() {
+                let ids = points.iter().map(|point| point.id.clone()).collect();
+                let existing = to.get_points(Some(ids)).await?;
+                let newer = newer_points(&points, &existing);
+                if !newer.is_empty() {
+                    sent_points += newer.len();
+                    to.upsert_points(newer).await?;
+                }
+            }
+
+            if page_len < SYNC_BATCH_SIZE {
+                break;
+            }
+        }
+        Ok(sent_points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        channel_service::ChannelService,
+        storage::{
+            replicas::{local_shard::LocalShard, ring::RingConfig},
+            segment::StorageType,
+        },
+    };
+    use serde_json::json;
+
+    fn point(id: u64, version: u64) -> Point {
+        Point {
+            id: PointId::Id(id),
+            payload: json!({}),
+            version,
+        }
+    }
+
+    fn hashes(points: &[Point], num_ranges: u32) -> Vec<u64> {
+        let versions = points
+            .iter()
+            .map(|point| Ok((point.id.clone(), point.version)));
+        range_hashes(versions, num_ranges).unwrap()
+    }
+
+    #[test]
+    fn test_range_hashes() {
+        let points = (0..100).map(|id| point(id, 1)).collect::<Vec<_>>();
+        let mut reversed = points.clone();
+        reversed.reverse();
+        assert_eq!(hashes(&points, 16), hashes(&reversed, 16));
+
+        // Only the range of the updated point differs
+        let mut updated = points.clone();
+        updated[42].version = 2;
+        let changed = hashes(&points, 16)
+            .iter()
+            .zip(hashes(&updated, 16))
+            .enumerate()
+            .filter(|(_, (a, b))| *a != b)
+            .map(|(range, _)| range as u32)
+            .collect::<Vec<_>>();
+        assert_eq!(changed, vec![point_range(&PointId::Id(42), 16)]);
+
+        let ours = vec![point(1, 2), point(2, 1), point(3, 1)];
+        let theirs = vec![point(1, 1), point(2, 2)];
+        let ids = newer_points(&ours, &theirs)
+            .into_iter()
+            .map(|point| point.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![PointId::Id(1), PointId::Id(3)]);
+    }
+
+    #[tokio::test]
+    async fn test_sync_skips_failing_remotes() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let local = LocalShard::init(tmp_dir.path().join("0"), 0, StorageType::Memory);
+        local.upsert_points(vec![point(1, 1)]).await.unwrap();
+
+        // Peers without a known address, so all requests to them fail
+        let replica_set = ReplicaSet::new(
+            local,
+            vec![998, 999],
+            "c1".to_string(),
+            ChannelService::default(),
+        );
+
+        let stats = replica_set.sync_replicas(None).await.unwrap();
+        assert_eq!(stats.differing_ranges, 0);
+        assert_eq!(
+            replica_set.local.range_hashes(NUM_RANGES).await.unwrap(),
+            hashes(&[point(1, 1)], NUM_RANGES)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_newer_points() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let replica_set = ReplicaSet::new(
+            LocalShard::init(tmp_dir.path().join("0"), 0, StorageType::Memory),
+            vec![],
+            "c1".to_string(),
+            ChannelService::default(),
+        );
+        let from = LocalShard::init(tmp_dir.path().join("1"), 0, StorageType::Memory);
+        let points = (0..2 * SYNC_BATCH_SIZE as u64 + 10)
+            .map(|id| point(id, 2))
+            .collect::<Vec<_>>();
+        from.upsert_points(points.clone()).await.unwrap();
+        let all_ranges = (0..NUM_RANGES).collect::<HashSet<_>>();
+
+        // An empty replica gets every point, a page at a time, except the ones it has newer
+        let to = LocalShard::init(tmp_dir.path().join("2"), 0, StorageType::Memory);
+        to.upsert_points(vec![point(0, 3), point(1, 1)])
+            .await
+            .unwrap();
+        let sent = replica_set
+            .send_newer_points(&from, &to, &all_ranges, None)
+            .await
+            .unwrap();
+        assert_eq!(sent, points.len() - 1);
+        assert_eq!(
+            to.range_hashes(NUM_RANGES).await.unwrap(),
+            hashes(
+                &[vec![point(0, 3)], points[1..].to_vec()].concat(),
+                NUM_RANGES
+            )
+        );
+
+        // Points placed on another shard by the ring are left out
+        let ring = HashRing::new(&RingConfig::default(), [0, 1]);
+        let to = LocalShard::init(tmp_dir.path().join("3"), 0, StorageType::Memory);
+        let sent = replica_set
+            .send_newer_points(&from, &to, &all_ranges, Some(&ring))
+            .await
+            .unwrap();
+        let owned = points
+            .iter()
+            .filter(|point| ring.get(&point.id) == Some(0))
+            .count();
+        assert!(owned > 0 && owned < points.len());
+        assert_eq!(sent, owned);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::storage::error::StorageError;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// How many replicas of a shard have to answer a read request.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(try_from = "ReadConsistencyRaw", into = "String")]
+pub enum ReadConsistency {
+    /// Any single replica, local one is preferred
+    #[default]
+    One,
+    /// More than half of the replicas, but all of them are queried to tolerate slow ones
+    Majority,
+    /// More than half of the replicas, only as many as needed are queried
+    Quorum,
+    /// Every replica
+    All,
+    /// Exact number of replicas
+    Factor(usize),
+}
+
+impl ReadConsistency {
+    /// Number of replicas that must answer out of `num_replicas`.
+    pub fn required_replicas(&self, num_replicas: usize) -> Result<usize, StorageError> {
+        let required = match self {
+            ReadConsistency::One => 1,
+            ReadConsistency::Majority | ReadConsistency::Quorum => num_replicas / 2 + 1,
+            ReadConsistency::All => num_replicas,
+            ReadConsistency::Factor(factor) => *factor,
+        };
+
+        if required > num_replicas {
+            return Err(StorageError::BadInput(format!(
+                "Read consistency {self} requires {required} replicas but shard only has {num_replicas}"
+            )));
+        }
+
+        Ok(required)
+    }
+
+    /// Number of replicas to query at once, which can be more than required.
+    pub fn queried_replicas(&self, num_replicas: usize) -> Result<usize, StorageError> {
+        match self {
+            ReadConsistency::Majority => Ok(num_replicas),
+            _ => self.required_replicas(num_replicas),
+        }
+    }
+}
+
+impl FromStr for ReadConsistency {
+    type Err = StorageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "one" => Ok(ReadConsistency::One),
+            "majority" => Ok(ReadConsistency::Majority),
+            "quorum" => Ok(ReadConsistency::Quorum),
+            "all" => Ok(ReadConsistency::All),
+            _ => match s.parse::<usize>() {
+                Ok(0) => Err(StorageError::BadInput(
+                    "Read consistency factor must be greater than 0".to_string(),
+                )),
+                Ok(factor) => Ok(ReadConsistency::Factor(factor)),
+                Err(_) => Err(StorageError::BadInput(format!(
+                    "Invalid read consistency '{s}', expected one of: one, majority, quorum, all or a number"
+                ))),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ReadConsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadConsistency::One => write!(f, "one"),
+            ReadConsistency::Majority => write!(f, "majority"),
+            ReadConsistency::Quorum => write!(f, "quorum"),
+            ReadConsistency::All => write!(f, "all"),
+            ReadConsistency::Factor(factor) => write!(f, "{factor}"),
+        }
+    }
+}
+
+impl From<ReadConsistency> for String {
+    fn from(value: ReadConsistency) -> Self {
+        value.to_string()
+    }
+}
+
+/// Accepts both `"majority"` and `2` in JSON, query params are always strings.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ReadConsistencyRaw {
+    Factor(usize),
+    Named(String),
+}
+
+impl TryFrom<ReadConsistencyRaw> for ReadConsistency {
+    type Error = StorageError;
+
+    fn try_from(value: ReadConsistencyRaw) -> Result<Self, Self::Error> {
+        match value {
+            ReadConsistencyRaw::Factor(factor) => factor.to_string().parse(),
+            ReadConsistencyRaw::Named(name) => name.parse(),
+        }
+    }
+}
@@ -1,20 +1,31 @@
 use crate::{
     storage::{
         error::{CollectionError, CollectionResult, StorageError},
-        replicas::ShardOperationTrait,
-        segment::{Point, PointId, Segment},
+        replicas::{anti_entropy, ShardOperationTrait},
+        segment::{Point, PointId, Segment, StorageType},
     },
     types::{SegmentId, ShardId},
 };
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 use tonic::async_trait;
 
 const SEGMENTS_DIR: &str = "segments";
 
+/// How long deleting a shard waits for background tasks to release it
+pub const RELEASE_TIMEOUT: Duration = Duration::from_secs(30);
+pub const RELEASE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 pub struct LocalShard {
     pub id: ShardId,
     pub path: PathBuf,
     pub segments: HashMap<SegmentId, Segment>,
+    /// Shards in memory have no directory
+    pub storage: StorageType,
     // ToDo: Wal
 }
 
@@ -45,23 +56,60 @@ impl ShardOperationTrait for LocalShard {
             ))
         }
     }
+
+    async fn get_points_page(
+        &self,
+        offset: Option<PointId>,
+        limit: usize,
+    ) -> CollectionResult<Vec<Point>> {
+        Ok(self.segment()?.get_points_page(offset.as_ref(), limit)?)
+    }
+
+    async fn range_hashes(&self, num_ranges: u32) -> CollectionResult<Vec<u64>> {
+        let segment = self.segment()?;
+        Ok(anti_entropy::range_hashes(
+            segment.iter_versions(),
+            num_ranges,
+        )?)
+    }
+
+    async fn get_points_in_ranges(
+        &self,
+        ranges: HashSet<u32>,
+        num_ranges: u32,
+        offset: Option<PointId>,
+        limit: usize,
+    ) -> CollectionResult<Vec<Point>> {
+        let segment = self.segment()?;
+        let points = segment
+            .iter_points(offset.as_ref(), |id| {
+                ranges.contains(&anti_entropy::point_range(id, num_ranges))
+            })
+            .take(limit)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(points)
+    }
 }
 
 impl LocalShard {
-    pub fn init(path: PathBuf, id: ShardId) -> Self {
+    pub fn init(path: PathBuf, id: ShardId, storage: StorageType) -> Self {
         let segments_dir = path.join(SEGMENTS_DIR);
-        std::fs::create_dir_all(&segments_dir).expect("Failed to create segments directory");
+        if storage.is_persistent() {
+            std::fs::create_dir_all(&segments_dir).expect("Failed to create segments directory");
+        }
 
-        let segment0 = Segment::create(&segments_dir).expect("Failed to create initial segment");
+        let segment0 =
+            Segment::create(&segments_dir, storage).expect("Failed to create initial segment");
 
         LocalShard {
             id,
             path: path.to_owned(),
             segments: HashMap::from_iter([(0, segment0)]),
+            storage,
         }
     }
 
-    pub fn load(path: &PathBuf) -> Result<Self, StorageError> {
+    pub fn load(path: &PathBuf, storage: StorageType) -> Result<Self, StorageError> {
         let segments_dir = path.join(SEGMENTS_DIR);
         std::fs::create_dir_all(&segments_dir).expect("Failed to create segments directory");
 
@@ -88,7 +136,7 @@ impl LocalShard {
 
         let mut segments = HashMap::new();
         for (id, segment_path) in segment_paths {
-            let segment = Segment::load(&segment_path)?;
+            let segment = Segment::load(&segment_path, storage)?;
             segments.insert(id, segment);
         }
 
@@ -96,9 +144,86 @@ impl LocalShard {
             id,
             path: path.to_owned(),
             segments,
+            storage,
         })
     }
 
+    fn segment(&self) -> Result<&Segment, StorageError> {
+        self.segments
+            .get(&0)
+            .ok_or_else(|| StorageError::ServiceError("No segments available".to_string()))
+    }
+
+    /// Highest version of the stored points, 0 if the shard is empty
+    pub fn max_version(&self) -> Result<u64, StorageError> {
+        let mut max_version = 0;
+        for segment in self.segments.values() {
+            for result in segment.iter_versions() {
+                let (_, version) = result?;
+                max_version = max_version.max(version);
+            }
+        }
+        Ok(max_version)
+    }
+
+    /// Removes points from the shard. Only used for moving points between local shards
+    /// while resharding, so it's not part of [`ShardOperationTrait`] yet.
+    pub fn delete_points(&self, ids: &[PointId]) -> Result<(), StorageError> {
+        if let Some(segment) = self.segments.get(&0) {
+            segment.delete_points(ids)
+        } else {
+            Err(StorageError::ServiceError(
+                "No segments available".to_string(),
+            ))
+        }
+    }
+
+    /// Deletes the shard directory once background tasks (e.g. read repair) released the shard,
+    /// so that its segments are closed before their files are removed.
+    pub async fn delete(self: Arc<Self>) -> Result<(), StorageError> {
+        let shard = Self::wait_released(self).await?;
+        let path = shard.path.clone();
+        let storage = shard.storage;
+        drop(shard);
+
+        if !storage.is_persistent() {
+            return Ok(());
+        }
+
+        tokio::task::spawn_blocking(move || std::fs::remove_dir_all(&path))
+            .await
+            .map_err(|e| StorageError::ServiceError(format!("Delete task failed: {e}")))?
+            .map_err(|e| StorageError::ServiceError(format!("Failed to delete shard: {e}")))
+    }
+
+    /// Waits until `shard` is the last reference to the shard
+    pub async fn wait_released(mut shard: Arc<Self>) -> Result<Self, StorageError> {
+        let deadline = tokio::time::Instant::now() + RELEASE_TIMEOUT;
+        loop {
+            match Arc::try_unwrap(shard) {
+                Ok(shard) => return Ok(shard),
+                Err(shared) if tokio::time::Instant::now() < deadline => {
+                    shard = shared;
+                    tokio::time::sleep(RELEASE_POLL_INTERVAL).await;
+                }
+                Err(shared) => {
+                    return Err(StorageError::ServiceError(format!(
+                        "Shard {} is still in use after {RELEASE_TIMEOUT:?}",
+                        shared.id
+                    )))
+                }
+            }
+        }
+    }
+
+    /// Writes every segment to disk, e.g. before its files are copied
+    pub fn flush(&self) -> Result<(), StorageError> {
+        for segment in self.segments.values() {
+            segment.flush()?;
+        }
+        Ok(())
+    }
+
     pub fn count_points(&self) -> usize {
         if let Some(segment) = self.segments.get(&0) {
             segment.count_points()
@@ -107,3 +232,26 @@ impl LocalShard {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_delete_waits_for_release() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("0");
+        let shard = Arc::new(LocalShard::init(path.clone(), 0, StorageType::Sled));
+
+        // E.g. a read repair task still writing to the shard
+        let task_shard = shard.clone();
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            drop(task_shard);
+        });
+
+        shard.delete().await.unwrap();
+        assert!(task.is_finished());
+        assert!(!path.exists());
+    }
+}
@@ -1,19 +1,34 @@
+pub mod anti_entropy;
+pub mod consistency;
 pub mod local_shard;
 pub mod remote_shard;
+pub mod ring;
+pub mod transfer;
 
+use crate::channel_service::ChannelService;
+use crate::storage::error::CollectionError;
+use crate::storage::replicas::consistency::ReadConsistency;
 use crate::storage::replicas::local_shard::LocalShard;
 use crate::storage::replicas::remote_shard::RemoteShard;
+use crate::storage::replicas::ring::{HashRing, RingConfig};
 use crate::storage::segment::Point;
 use crate::storage::{
     collection::CollectionName,
     error::{CollectionResult, StorageError},
     segment::PointId,
 };
-use crate::types::{PeerId, ShardId};
-use futures::future::BoxFuture;
-use std::collections::HashMap;
+use crate::types::{PeerId, ShardId, ShardKey};
+use futures::future::{join_all, BoxFuture};
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{hash_map::Entry, BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use tonic::async_trait;
 
+/// Called with the peer a write failed on, see [`ReplicaSet::execute_cluster_operation`]
+pub type OnRemoteFailure = Arc<dyn Fn(PeerId, &CollectionError) + Send + Sync>;
+
 #[derive(Copy, Clone, Debug)]
 pub struct UpdateResult {
     pub operation_id: Option<u64>,
@@ -23,90 +38,590 @@ pub struct UpdateResult {
 pub trait ShardOperationTrait {
     async fn get_points(&self, ids: Option<Vec<PointId>>) -> CollectionResult<Vec<Point>>;
     async fn upsert_points(&self, points: Vec<Point>) -> CollectionResult<()>;
+    /// At most `limit` points after `offset`, to read a whole shard page by page
+    async fn get_points_page(
+        &self,
+        offset: Option<PointId>,
+        limit: usize,
+    ) -> CollectionResult<Vec<Point>>;
+    /// Hashes of the point ranges used to find out which ranges differ between replicas
+    async fn range_hashes(&self, num_ranges: u32) -> CollectionResult<Vec<u64>>;
+    /// At most `limit` points of the ranges after `offset`, see [`Self::get_points_page`]
+    async fn get_points_in_ranges(
+        &self,
+        ranges: HashSet<u32>,
+        num_ranges: u32,
+        offset: Option<PointId>,
+        limit: usize,
+    ) -> CollectionResult<Vec<Point>>;
+}
+
+/// Identifies a replica within a [`ReplicaSet`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ReplicaId {
+    Local,
+    Remote(PeerId),
+}
+
+/// Number of points pushed to stale replicas after reads, see [`ReplicaSet::get_points`].
+#[derive(Default)]
+pub struct ReadRepairCounters {
+    pub repaired_points: AtomicU64,
+    pub failed_points: AtomicU64,
 }
 
+/// Cheap to clone, e.g. to release the replica holder before calling remote replicas
+#[derive(Clone)]
 pub struct ReplicaSet {
-    pub local: LocalShard,
+    pub local: Arc<LocalShard>,
     pub remotes: Vec<RemoteShard>,
+    pub read_repairs: Arc<ReadRepairCounters>,
+    channel_service: ChannelService,
 
     #[allow(dead_code)]
     collection_id: CollectionName,
 }
 
 impl ReplicaSet {
-    pub fn new(local: LocalShard, remotes: Vec<PeerId>, collection_id: CollectionName) -> Self {
+    pub fn new(
+        local: LocalShard,
+        remotes: Vec<PeerId>,
+        collection_id: CollectionName,
+        channel_service: ChannelService,
+    ) -> Self {
         let remotes = remotes
             .into_iter()
-            .map(|peer_id| RemoteShard::new(local.id, collection_id.clone(), peer_id))
+            .map(|peer_id| {
+                RemoteShard::new(
+                    local.id,
+                    collection_id.clone(),
+                    peer_id,
+                    channel_service.clone(),
+                )
+            })
             .collect();
 
         ReplicaSet {
-            local,
+            local: Arc::new(local),
             remotes,
+            read_repairs: Arc::new(ReadRepairCounters::default()),
+            channel_service,
             collection_id,
         }
     }
 
+    fn replica(&self, replica_id: ReplicaId) -> Option<Arc<dyn ShardOperationTrait + Send + Sync>> {
+        match replica_id {
+            ReplicaId::Local => Some(self.local.clone()),
+            ReplicaId::Remote(peer_id) => self
+                .remotes
+                .iter()
+                .find(|remote| remote.peer_id == peer_id)
+                .map(|remote| Arc::new(remote.clone()) as _),
+        }
+    }
+
     pub fn num_replicas(&self) -> usize {
         self.remotes.len() + 1 // +1 for the local shard
     }
 
-    /// Executes the operation on the local shard and then on all the remote shards.
+    /// Executes the operation on the local shard and all the remote shards concurrently.
     /// If `local_only` is true, it only executes on the local shard.
+    ///
+    /// Returns once the local shard answered and at least `min_success` replicas succeeded,
+    /// or once every replica answered. Slower replicas finish in the background and
+    /// `on_remote_failure` is called for every remote that fails or times out, even after that.
     pub async fn execute_cluster_operation<Res, F>(
         &self,
         operation: F,
+        min_success: usize,
         local_only: bool,
-    ) -> Vec<CollectionResult<Res>>
+        on_remote_failure: Option<OnRemoteFailure>,
+    ) -> Vec<(ReplicaId, CollectionResult<Res>)>
     where
-        F: Fn(&(dyn ShardOperationTrait + Send + Sync)) -> BoxFuture<'_, CollectionResult<Res>>,
+        Res: Send + 'static,
+        F: Fn(
+            Arc<dyn ShardOperationTrait + Send + Sync>,
+        ) -> BoxFuture<'static, CollectionResult<Res>>,
     {
-        let local_result = operation(&self.local).await;
-        let mut final_results = vec![local_result];
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let shard_id = self.local.id;
+
+        let local = self.local.clone() as Arc<dyn ShardOperationTrait + Send + Sync>;
+        tokio::spawn({
+            // Local shard isn't cancel safe, so it doesn't get a timeout
+            let future = operation(local);
+            let sender = sender.clone();
+            async move {
+                let _ = sender.send((ReplicaId::Local, future.await));
+            }
+        });
+
+        if !local_only {
+            for remote in &self.remotes {
+                let peer_id = remote.peer_id;
+                let future = operation(Arc::new(remote.clone()));
+                let sender = sender.clone();
+                let on_remote_failure = on_remote_failure.clone();
+                let replica_timeout = self.channel_service.replica_timeout;
 
+                tokio::spawn(async move {
+                    let result = match tokio::time::timeout(replica_timeout, future).await {
+                        Ok(result) => result,
+                        Err(_) => Err(CollectionError::ServiceError(format!(
+                            "Timed out after {replica_timeout:?}"
+                        ))),
+                    };
+
+                    if let Err(e) = &result {
+                        // Ignore errors from remote shards, but log them
+                        println!(
+                            "Error executing operation on remote shard {peer_id}/{shard_id}: {e}"
+                        );
+                        if let Some(on_remote_failure) = on_remote_failure {
+                            on_remote_failure(peer_id, e);
+                        }
+                    }
+
+                    // Nobody is listening anymore if enough replicas answered already
+                    let _ = sender.send((ReplicaId::Remote(peer_id), result));
+                });
+            }
+        }
+        drop(sender);
+
+        let mut results = vec![];
+        let mut local_answered = false;
+        let mut successes = 0;
+
+        while let Some((replica_id, result)) = receiver.recv().await {
+            local_answered |= replica_id == ReplicaId::Local;
+            successes += result.is_ok() as usize;
+            results.push((replica_id, result));
+
+            if local_answered && successes >= min_success {
+                break;
+            }
+        }
+
+        results
+    }
+
+    /// Executes a read operation on as many replicas as `read_consistency` requires, in parallel.
+    /// The local shard is queried first and the next replicas are only tried if some of them fail.
+    /// If `local_only` is true, it only executes on the local shard.
+    pub async fn execute_read_operation<Res, F>(
+        &self,
+        operation: F,
+        read_consistency: ReadConsistency,
+        local_only: bool,
+    ) -> CollectionResult<Vec<(ReplicaId, Res)>>
+    where
+        F: Fn(&(dyn ShardOperationTrait + Send + Sync)) -> BoxFuture<'_, CollectionResult<Res>>,
+    {
         if local_only {
-            return final_results;
-        }
-
-        for remote in &self.remotes {
-            let operation_result = operation(remote).await;
-            match operation_result {
-                Ok(res) => final_results.push(Ok(res)),
-                Err(e) => {
-                    // Ignore errors from remote shards, but log them
-                    println!(
-                        "Error executing operation on remote shard {}/{}: {}",
-                        remote.peer_id, remote.id, e
-                    );
-                    final_results.push(Err(e));
+            return Ok(vec![(
+                ReplicaId::Local,
+                operation(self.local.as_ref()).await?,
+            )]);
+        }
+
+        let num_replicas = self.num_replicas();
+        let required = read_consistency.required_replicas(num_replicas)?;
+        let mut batch_size = read_consistency.queried_replicas(num_replicas)?;
+
+        let mut replicas = std::iter::once((
+            ReplicaId::Local,
+            self.local.as_ref() as &(dyn ShardOperationTrait + Send + Sync),
+        ))
+        .chain(self.remotes.iter().map(|remote| {
+            (
+                ReplicaId::Remote(remote.peer_id),
+                remote as &(dyn ShardOperationTrait + Send + Sync),
+            )
+        }));
+
+        let mut results = Vec::with_capacity(required);
+        let mut errors = vec![];
+
+        while results.len() < required {
+            let batch = replicas
+                .by_ref()
+                .take(batch_size)
+                .map(|(replica_id, replica)| operation(replica).map(move |res| (replica_id, res)))
+                .collect::<Vec<_>>();
+
+            if batch.is_empty() {
+                break; // No replicas left to try
+            }
+
+            for (replica_id, result) in join_all(batch).await {
+                match result {
+                    Ok(res) => results.push((replica_id, res)),
+                    Err(e) => errors.push(e.to_string()),
                 }
             }
+
+            batch_size = required.saturating_sub(results.len());
+        }
+
+        if results.len() < required {
+            return Err(CollectionError::ServiceError(format!(
+                "Only {} out of {required} required replicas of shard {} answered: {}",
+                results.len(),
+                self.local.id,
+                errors.join(", ")
+            )));
+        }
+
+        Ok(results)
+    }
+
+    /// Reads points from the replicas required by `read_consistency` and merges the responses.
+    ///
+    /// If `read_repair` is set and replicas disagree, the newest version of the points is pushed
+    /// to the stale replicas in the background.
+    pub async fn get_points(
+        &self,
+        ids: Option<Vec<PointId>>,
+        read_consistency: ReadConsistency,
+        local_only: bool,
+        read_repair: bool,
+    ) -> CollectionResult<Vec<Point>> {
+        let responses = self
+            .execute_read_operation(
+                |shard| {
+                    let ids_cloned = ids.clone();
+                    async move { shard.get_points(ids_cloned).await }.boxed()
+                },
+                read_consistency,
+                local_only,
+            )
+            .await?;
+
+        let mut points: HashMap<PointId, Point> = HashMap::new();
+        for point in responses
+            .iter()
+            .flat_map(|(_, points)| points.iter())
+            .cloned()
+        {
+            // Newest version wins, local shard takes precedence on ties
+            match points.entry(point.id.clone()) {
+                Entry::Occupied(mut e) if e.get().version < point.version => {
+                    e.insert(point);
+                }
+                Entry::Occupied(_) => {}
+                Entry::Vacant(e) => {
+                    e.insert(point);
+                }
+            }
+        }
+
+        if read_repair && responses.len() > 1 {
+            self.repair_stale_replicas(&responses, &points);
+        }
+
+        Ok(points.into_values().collect())
+    }
+
+    /// Pushes the newest version of the points to the replicas that returned an older
+    /// version or no point at all. Doesn't wait for the replicas to be updated.
+    fn repair_stale_replicas(
+        &self,
+        responses: &[(ReplicaId, Vec<Point>)],
+        newest_points: &HashMap<PointId, Point>,
+    ) {
+        for (replica_id, replica_points) in responses {
+            let versions = replica_points
+                .iter()
+                .map(|point| (&point.id, point.version))
+                .collect::<HashMap<_, _>>();
+
+            let stale_points = newest_points
+                .values()
+                .filter(|point| {
+                    versions
+                        .get(&point.id)
+                        .is_none_or(|version| *version < point.version)
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if stale_points.is_empty() {
+                continue;
+            }
+
+            let Some(replica) = self.replica(*replica_id) else {
+                continue;
+            };
+
+            let counters = self.read_repairs.clone();
+            let shard_id = self.local.id;
+            let replica_id = *replica_id;
+            let num_points = stale_points.len() as u64;
+
+            tokio::spawn(async move {
+                match replica.upsert_points(stale_points).await {
+                    Ok(()) => {
+                        counters
+                            .repaired_points
+                            .fetch_add(num_points, Ordering::Relaxed);
+                    }
+                    Err(e) => {
+                        counters
+                            .failed_points
+                            .fetch_add(num_points, Ordering::Relaxed);
+                        eprintln!(
+                            "Failed to repair {num_points} points of shard {shard_id} on {replica_id:?}: {e}"
+                        );
+                    }
+                }
+            });
         }
+    }
+}
+
+/// Target routing of a collection while its shard count is being changed.
+///
+/// Writes are routed with the target ring right away, while reads check both the old and
+/// the new owner until all the points are migrated and resharding is finalized.
+pub struct ReshardingState {
+    pub shard_count: u32,
+    ring: HashRing,
+}
+
+/// How the points of a collection are distributed over its shards
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShardingMethod {
+    /// Points are hashed over all the shards
+    #[default]
+    Auto,
+    /// Shards are created per shard key and points are hashed over the shards of their key
+    Custom,
+}
 
-        final_results
+/// Shards an operation is routed to
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ShardSelector {
+    /// Hash the point ids over the shards of the collection
+    #[default]
+    Auto,
+    /// Hash the point ids over the shards of the key, see [`ShardingMethod::Custom`]
+    Key(ShardKey),
+    /// Only this shard, used by replicas of the shard
+    Shard(ShardId),
+}
+
+impl From<Option<ShardKey>> for ShardSelector {
+    fn from(shard_key: Option<ShardKey>) -> Self {
+        shard_key.map_or(ShardSelector::Auto, ShardSelector::Key)
     }
 }
 
 pub struct ReplicaHolder {
     pub shards: HashMap<ShardId, ReplicaSet>,
-    ring: hashring::HashRing<ShardId>,
+    ring: HashRing,
+    ring_config: RingConfig,
+    resharding: Option<ReshardingState>,
+    sharding_method: ShardingMethod,
+    /// Shards of each key, only used with [`ShardingMethod::Custom`]
+    key_shards: HashMap<ShardKey, KeyShards>,
+}
+
+struct KeyShards {
+    shard_ids: Vec<ShardId>,
+    ring: HashRing,
+}
+
+impl KeyShards {
+    fn new(mut shard_ids: Vec<ShardId>, ring_config: &RingConfig) -> Self {
+        shard_ids.sort();
+        let ring = HashRing::new(ring_config, shard_ids.iter().copied());
+        KeyShards { shard_ids, ring }
+    }
 }
 
 impl ReplicaHolder {
-    pub fn new(shards: HashMap<ShardId, ReplicaSet>) -> Self {
-        let mut ring = hashring::HashRing::new();
-        for shard_id in shards.keys() {
-            ring.add(*shard_id);
+    pub fn new(shards: HashMap<ShardId, ReplicaSet>, ring_config: RingConfig) -> Self {
+        let ring = HashRing::new(&ring_config, shards.keys().copied());
+
+        ReplicaHolder {
+            shards,
+            ring,
+            ring_config,
+            resharding: None,
+            sharding_method: ShardingMethod::Auto,
+            key_shards: HashMap::new(),
         }
+    }
+
+    /// Holder of a collection with [`ShardingMethod::Custom`], `shards` must contain the shards
+    /// of every key.
+    pub fn with_shard_keys(
+        shards: HashMap<ShardId, ReplicaSet>,
+        shard_keys: &BTreeMap<ShardKey, Vec<ShardId>>,
+        ring_config: RingConfig,
+    ) -> Self {
+        let key_shards = shard_keys
+            .iter()
+            .map(|(shard_key, shard_ids)| {
+                (
+                    shard_key.clone(),
+                    KeyShards::new(shard_ids.clone(), &ring_config),
+                )
+            })
+            .collect();
 
-        ReplicaHolder { shards, ring }
+        ReplicaHolder {
+            shards,
+            ring: HashRing::default(),
+            ring_config,
+            resharding: None,
+            sharding_method: ShardingMethod::Custom,
+            key_shards,
+        }
     }
 
     pub fn dummy() -> Self {
         ReplicaHolder {
             shards: HashMap::new(),
-            ring: hashring::HashRing::new(),
+            ring: HashRing::default(),
+            ring_config: RingConfig::default(),
+            resharding: None,
+            sharding_method: ShardingMethod::Auto,
+            key_shards: HashMap::new(),
+        }
+    }
+
+    pub fn sharding_method(&self) -> ShardingMethod {
+        self.sharding_method
+    }
+
+    /// Adds the shards of a new key, see [`ShardingMethod::Custom`]
+    pub fn add_shard_key(
+        &mut self,
+        shard_key: ShardKey,
+        new_shards: HashMap<ShardId, ReplicaSet>,
+    ) -> Result<(), StorageError> {
+        if self.sharding_method != ShardingMethod::Custom {
+            return Err(StorageError::BadInput(
+                "Shard keys require a collection with custom sharding".to_string(),
+            ));
+        }
+
+        if self.key_shards.contains_key(&shard_key) {
+            return Err(StorageError::BadInput(format!(
+                "Shard key '{shard_key}' already exists"
+            )));
+        }
+
+        if new_shards.is_empty() {
+            return Err(StorageError::BadInput(
+                "Shard key needs at least one shard".to_string(),
+            ));
+        }
+
+        if let Some(shard_id) = new_shards.keys().find(|id| self.shards.contains_key(id)) {
+            return Err(StorageError::ServiceError(format!(
+                "Shard {shard_id} already exists"
+            )));
         }
+
+        let key_shards = KeyShards::new(new_shards.keys().copied().collect(), &self.ring_config);
+        self.key_shards.insert(shard_key, key_shards);
+        self.shards.extend(new_shards);
+
+        Ok(())
+    }
+
+    pub fn resharding(&self) -> Option<&ReshardingState> {
+        self.resharding.as_ref()
+    }
+
+    /// Starts routing writes to a ring of `shard_count` shards.
+    /// `new_shards` must contain the shards that don't exist yet in the current ring.
+    pub fn start_resharding(
+        &mut self,
+        shard_count: u32,
+        new_shards: HashMap<ShardId, ReplicaSet>,
+    ) -> Result<(), StorageError> {
+        if self.resharding.is_some() {
+            return Err(StorageError::BadInput(
+                "Resharding is already in progress".to_string(),
+            ));
+        }
+
+        if shard_count == 0 {
+            return Err(StorageError::BadInput(
+                "Shard count must be greater than 0".to_string(),
+            ));
+        }
+
+        for (shard_id, replica_set) in new_shards {
+            if shard_id >= shard_count || self.shards.contains_key(&shard_id) {
+                return Err(StorageError::ServiceError(format!(
+                    "Unexpected shard {shard_id} while resharding to {shard_count} shards"
+                )));
+            }
+            self.shards.insert(shard_id, replica_set);
+        }
+
+        if let Some(missing) = (0..shard_count).find(|id| !self.shards.contains_key(id)) {
+            return Err(StorageError::ServiceError(format!(
+                "Shard {missing} is missing while resharding to {shard_count} shards"
+            )));
+        }
+
+        self.resharding = Some(ReshardingState {
+            shard_count,
+            ring: HashRing::new(&self.ring_config, 0..shard_count),
+        });
+
+        Ok(())
+    }
+
+    /// Switches routing to the target ring and returns the shards that are not part of it anymore.
+    /// Points must be migrated before calling this, otherwise they become unreachable.
+    pub fn finish_resharding(&mut self) -> Result<Vec<ReplicaSet>, StorageError> {
+        let Some(resharding) = self.resharding.take() else {
+            return Err(StorageError::BadInput(
+                "Resharding is not in progress".to_string(),
+            ));
+        };
+
+        self.ring = resharding.ring;
+
+        let dropped_shard_ids = self
+            .shards
+            .keys()
+            .filter(|shard_id| **shard_id >= resharding.shard_count)
+            .copied()
+            .collect::<Vec<_>>();
+
+        Ok(dropped_shard_ids
+            .into_iter()
+            .filter_map(|shard_id| self.shards.remove(&shard_id))
+            .collect())
+    }
+
+    /// Ring the points are placed with once resharding is over, `None` with shard keys
+    pub fn placement_ring(&self) -> Option<HashRing> {
+        match self.sharding_method {
+            ShardingMethod::Auto => Some(self.ring.clone()),
+            ShardingMethod::Custom => None,
+        }
+    }
+
+    /// Shard that owns the point. Uses the target ring if resharding is in progress.
+    pub fn target_shard(&self, point_id: &PointId) -> Result<ShardId, StorageError> {
+        let ring = match &self.resharding {
+            Some(resharding) => &resharding.ring,
+            None => &self.ring,
+        };
+
+        ring.get(point_id)
+            .ok_or_else(|| StorageError::ServiceError("No shards found".to_string()))
     }
 
     pub async fn get_replica_set(&self, shard_id: ShardId) -> Result<&ReplicaSet, StorageError> {
@@ -130,60 +645,204 @@ impl ReplicaHolder {
                 continue; // Skip if remote shard already exists
             }
 
+            replica_set.remotes.push(RemoteShard::new(
+                *shard_id,
+                collection.clone(),
+                peer_id,
+                replica_set.channel_service.clone(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Drops the remote replicas held by the peer, returns how many were dropped.
+    pub fn remove_remote_shards(&mut self, peer_id: PeerId) -> usize {
+        let mut removed = 0;
+        for replica_set in self.shards.values_mut() {
+            let num_remotes = replica_set.remotes.len();
             replica_set
                 .remotes
-                .push(RemoteShard::new(*shard_id, collection.clone(), peer_id));
+                .retain(|remote| remote.peer_id != peer_id);
+            removed += num_remotes - replica_set.remotes.len();
         }
+        removed
+    }
 
-        // ToDo: What happens to hashring if shard already exists when you add?
-        // self.ring.add(shard_id);
-
-        Ok(())
+    fn key_shards(&self, shard_key: &ShardKey) -> Result<&KeyShards, StorageError> {
+        self.key_shards.get(shard_key).ok_or_else(|| {
+            StorageError::BadInput(format!("Shard key '{shard_key}' does not exist"))
+        })
     }
 
+    /// Groups the points by the shard they are written to
     pub fn select_shards(
         &self,
         point_ids: &[PointId],
+        shard_selector: &ShardSelector,
     ) -> Result<HashMap<ShardId, Vec<PointId>>, StorageError> {
+        if let ShardSelector::Shard(shard_id) = shard_selector {
+            if !self.shards.contains_key(shard_id) {
+                return Err(StorageError::BadInput(format!(
+                    "Shard {shard_id} not found"
+                )));
+            }
+            return Ok(HashMap::from([(*shard_id, point_ids.to_vec())]));
+        }
+
+        let key_shards = match (self.sharding_method, shard_selector) {
+            (ShardingMethod::Auto, ShardSelector::Key(shard_key)) => {
+                return Err(StorageError::BadInput(format!(
+                    "Shard key '{shard_key}' given, but the collection doesn't use custom sharding"
+                )));
+            }
+            (ShardingMethod::Custom, ShardSelector::Key(shard_key)) => {
+                Some(self.key_shards(shard_key)?)
+            }
+            (ShardingMethod::Custom, _) => {
+                return Err(StorageError::BadInput(
+                    "Collection uses custom sharding, a shard key is required".to_string(),
+                ));
+            }
+            (ShardingMethod::Auto, _) => None,
+        };
+
         let mut shards_to_point_ids = HashMap::new();
         for point_id in point_ids {
-            let shard_id = self
-                .ring
-                .get(&point_id)
-                .ok_or_else(|| StorageError::ServiceError("No shards found".to_string()))?;
+            let shard_id = match key_shards {
+                Some(key_shards) => key_shards
+                    .ring
+                    .get(point_id)
+                    .ok_or_else(|| StorageError::ServiceError("No shards found".to_string()))?,
+                None => self.target_shard(point_id)?,
+            };
 
             shards_to_point_ids
-                .entry(*shard_id)
+                .entry(shard_id)
                 .or_insert_with(Vec::new)
                 .push(point_id.clone());
         }
         Ok(shards_to_point_ids)
     }
+
+    /// Same as [`Self::select_shards`] but also includes the previous owner of each point
+    /// while resharding, since the point might not have been migrated yet.
+    /// Without a shard key, reads of a collection with custom sharding go to every shard.
+    pub fn select_read_shards(
+        &self,
+        point_ids: &[PointId],
+        shard_selector: &ShardSelector,
+    ) -> Result<HashMap<ShardId, Vec<PointId>>, StorageError> {
+        if self.sharding_method == ShardingMethod::Custom && *shard_selector == ShardSelector::Auto
+        {
+            return Ok(self
+                .shards
+                .keys()
+                .map(|shard_id| (*shard_id, point_ids.to_vec()))
+                .collect());
+        }
+
+        let mut shards_to_point_ids = self.select_shards(point_ids, shard_selector)?;
+
+        if self.resharding.is_none() || *shard_selector != ShardSelector::Auto {
+            return Ok(shards_to_point_ids);
+        }
+
+        for point_id in point_ids {
+            let old_shard_id = self
+                .ring
+                .get(point_id)
+                .ok_or_else(|| StorageError::ServiceError("No shards found".to_string()))?;
+
+            if old_shard_id != self.target_shard(point_id)? {
+                shards_to_point_ids
+                    .entry(old_shard_id)
+                    .or_insert_with(Vec::new)
+                    .push(point_id.clone());
+            }
+        }
+
+        Ok(shards_to_point_ids)
+    }
+
+    pub fn ring_config(&self) -> &RingConfig {
+        &self.ring_config
+    }
+
+    /// Fraction of the hash space owned by each shard, within its key with custom sharding.
+    /// Uses the target ring if resharding is in progress.
+    pub fn ring_shares(&self) -> BTreeMap<ShardId, f64> {
+        if self.sharding_method == ShardingMethod::Custom {
+            return self
+                .key_shards
+                .values()
+                .flat_map(|key_shards| key_shards.ring.shares())
+                .collect();
+        }
+
+        match &self.resharding {
+            Some(resharding) => resharding.ring.shares(),
+            None => self.ring.shares(),
+        }
+    }
+
+    /// Shards scanned when reading all the points
+    pub fn selected_shard_ids(
+        &self,
+        shard_selector: &ShardSelector,
+    ) -> Result<Vec<ShardId>, StorageError> {
+        match shard_selector {
+            ShardSelector::Auto => Ok(self.shards.keys().copied().collect()),
+            ShardSelector::Shard(shard_id) => Ok(vec![*shard_id]),
+            ShardSelector::Key(shard_key) => {
+                if self.sharding_method != ShardingMethod::Custom {
+                    return Err(StorageError::BadInput(format!(
+                        "Shard key '{shard_key}' given, but the collection doesn't use custom sharding"
+                    )));
+                }
+                Ok(self.key_shards(shard_key)?.shard_ids.clone())
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::storage::{clock::HybridLogicalClock, segment::StorageType};
 
     #[tokio::test]
     async fn test_shard_routing() {
         let tmp_dir = tempfile::tempdir().unwrap();
 
-        let s0 = LocalShard::init(tmp_dir.path().join("0"), 0);
-        let s1 = LocalShard::init(tmp_dir.path().join("1"), 1);
+        let s0 = LocalShard::init(tmp_dir.path().join("0"), 0, StorageType::Sled);
+        let s1 = LocalShard::init(tmp_dir.path().join("1"), 1, StorageType::Sled);
 
-        let shard_holder = ReplicaHolder::new(HashMap::from_iter([
-            (0, ReplicaSet::new(s0, vec![], "c1".to_string())),
-            (1, ReplicaSet::new(s1, vec![], "c1".to_string())),
-        ]));
+        // Single node per shard, see `test_virtual_nodes` for the default ring
+        let shard_holder = ReplicaHolder::new(
+            HashMap::from_iter([
+                (
+                    0,
+                    ReplicaSet::new(s0, vec![], "c1".to_string(), ChannelService::default()),
+                ),
+                (
+                    1,
+                    ReplicaSet::new(s1, vec![], "c1".to_string(), ChannelService::default()),
+                ),
+            ]),
+            RingConfig::legacy(),
+        );
 
         let shards_to_point_ids = shard_holder
-            .select_shards(&[
-                PointId::Id(1),
-                PointId::Id(2),
-                PointId::Id(100),
-                PointId::Uuid("dummy-uuid".to_string()),
-            ])
+            .select_shards(
+                &[
+                    PointId::Id(1),
+                    PointId::Id(2),
+                    PointId::Id(100),
+                    PointId::Uuid("5c56c793-69f3-4fbf-87e6-c4bf54c28c26".parse().unwrap()),
+                ],
+                &ShardSelector::Auto,
+            )
             .unwrap();
 
         let expected_grouping = HashMap::from_iter([
@@ -192,7 +851,7 @@ mod tests {
                 vec![
                     PointId::Id(1),
                     PointId::Id(100),
-                    PointId::Uuid("dummy-uuid".to_string()),
+                    PointId::Uuid("5c56c793-69f3-4fbf-87e6-c4bf54c28c26".parse().unwrap()),
                 ],
             ),
             (1, vec![PointId::Id(2)]),
@@ -200,4 +859,245 @@ mod tests {
 
         assert_eq!(shards_to_point_ids, expected_grouping);
     }
+
+    #[tokio::test]
+    async fn test_custom_shard_routing() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let replica_set = |shard_id: ShardId| {
+            let local = LocalShard::init(
+                tmp_dir.path().join(shard_id.to_string()),
+                shard_id,
+                StorageType::Sled,
+            );
+            ReplicaSet::new(local, vec![], "c1".to_string(), ChannelService::default())
+        };
+
+        let mut shard_holder = ReplicaHolder::with_shard_keys(
+            HashMap::from_iter([(0, replica_set(0)), (1, replica_set(1))]),
+            &BTreeMap::from([("tenant-a".to_string(), vec![0, 1])]),
+            RingConfig::default(),
+        );
+        shard_holder
+            .add_shard_key(
+                "tenant-b".to_string(),
+                HashMap::from_iter([(2, replica_set(2))]),
+            )
+            .unwrap();
+        assert!(shard_holder
+            .add_shard_key(
+                "tenant-b".to_string(),
+                HashMap::from_iter([(3, replica_set(3))]),
+            )
+            .is_err());
+
+        let point_ids = [PointId::Id(1), PointId::Id(2), PointId::Id(100)];
+
+        // Writes need a key
+        assert!(shard_holder
+            .select_shards(&point_ids, &ShardSelector::Auto)
+            .is_err());
+        assert!(shard_holder
+            .select_shards(&point_ids, &ShardSelector::Key("unknown".to_string()))
+            .is_err());
+
+        let selected = shard_holder
+            .select_shards(&point_ids, &ShardSelector::Key("tenant-b".to_string()))
+            .unwrap();
+        assert_eq!(selected, HashMap::from_iter([(2, point_ids.to_vec())]));
+
+        // Points of a key are spread over its own shards only
+        let selected = shard_holder
+            .select_shards(&point_ids, &ShardSelector::Key("tenant-a".to_string()))
+            .unwrap();
+        assert!(selected.keys().all(|shard_id| [0, 1].contains(shard_id)));
+        assert_eq!(selected.values().map(Vec::len).sum::<usize>(), 3);
+
+        // Reads without a key go to every shard
+        let mut read_shards = shard_holder
+            .select_read_shards(&point_ids, &ShardSelector::Auto)
+            .unwrap()
+            .into_keys()
+            .collect::<Vec<_>>();
+        read_shards.sort();
+        assert_eq!(read_shards, vec![0, 1, 2]);
+
+        let mut key_shards = shard_holder
+            .selected_shard_ids(&ShardSelector::Key("tenant-a".to_string()))
+            .unwrap();
+        key_shards.sort();
+        assert_eq!(key_shards, vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_read_consistency() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let local = LocalShard::init(tmp_dir.path().join("0"), 0, StorageType::Sled);
+        local
+            .upsert_points(vec![Point {
+                id: PointId::Id(1),
+                payload: serde_json::json!({}),
+                version: 0,
+            }])
+            .await
+            .unwrap();
+
+        // Peer without a known address, so all requests to it fail
+        let replica_set = ReplicaSet::new(
+            local,
+            vec![999],
+            "c1".to_string(),
+            ChannelService::default(),
+        );
+
+        let ids = Some(vec![PointId::Id(1)]);
+        for (read_consistency, expected_ok) in [
+            ("one", true),
+            ("1", true),
+            ("quorum", false),
+            ("majority", false),
+            ("all", false),
+            ("3", false),
+        ] {
+            let read_consistency = read_consistency.parse::<ReadConsistency>().unwrap();
+            let result = replica_set
+                .get_points(ids.clone(), read_consistency, false, true)
+                .await;
+            assert_eq!(result.is_ok(), expected_ok, "{read_consistency}");
+        }
+
+        assert!("0".parse::<ReadConsistency>().is_err());
+        assert!("some".parse::<ReadConsistency>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_last_write_wins() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let local = LocalShard::init(tmp_dir.path().join("0"), 0, StorageType::Sled);
+
+        let point = |version: u64| Point {
+            id: PointId::Id(1),
+            payload: serde_json::json!({ "version": version }),
+            version,
+        };
+
+        for (version, expected_version) in [(5, 5), (3, 5), (5, 5), (7, 7)] {
+            local.upsert_points(vec![point(version)]).await.unwrap();
+
+            let stored = local.get_points(Some(vec![PointId::Id(1)])).await.unwrap();
+            assert_eq!(stored[0].version, expected_version);
+            assert_eq!(stored[0].payload["version"], expected_version);
+        }
+
+        let clock = HybridLogicalClock::default();
+        let first = clock.tick();
+        clock.observe(first + 1_000);
+        assert!(clock.tick() > first + 1_000);
+    }
+
+    #[tokio::test]
+    async fn test_read_repair() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let local = LocalShard::init(tmp_dir.path().join("0"), 0, StorageType::Sled);
+        let replica_set = ReplicaSet::new(
+            local,
+            vec![999],
+            "c1".to_string(),
+            ChannelService::default(),
+        );
+
+        let point = |version: u64| Point {
+            id: PointId::Id(1),
+            payload: serde_json::json!({}),
+            version,
+        };
+        replica_set
+            .local
+            .upsert_points(vec![point(1)])
+            .await
+            .unwrap();
+
+        // Remote replica answered with a newer version than the local one
+        let responses = vec![
+            (ReplicaId::Local, vec![point(1)]),
+            (ReplicaId::Remote(999), vec![point(2)]),
+        ];
+        let newest = HashMap::from([(PointId::Id(1), point(2))]);
+        replica_set.repair_stale_replicas(&responses, &newest);
+
+        for _ in 0..100 {
+            if replica_set
+                .read_repairs
+                .repaired_points
+                .load(Ordering::Relaxed)
+                > 0
+            {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let stored = replica_set
+            .local
+            .get_points(Some(vec![PointId::Id(1)]))
+            .await
+            .unwrap();
+        assert_eq!(stored[0].version, 2);
+        assert_eq!(
+            replica_set
+                .read_repairs
+                .repaired_points
+                .load(Ordering::Relaxed),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parallel_fan_out() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let local = LocalShard::init(tmp_dir.path().join("0"), 0, StorageType::Sled);
+        let replica_set = ReplicaSet::new(
+            local,
+            vec![998, 999],
+            "c1".to_string(),
+            ChannelService::default(),
+        );
+
+        let failed_peers = Arc::new(std::sync::Mutex::new(vec![]));
+        let on_remote_failure: OnRemoteFailure = {
+            let failed_peers = failed_peers.clone();
+            Arc::new(move |peer_id, _| failed_peers.lock().unwrap().push(peer_id))
+        };
+
+        let upsert = |shard: Arc<dyn ShardOperationTrait + Send + Sync>| {
+            async move {
+                shard
+                    .upsert_points(vec![Point {
+                        id: PointId::Id(1),
+                        payload: serde_json::json!({}),
+                        version: 1,
+                    }])
+                    .await
+            }
+            .boxed()
+        };
+
+        // Returns as soon as the local shard succeeded
+        let results = replica_set
+            .execute_cluster_operation(upsert, 1, false, Some(on_remote_failure.clone()))
+            .await;
+        assert!(results
+            .iter()
+            .any(|(replica_id, result)| *replica_id == ReplicaId::Local && result.is_ok()));
+
+        // Waits for every replica when the factor can't be met
+        let results = replica_set
+            .execute_cluster_operation(upsert, 2, false, Some(on_remote_failure))
+            .await;
+        assert_eq!(results.len(), 3);
+        assert_eq!(results.iter().filter(|(_, r)| r.is_ok()).count(), 1);
+
+        // Failures are reported before results are sent back
+        let failed_peers = failed_peers.lock().unwrap().clone();
+        assert!(failed_peers.contains(&998) && failed_peers.contains(&999));
+    }
 }
@@ -1,7 +1,10 @@
 use crate::{
-    api::grpc::p2p_grpc_schema::{
-        points_internal_client::PointsInternalClient, GetPointsRequest, Point as PointGrpc,
-        UpsertPointsRequest,
+    api::grpc::{
+        conversions::{point_ids_to_grpc, points_from_grpc, points_to_grpc},
+        p2p_grpc_schema::{
+            points_internal_client::PointsInternalClient, GetPointsInRangesRequest,
+            GetPointsRequest, GetRangeHashesRequest, PointId as GrpcPointId, UpsertPointsRequest,
+        },
     },
     channel_service::ChannelService,
     storage::{
@@ -12,55 +15,59 @@ use crate::{
     },
     types::{PeerId, ShardId},
 };
-use std::{collections::HashMap, future::Future, str::FromStr, sync::Arc};
-use tokio::sync::RwLock;
+use std::{collections::HashSet, future::Future};
 use tonic::{async_trait, transport::Channel, Request, Status};
 
+#[derive(Clone)]
 pub struct RemoteShard {
     pub id: ShardId,
     pub collection: CollectionName,
     pub peer_id: PeerId,
+    /// Shared by the whole node, peer addresses are kept up to date by consensus
+    channel_service: ChannelService,
 }
 
 impl RemoteShard {
     /// Init a remote shard in memory that can be used to communicate with replicas on a remote peer.
-    pub fn new(id: ShardId, collection: CollectionName, peer_id: PeerId) -> Self {
+    pub fn new(
+        id: ShardId,
+        collection: CollectionName,
+        peer_id: PeerId,
+        channel_service: ChannelService,
+    ) -> Self {
         RemoteShard {
             id,
             collection,
             peer_id,
+            channel_service,
         }
     }
 
-    async fn current_address(
-        &self,
-        channel_service: &ChannelService,
-    ) -> CollectionResult<http::Uri> {
-        let guard_peer_addresses = channel_service.id_to_address.read().await;
-        let peer_address = guard_peer_addresses.get(&self.peer_id).cloned();
-
-        println!(
-            "Remote shard {}:{} has address {:?}",
-            self.peer_id, self.id, peer_address
-        );
-
-        match peer_address {
-            Some(uri) => Ok(uri),
-            None => Err(CollectionError::ServiceError(format!(
-                "Peer {} does not have an address in the channel service",
-                self.peer_id
-            ))),
-        }
+    async fn current_address(&self) -> CollectionResult<http::Uri> {
+        self.channel_service.resolve_address(self.peer_id).await
+    }
+
+    /// False if the failure detector considers the peer down
+    pub fn is_alive(&self) -> bool {
+        self.channel_service.failure_detector.is_alive(self.peer_id)
     }
 
     async fn with_points_client<T, O: Future<Output = Result<T, Status>>>(
         &self,
-        channel_service: ChannelService,
         f: impl Fn(PointsInternalClient<Channel>) -> O,
     ) -> CollectionResult<T> {
-        let uri = self.current_address(&channel_service).await?;
+        // Fail fast instead of waiting for the request to time out
+        if !self.is_alive() {
+            return Err(CollectionError::ServiceError(format!(
+                "Peer {} is down, skipping remote shard {}",
+                self.peer_id, self.id
+            )));
+        }
 
-        let channel = channel_service
+        let uri = self.current_address().await?;
+
+        let channel = self
+            .channel_service
             .channel_pool
             .get_or_create_channel(uri)
             .await?;
@@ -74,19 +81,6 @@ impl RemoteShard {
             ))
         })
     }
-
-    pub fn get_channel_service(&self) -> ChannelService {
-        let mut channel_service = ChannelService::default();
-
-        let inner_map: HashMap<_, _> = HashMap::from_iter(vec![
-            (101, http::Uri::from_str("http://0.0.0.0:5001").unwrap()),
-            (102, http::Uri::from_str("http://0.0.0.0:5002").unwrap()),
-            (103, http::Uri::from_str("http://0.0.0.0:5003").unwrap()),
-        ]);
-        channel_service.id_to_address = Arc::new(RwLock::new(inner_map));
-
-        channel_service
-    }
 }
 
 #[async_trait]
@@ -95,21 +89,10 @@ impl ShardOperationTrait for RemoteShard {
         let return_all = ids.is_none();
         let ids = ids.unwrap_or_default();
 
-        let ids = ids
-            .into_iter()
-            .filter_map(|id| {
-                if let PointId::Id(id) = id {
-                    Some(id)
-                } else {
-                    None // Skip non-ID point IDs
-                }
-            })
-            .collect::<Vec<_>>();
-
-        let channel_service = self.get_channel_service();
+        let ids = point_ids_to_grpc(ids);
 
         let get_points_response = self
-            .with_points_client(channel_service, |mut client| {
+            .with_points_client(|mut client| {
                 println!(
                     "Calling PointsInternalClient::get_points on remote shard {}:{}",
                     self.peer_id, self.id
@@ -121,6 +104,8 @@ impl ShardOperationTrait for RemoteShard {
                         ids,
                         return_all,
                         shard_id: Some(self.id), // Ask the other node to return points only for this shard
+                        offset: None,
+                        limit: None,
                     });
 
                     client.get_points(request).await
@@ -129,43 +114,19 @@ impl ShardOperationTrait for RemoteShard {
             .await?
             .into_inner();
 
-        let points = get_points_response
-            .points
-            .into_iter()
-            .map(|p| Point {
-                id: PointId::Id(p.id),
-                payload: serde_json::from_str(&p.payload).unwrap(),
-            })
-            .collect::<Vec<_>>();
-
-        Ok(points)
+        Ok(points_from_grpc(get_points_response.points)?)
     }
 
     async fn upsert_points(&self, points: Vec<Point>) -> CollectionResult<()> {
-        let channel_service = self.get_channel_service();
-
         let _upsert_points_response = self
-            .with_points_client(channel_service, |mut client| {
+            .with_points_client(|mut client| {
                 let points = points.clone();
                 async move {
                     client
                         .upsert_points(Request::new(UpsertPointsRequest {
                             collection_name: self.collection.clone(),
-                            shard_id: None,
-                            points: points
-                                .into_iter()
-                                .filter_map(|p| {
-                                    if let PointId::Id(p_id) = p.id {
-                                        // Only include points with PointId::Id
-                                        Some(PointGrpc {
-                                            id: p_id,
-                                            payload: p.payload.to_string(),
-                                        })
-                                    } else {
-                                        None // Skip UUIDs for now
-                                    }
-                                })
-                                .collect(),
+                            shard_id: Some(self.id),
+                            points: points_to_grpc(points),
                         }))
                         .await
                 }
@@ -175,4 +136,94 @@ impl ShardOperationTrait for RemoteShard {
 
         Ok(()) // Placeholder for actual remote shard logic
     }
+
+    async fn get_points_page(
+        &self,
+        offset: Option<PointId>,
+        limit: usize,
+    ) -> CollectionResult<Vec<Point>> {
+        let offset = offset.map(GrpcPointId::from);
+        let limit = u32::try_from(limit).unwrap_or(u32::MAX);
+
+        let response = self
+            .with_points_client(|mut client| {
+                let offset = offset.clone();
+                async move {
+                    client
+                        .get_points(Request::new(GetPointsRequest {
+                            collection_name: self.collection.clone(),
+                            ids: vec![],
+                            return_all: false,
+                            shard_id: Some(self.id),
+                            offset,
+                            limit: Some(limit),
+                        }))
+                        .await
+                }
+            })
+            .await?
+            .into_inner();
+
+        Ok(points_from_grpc(response.points)?)
+    }
+
+    async fn range_hashes(&self, num_ranges: u32) -> CollectionResult<Vec<u64>> {
+        let response = self
+            .with_points_client(|mut client| async move {
+                client
+                    .get_range_hashes(Request::new(GetRangeHashesRequest {
+                        collection_name: self.collection.clone(),
+                        shard_id: self.id,
+                        num_ranges,
+                    }))
+                    .await
+            })
+            .await?
+            .into_inner();
+
+        if response.hashes.len() != num_ranges as usize {
+            return Err(CollectionError::ServiceError(format!(
+                "Remote shard {}:{} returned {} range hashes instead of {num_ranges}",
+                self.peer_id,
+                self.id,
+                response.hashes.len()
+            )));
+        }
+
+        Ok(response.hashes)
+    }
+
+    async fn get_points_in_ranges(
+        &self,
+        ranges: HashSet<u32>,
+        num_ranges: u32,
+        offset: Option<PointId>,
+        limit: usize,
+    ) -> CollectionResult<Vec<Point>> {
+        let ranges = ranges.into_iter().collect::<Vec<_>>();
+        let offset = offset.map(GrpcPointId::from);
+        let limit = u32::try_from(limit).unwrap_or(u32::MAX);
+
+        let response = self
+            .with_points_client(|mut client| {
+                let ranges = ranges.clone();
+                let offset = offset.clone();
+                async move {
+                    client
+                        .get_points_in_ranges(Request::new(GetPointsInRangesRequest {
+                            collection_name: self.collection.clone(),
+                            shard_id: self.id,
+                            num_ranges,
+                            ranges,
+                            offset,
+                            limit: Some(limit),
+                        }))
+                        .await
+                }
+            })
+            .await?
+            .into_inner();
+
+        Ok(points_from_grpc(response.points)?)
+    }
 }
@@ -0,0 +1,232 @@
+use crate::{
+    storage::{error::StorageError, segment::PointId},
+    types::ShardId,
+};
+use serde::{Deserialize, Serialize};
+use siphasher::sip::SipHasher;
+use std::{collections::BTreeMap, hash::Hasher};
+use uuid::Uuid;
+
+/// Virtual nodes per unit of weight of the shards of new collections
+pub const DEFAULT_VIRTUAL_NODES: u32 = 64;
+
+const MAX_VIRTUAL_NODES: u32 = 1_024;
+const MAX_SHARD_WEIGHT: u32 = 100;
+
+/// Placement of the shards on the ring, persisted with the collection so routing never changes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RingConfig {
+    /// Nodes placed on the ring for a shard of weight 1
+    pub virtual_nodes: u32,
+    /// Shards with a weight of 2 get twice as many virtual nodes, and so twice as many points.
+    /// Missing shards have a weight of 1.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub shard_weights: BTreeMap<ShardId, u32>,
+}
+
+impl Default for RingConfig {
+    fn default() -> Self {
+        RingConfig {
+            virtual_nodes: DEFAULT_VIRTUAL_NODES,
+            shard_weights: BTreeMap::new(),
+        }
+    }
+}
+
+impl RingConfig {
+    /// Single node per shard, which places shards exactly like the `hashring` crate used before.
+    /// Collections created before virtual nodes existed keep this config.
+    pub fn legacy() -> Self {
+        RingConfig {
+            virtual_nodes: 1,
+            shard_weights: BTreeMap::new(),
+        }
+    }
+
+    pub fn weight(&self, shard_id: ShardId) -> u32 {
+        self.shard_weights.get(&shard_id).copied().unwrap_or(1)
+    }
+
+    pub fn validate(&self) -> Result<(), StorageError> {
+        if !(1..=MAX_VIRTUAL_NODES).contains(&self.virtual_nodes) {
+            return Err(StorageError::BadInput(format!(
+                "Virtual nodes must be between 1 and {MAX_VIRTUAL_NODES}"
+            )));
+        }
+
+        if let Some((shard_id, _)) = self
+            .shard_weights
+            .iter()
+            .find(|(_, weight)| !(1..=MAX_SHARD_WEIGHT).contains(*weight))
+        {
+            return Err(StorageError::BadInput(format!(
+                "Weight of shard {shard_id} must be between 1 and {MAX_SHARD_WEIGHT}"
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Consistent hashing ring, a point belongs to the first virtual node at or after its hash.
+///
+/// Hashes are computed from explicit byte encodings with SipHash-2-4 and zero keys, so they
+/// don't depend on the `Hash` implementations of the compiler or of our types.
+#[derive(Clone, Debug, Default)]
+pub struct HashRing {
+    nodes: BTreeMap<u64, ShardId>,
+}
+
+impl HashRing {
+    pub fn new(config: &RingConfig, shard_ids: impl IntoIterator<Item = ShardId>) -> Self {
+        let mut shard_ids = shard_ids.into_iter().collect::<Vec<_>>();
+        // Colliding virtual nodes go to the lowest shard, whatever the order of `shard_ids`
+        shard_ids.sort();
+
+        let mut nodes = BTreeMap::new();
+        for shard_id in shard_ids {
+            for vnode in 0..config.virtual_nodes * config.weight(shard_id) {
+                nodes.entry(node_hash(shard_id, vnode)).or_insert(shard_id);
+            }
+        }
+
+        HashRing { nodes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn get(&self, point_id: &PointId) -> Option<ShardId> {
+        let hash = point_hash(point_id);
+        self.nodes
+            .range(hash..)
+            .next()
+            .or_else(|| self.nodes.iter().next())
+            .map(|(_, shard_id)| *shard_id)
+    }
+
+    /// Fraction of the hash space owned by each shard
+    pub fn shares(&self) -> BTreeMap<ShardId, f64> {
+        let mut shares = BTreeMap::new();
+        let Some((last, _)) = self.nodes.last_key_value() else {
+            return shares;
+        };
+
+        // The first node also owns the wrapping range after the last one
+        let mut previous = *last;
+        for (hash, shard_id) in &self.nodes {
+            let range = hash.wrapping_sub(previous);
+            let share = if self.nodes.len() == 1 {
+                1.0
+            } else {
+                range as f64 / u64::MAX as f64
+            };
+            *shares.entry(*shard_id).or_insert(0.0) += share;
+            previous = *hash;
+        }
+        shares
+    }
+}
+
+/// SipHash with fixed keys, unlike `DefaultHasher` it's the same across peers and builds
+pub fn sip_hash(bytes: &[&[u8]]) -> u64 {
+    let mut hasher = SipHasher::new();
+    for bytes in bytes {
+        hasher.write(bytes);
+    }
+    hasher.finish()
+}
+
+/// The first node of a shard is hashed like `u32::hash`, which keeps legacy placements
+fn node_hash(shard_id: ShardId, vnode: u32) -> u64 {
+    if vnode == 0 {
+        sip_hash(&[&shard_id.to_le_bytes()])
+    } else {
+        sip_hash(&[&shard_id.to_le_bytes(), &vnode.to_le_bytes()])
+    }
+}
+
+/// Same bytes as the derived `Hash` of [`PointId`] on 64-bit targets when uuids were strings:
+/// discriminant, then value. Changing them would move points between shards.
+fn point_hash(point_id: &PointId) -> u64 {
+    match point_id {
+        PointId::Id(id) => sip_hash(&[&0u64.to_le_bytes(), &id.to_le_bytes()]),
+        PointId::Uuid(uuid) => sip_hash(&[
+            &1u64.to_le_bytes(),
+            uuid.hyphenated()
+                .encode_lower(&mut Uuid::encode_buffer())
+                .as_bytes(),
+            &[0xff],
+        ]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distribution(ring: &HashRing, num_points: u64) -> BTreeMap<ShardId, u64> {
+        let mut counts = BTreeMap::new();
+        for id in 0..num_points {
+            *counts
+                .entry(ring.get(&PointId::Id(id)).unwrap())
+                .or_insert(0) += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_virtual_nodes() {
+        assert!(HashRing::new(&RingConfig::default(), []).is_empty());
+        assert_eq!(
+            HashRing::new(&RingConfig::default(), []).get(&PointId::Id(1)),
+            None
+        );
+
+        let ring = HashRing::new(&RingConfig::default(), 0..4);
+        let counts = distribution(&ring, 10_000);
+        // Every shard gets 25% of the points, give or take 5%
+        assert!(counts.values().all(|count| (2_000..=3_000).contains(count)));
+
+        let shares = ring.shares();
+        assert!((shares.values().sum::<f64>() - 1.0).abs() < 1e-9);
+
+        // Routing doesn't depend on the order of the shards
+        let reversed = HashRing::new(&RingConfig::default(), (0..4).rev());
+        assert_eq!(distribution(&reversed, 10_000), counts);
+    }
+
+    #[test]
+    fn test_shard_weights() {
+        let config = RingConfig {
+            virtual_nodes: DEFAULT_VIRTUAL_NODES,
+            shard_weights: BTreeMap::from([(1, 3)]),
+        };
+        assert!(config.validate().is_ok());
+
+        let counts = distribution(&HashRing::new(&config, 0..2), 10_000);
+        assert!((6_500..=8_500).contains(&counts[&1]));
+
+        let invalid = RingConfig {
+            virtual_nodes: 0,
+            ..RingConfig::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_legacy_placement() {
+        // Placement of the `hashring` crate, with one node per shard
+        let ring = HashRing::new(&RingConfig::legacy(), [0, 1]);
+        assert_eq!(ring.get(&PointId::Id(1)), Some(0));
+        assert_eq!(ring.get(&PointId::Id(2)), Some(1));
+        assert_eq!(ring.get(&PointId::Id(100)), Some(0));
+        assert_eq!(
+            ring.get(&PointId::Uuid(
+                "5c56c793-69f3-4fbf-87e6-c4bf54c28c26".parse().unwrap()
+            )),
+            Some(0)
+        );
+    }
+}
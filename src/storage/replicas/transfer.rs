@@ -0,0 +1,95 @@
+use crate::{
+    storage::{
+        error::{CollectionError, CollectionResult},
+        replicas::{ReplicaId, ReplicaSet},
+    },
+    types::PeerId,
+};
+use futures::future::join_all;
+use std::time::Duration;
+
+/// Number of points sent to the target replicas at once during a shard transfer
+pub const TRANSFER_BATCH_SIZE: usize = 1_000;
+
+impl ReplicaSet {
+    /// Identifies the replica held by the peer, which is the local shard for this peer
+    pub fn replica_id_of(&self, peer_id: PeerId) -> ReplicaId {
+        if peer_id == self.channel_service.this_peer_id {
+            ReplicaId::Local
+        } else {
+            ReplicaId::Remote(peer_id)
+        }
+    }
+
+    /// Copies every point of the `from` replica to the `to` replicas in batches.
+    /// Versions are preserved, so points written in the meantime are never overridden by older ones.
+    /// Sends at most `points_per_second` points if set. Returns the number of transferred points.
+    pub async fn transfer(
+        &self,
+        from: ReplicaId,
+        to: &[ReplicaId],
+        points_per_second: Option<u64>,
+    ) -> CollectionResult<usize> {
+        let shard_id = self.local.id;
+
+        let source = self.replica(from).ok_or_else(|| {
+            CollectionError::ServiceError(format!("Shard {shard_id} has no replica on {from:?}"))
+        })?;
+
+        let mut targets = Vec::with_capacity(to.len());
+        for replica_id in to {
+            let Some(replica) = self.replica(*replica_id) else {
+                return Err(CollectionError::ServiceError(format!(
+                    "Shard {shard_id} has no replica on {replica_id:?}"
+                )));
+            };
+            targets.push((*replica_id, replica));
+        }
+
+        if targets.is_empty() {
+            return Err(CollectionError::ServiceError(format!(
+                "No replica to transfer shard {shard_id} to"
+            )));
+        }
+
+        // Pages are read as the transfer goes, so the shard is never loaded at once
+        let mut offset = None;
+        let mut transferred = 0;
+        loop {
+            let batch = source
+                .get_points_page(offset.clone(), TRANSFER_BATCH_SIZE)
+                .await?;
+            let Some(last) = batch.last() else {
+                break;
+            };
+            offset = Some(last.id.clone());
+
+            let results = join_all(
+                targets
+                    .iter()
+                    .map(|(_, target)| target.upsert_points(batch.clone())),
+            )
+            .await;
+
+            for ((replica_id, _), result) in targets.iter().zip(results) {
+                result.map_err(|e| {
+                    CollectionError::ServiceError(format!(
+                        "Failed to transfer shard {shard_id} to {replica_id:?}: {e}"
+                    ))
+                })?;
+            }
+            transferred += batch.len();
+
+            if let Some(points_per_second) = points_per_second.filter(|rate| *rate > 0) {
+                let delay = batch.len() as f64 / points_per_second as f64;
+                tokio::time::sleep(Duration::from_secs_f64(delay)).await;
+            }
+
+            if batch.len() < TRANSFER_BATCH_SIZE {
+                break;
+            }
+        }
+
+        Ok(transferred)
+    }
+}
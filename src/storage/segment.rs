@@ -1,20 +1,147 @@
-use crate::storage::error::StorageError;
-use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use crate::storage::{
+    error::StorageError,
+    point_encoding,
+    segment_storage::{self, Entry, SegmentStorage},
+};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Clone, Hash, Eq, PartialEq, Ord, PartialOrd, Debug)]
+/// Type tags of the segment keys, numeric ids are ordered before uuids
+const NUM_KEY: u8 = 0;
+const UUID_KEY: u8 = 1;
+/// Keys of the segment metadata, ordered after the points. Never the first byte of a legacy
+/// key, which is valid UTF-8.
+const META_KEY: u8 = 0xff;
+
+/// Written once the points of the segment are all in the current format, holds that format.
+/// Segments without it are migrated on load, see [`Segment::migrate_legacy_points`].
+pub(crate) const FORMAT_MARKER_KEY: &[u8] = &[META_KEY, b'f'];
+
+#[derive(Serialize, Clone, Hash, Eq, PartialEq, Ord, PartialOrd, Debug)]
 #[serde(untagged)]
 pub enum PointId {
     Id(u64),
-    Uuid(String),
+    Uuid(Uuid),
 }
 
 impl PointId {
     pub fn into_string(&self) -> String {
         match self {
             PointId::Id(id) => id.to_string(),
-            PointId::Uuid(uuid) => uuid.clone(),
+            PointId::Uuid(uuid) => uuid.to_string(),
+        }
+    }
+
+    /// Key of the point in a segment: the type tag then the id in big endian or the uuid bytes,
+    /// so iterating over a segment yields numeric ids by increasing value
+    pub fn to_key(&self) -> Vec<u8> {
+        let mut key = Vec::with_capacity(17);
+        match self {
+            PointId::Id(id) => {
+                key.push(NUM_KEY);
+                key.extend_from_slice(&id.to_be_bytes());
+            }
+            PointId::Uuid(uuid) => {
+                key.push(UUID_KEY);
+                key.extend_from_slice(uuid.as_bytes());
+            }
+        }
+        key
+    }
+
+    /// Inverse of [`PointId::to_key`]
+    pub fn from_key(key: &[u8]) -> Result<Self, StorageError> {
+        match (key.first(), key.len()) {
+            (Some(&NUM_KEY), 9) => Ok(PointId::Id(u64::from_be_bytes(
+                key[1..].try_into().unwrap(),
+            ))),
+            (Some(&UUID_KEY), 17) => Ok(PointId::Uuid(Uuid::from_slice(&key[1..]).unwrap())),
+            _ => Err(StorageError::ServiceError(format!(
+                "Malformed point key {key:?}"
+            ))),
+        }
+    }
+
+    /// Keys were the ids as strings before [`PointId::to_key`], they never start with a tag.
+    /// Returns `None` for tagged keys.
+    ///
+    /// Any string was accepted as an id then. Those that are neither an unsigned integer nor a
+    /// canonical uuid get the uuid v5 of the string, so that distinct ids stay distinct.
+    fn from_legacy_key(key: &[u8]) -> Option<Self> {
+        match key.first() {
+            Some(&NUM_KEY | &UUID_KEY | &META_KEY) => None,
+            _ => Some(
+                String::from_utf8_lossy(key)
+                    .parse()
+                    .unwrap_or_else(|_| PointId::Uuid(Uuid::new_v5(&LEGACY_ID_NAMESPACE, key))),
+            ),
+        }
+    }
+}
+
+/// Namespace of the uuids given to legacy ids, see [`PointId::from_legacy_key`]
+const LEGACY_ID_NAMESPACE: Uuid = Uuid::from_u128(0xa196da9c_1db1_4c71_ae73_e7ebf61cd70f);
+
+/// Only the lowercase hyphenated form is accepted. Points are placed on shards by the hash of
+/// this form, other spellings of the same uuid were distinct ids placed elsewhere before.
+fn parse_uuid(id: &str) -> Result<Uuid, String> {
+    let uuid = Uuid::parse_str(id).map_err(|e| e.to_string())?;
+    if uuid.hyphenated().to_string() != id {
+        return Err(format!("expected the lowercase hyphenated form {uuid}"));
+    }
+    Ok(uuid)
+}
+
+impl FromStr for PointId {
+    type Err = StorageError;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        if let Ok(id) = id.parse() {
+            return Ok(PointId::Id(id));
         }
+        parse_uuid(id).map(PointId::Uuid).map_err(|e| {
+            StorageError::BadInput(format!(
+                "Point id '{id}' is neither an unsigned integer nor a uuid: {e}"
+            ))
+        })
+    }
+}
+
+/// Rejects negative ids and malformed uuids with a clearer message than an untagged enum
+impl<'de> Deserialize<'de> for PointId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PointIdVisitor;
+
+        impl de::Visitor<'_> for PointIdVisitor {
+            type Value = PointId;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an unsigned integer or a uuid string")
+            }
+
+            fn visit_u64<E: de::Error>(self, id: u64) -> Result<PointId, E> {
+                Ok(PointId::Id(id))
+            }
+
+            fn visit_i64<E: de::Error>(self, id: i64) -> Result<PointId, E> {
+                u64::try_from(id)
+                    .map(PointId::Id)
+                    .map_err(|_| E::custom(format!("Point id {id} is negative")))
+            }
+
+            fn visit_str<E: de::Error>(self, id: &str) -> Result<PointId, E> {
+                parse_uuid(id)
+                    .map(PointId::Uuid)
+                    .map_err(|e| E::custom(format!("Point id '{id}' is not a valid uuid: {e}")))
+            }
+        }
+
+        deserializer.deserialize_any(PointIdVisitor)
     }
 }
 
@@ -22,55 +149,164 @@ impl PointId {
 pub struct Point {
     pub id: PointId,
     pub payload: serde_json::Value,
+    /// Assigned by the peer coordinating the write, see [`crate::storage::clock::HybridLogicalClock`].
+    /// Replicas keep the point with the highest version (last write wins).
+    #[serde(default)]
+    pub version: u64,
+}
+
+/// Engine storing the segments of a collection, chosen when the collection is created
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageType {
+    /// Sled database flushed after every write
+    #[default]
+    #[serde(alias = "disk")]
+    Sled,
+    /// Append-only log read through a memory map, synced after every write
+    Log,
+    /// Segments are never flushed and nothing is loaded on restart, for ephemeral collections
+    Memory,
+}
+
+impl StorageType {
+    pub fn is_persistent(&self) -> bool {
+        *self != StorageType::Memory
+    }
 }
 
 pub struct Segment {
     pub path: PathBuf,
-    pub db: sled::Db,
+    pub storage: Box<dyn SegmentStorage>,
     // ToDo: ID tracker, data storage, index, etc
 }
 
 impl Segment {
-    pub fn create(segments_dir: &Path) -> Result<Self, StorageError> {
+    pub fn create(segments_dir: &Path, storage: StorageType) -> Result<Self, StorageError> {
         // ToDo: Have uuid segment ID
         let path = segments_dir.join("0");
-        std::fs::create_dir_all(&path).expect("Failed to create segment directory");
-
-        let db = sled::open(&path).map_err(|e| {
-            StorageError::ServiceError(format!("Failed to open segment database: {e}"))
-        })?;
-
-        Ok(Self { path, db })
+        let storage = segment_storage::open(&path, storage)?;
+        let segment = Self { path, storage };
+        segment.write_format_marker()?;
+        Ok(segment)
     }
 
-    pub fn load(path: &PathBuf) -> Result<Self, StorageError> {
+    pub fn load(path: &Path, storage: StorageType) -> Result<Self, StorageError> {
         if !path.exists() {
             return Err(StorageError::ServiceError(format!(
                 "Segment path does not exist: {path:?}"
             )));
         }
 
-        let db = sled::open(path).expect("Failed to open segment database");
-
-        Ok(Self {
+        let segment = Self {
             path: path.to_owned(),
-            db,
-        })
+            storage: segment_storage::open(path, storage)?,
+        };
+        if segment.storage.get(FORMAT_MARKER_KEY)?.is_none() {
+            segment.migrate_legacy_points()?;
+            segment.write_format_marker()?;
+        }
+        Ok(segment)
+    }
+
+    fn write_format_marker(&self) -> Result<(), StorageError> {
+        self.storage
+            .put(FORMAT_MARKER_KEY, &[point_encoding::FORMAT_V1])?;
+        self.flush()
+    }
+
+    /// Entries of the points from `start` on, the metadata keys are left out
+    fn point_entries(
+        &self,
+        start: &[u8],
+    ) -> impl Iterator<Item = Result<Entry, StorageError>> + '_ {
+        self.storage
+            .iter_from(start)
+            .take_while(|entry| !matches!(entry, Ok((key, _)) if key.first() == Some(&META_KEY)))
+    }
+
+    /// Rewrites the points stored as JSON with the binary encoding of [`point_encoding`],
+    /// and moves the points stored under string keys to [`PointId::to_key`]
+    fn migrate_legacy_points(&self) -> Result<(), StorageError> {
+        // Collected first, the storage isn't written while it's iterated
+        let mut legacy_keys = vec![];
+        for result in self.storage.iter() {
+            let (key, value) = result?;
+            if PointId::from_legacy_key(&key).is_some() || point_encoding::is_legacy(&value) {
+                legacy_keys.push(key);
+            }
+        }
+
+        let mut migrated = 0;
+        for key in legacy_keys {
+            let Some(value) = self.storage.get(&key)? else {
+                continue;
+            };
+            let legacy_id = PointId::from_legacy_key(&key);
+            let id = match &legacy_id {
+                Some(id) => {
+                    if id.into_string().as_bytes() != key {
+                        println!(
+                            "Legacy point id '{}' of segment {:?} is stored as {}",
+                            String::from_utf8_lossy(&key),
+                            self.path,
+                            id.into_string()
+                        );
+                    }
+                    id.clone()
+                }
+                None => PointId::from_key(&key)?,
+            };
+            let point = point_encoding::decode(id, &value)?;
+            // Keeps the newest version if a previous migration was interrupted
+            self.insert_point(&point)?;
+            if legacy_id.is_some() {
+                self.storage.delete(&key)?;
+            }
+            migrated += 1;
+        }
+
+        if migrated > 0 {
+            self.flush()?;
+            println!(
+                "Migrated {migrated} legacy points of segment {:?}",
+                self.path
+            );
+        }
+        Ok(())
     }
 
+    /// Inserts points unless a newer version of them is already stored.
     pub fn insert_points(&self, points: &[Point]) -> Result<(), StorageError> {
         for point in points {
-            let key = point.id.into_string();
-            let value = serde_json::to_string(point).map_err(|e| {
-                StorageError::ServiceError(format!("Failed to serialize point: {e}"))
-            })?;
-            self.db.insert(key, value.as_str()).map_err(|e| {
-                StorageError::ServiceError(format!("Failed to insert point into segment db: {e}"))
-            })?;
-        }
-        self.db
-            .flush()
-            .map_err(|e| StorageError::ServiceError(format!("Failed to flush segment db: {e}")))?;
+            self.insert_point(point)?;
+        }
+        self.flush()
+    }
+
+    fn insert_point(&self, point: &Point) -> Result<(), StorageError> {
+        let key = point.id.to_key();
+        let value = point_encoding::encode(point)?;
+
+        // Compare and swap so that a concurrent upsert of an older version can't win
+        loop {
+            let stored = self.storage.get(&key)?;
+
+            if stored
+                .as_ref()
+                .is_some_and(|stored| point_encoding::stored_version(stored) > point.version)
+            {
+                break; // Keep the newer version
+            }
+
+            let swapped =
+                self.storage
+                    .compare_and_swap(&key, stored.as_deref(), Some(value.as_slice()))?;
+
+            if swapped {
+                break;
+            }
+        }
         Ok(())
     }
 
@@ -79,40 +315,283 @@ impl Segment {
 
         let Some(ids) = ids else {
             // If no ids are provided, return all points
-            for result in self.db.iter() {
-                match result {
-                    Ok((_, value)) => {
-                        let point: Point = serde_json::from_slice(&value).map_err(|e| {
-                            StorageError::ServiceError(format!("Failed to deserialize point: {e}"))
-                        })?;
-                        points.push(point);
-                    }
-                    Err(e) => {
-                        return Err(StorageError::ServiceError(format!(
-                            "Failed to iterate over segment db: {e}"
-                        )));
-                    }
-                }
+            for result in self.point_entries(&[]) {
+                let (key, value) = result?;
+                points.push(point_encoding::decode(PointId::from_key(&key)?, &value)?);
             }
             return Ok(points);
         };
 
         // If ids are provided, retrieve only those points
         for id in ids {
-            let key = id.into_string();
-            if let Some(value) = self.db.get(key).map_err(|e| {
-                StorageError::ServiceError(format!("Failed to get point from segment db: {e}"))
-            })? {
-                let point: Point = serde_json::from_slice(&value).map_err(|e| {
-                    StorageError::ServiceError(format!("Failed to deserialize point: {e}"))
-                })?;
-                points.push(point);
+            if let Some(value) = self.storage.get(&id.to_key())? {
+                points.push(point_encoding::decode(id, &value)?);
             }
         }
         Ok(points)
     }
 
+    /// At most `limit` points with an id after `offset`, in the order of their keys
+    pub fn get_points_page(
+        &self,
+        offset: Option<&PointId>,
+        limit: usize,
+    ) -> Result<Vec<Point>, StorageError> {
+        let offset_key = offset.map(PointId::to_key);
+        self.point_entries(offset_key.as_deref().unwrap_or_default())
+            .filter(|entry| {
+                !matches!((entry, &offset_key), (Ok((key, _)), Some(offset_key)) if key == offset_key)
+            })
+            .take(limit)
+            .map(|entry| {
+                let (key, value) = entry?;
+                point_encoding::decode(PointId::from_key(&key)?, &value)
+            })
+            .collect()
+    }
+
+    /// Iterates over the points with an id after `offset` without loading them all at once.
+    /// Points rejected by `filter` are skipped before their payload is decoded.
+    pub fn iter_points<'a>(
+        &'a self,
+        offset: Option<&PointId>,
+        filter: impl Fn(&PointId) -> bool + 'a,
+    ) -> impl Iterator<Item = Result<Point, StorageError>> + 'a {
+        let offset_key = offset.map(PointId::to_key);
+        let start = offset_key.clone().unwrap_or_default();
+        self.point_entries(&start).filter_map(move |result| {
+            let decoded = result.and_then(|(key, value)| {
+                if offset_key.as_ref() == Some(&key) {
+                    return Ok(None);
+                }
+                let id = PointId::from_key(&key)?;
+                if !filter(&id) {
+                    return Ok(None);
+                }
+                point_encoding::decode(id, &value).map(Some)
+            });
+            decoded.transpose()
+        })
+    }
+
+    /// Ids and versions of the stored points, without decoding their payloads
+    pub fn iter_versions(&self) -> impl Iterator<Item = Result<(PointId, u64), StorageError>> + '_ {
+        self.point_entries(&[]).map(|result| {
+            let (key, value) = result?;
+            Ok((
+                PointId::from_key(&key)?,
+                point_encoding::stored_version(&value),
+            ))
+        })
+    }
+
+    pub fn delete_points(&self, ids: &[PointId]) -> Result<(), StorageError> {
+        for id in ids {
+            self.storage.delete(&id.to_key())?;
+        }
+        self.flush()
+    }
+
+    /// Does nothing for segments in memory
+    pub fn flush(&self) -> Result<(), StorageError> {
+        self.storage.flush()
+    }
+
     pub fn count_points(&self) -> usize {
-        self.db.len()
+        // Every segment holds the format marker once created or loaded
+        self.storage.len().saturating_sub(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_point_keys() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let segment = Segment::create(tmp_dir.path(), StorageType::Memory).unwrap();
+
+        let uuid: PointId = "5c56c793-69f3-4fbf-87e6-c4bf54c28c26".parse().unwrap();
+        let ids = [
+            PointId::Id(10),
+            uuid.clone(),
+            PointId::Id(2),
+            PointId::Id(256),
+        ];
+        let points: Vec<_> = ids
+            .iter()
+            .map(|id| Point {
+                id: id.clone(),
+                payload: json!({}),
+                version: 0,
+            })
+            .collect();
+        segment.insert_points(&points).unwrap();
+
+        // Numeric ids by value, then uuids
+        let stored: Vec<_> = segment
+            .get_points(None)
+            .unwrap()
+            .into_iter()
+            .map(|point| point.id)
+            .collect();
+        assert_eq!(
+            stored,
+            vec![
+                PointId::Id(2),
+                PointId::Id(10),
+                PointId::Id(256),
+                uuid.clone()
+            ]
+        );
+        for id in &ids {
+            assert_eq!(&PointId::from_key(&id.to_key()).unwrap(), id);
+        }
+
+        // Uuid strings made of digits can't collide with numeric ids anymore
+        assert_eq!(
+            serde_json::from_value::<PointId>(json!(5)).unwrap(),
+            PointId::Id(5)
+        );
+        assert_eq!(
+            serde_json::from_value::<PointId>(json!("5c56c793-69f3-4fbf-87e6-c4bf54c28c26"))
+                .unwrap(),
+            uuid
+        );
+        assert!(serde_json::from_value::<PointId>(json!("5")).is_err());
+        assert!(serde_json::from_value::<PointId>(json!("not-a-uuid")).is_err());
+        assert!(serde_json::from_value::<PointId>(json!(-1)).is_err());
+        assert!("not-a-uuid".parse::<PointId>().is_err());
+
+        // Other spellings of uuids were placed on shards as distinct ids
+        for id in [
+            "5C56C793-69F3-4FBF-87E6-C4BF54C28C26",
+            "5c56c79369f34fbf87e6c4bf54c28c26",
+            "{5c56c793-69f3-4fbf-87e6-c4bf54c28c26}",
+        ] {
+            assert!(id.parse::<PointId>().is_err());
+            assert!(serde_json::from_value::<PointId>(json!(id)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_legacy_ids() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let segment = Segment::create(tmp_dir.path(), StorageType::Log).unwrap();
+        // Segments written before the format marker, with the ids as string keys
+        segment.storage.delete(FORMAT_MARKER_KEY).unwrap();
+        let uuid = "5c56c793-69f3-4fbf-87e6-c4bf54c28c26";
+        let upper_uuid = uuid.to_uppercase();
+        for (key, id) in [
+            ("dummy-uuid", json!("dummy-uuid")),
+            ("12", json!("12")),
+            (uuid, json!(uuid)),
+            (upper_uuid.as_str(), json!(upper_uuid)),
+        ] {
+            let value = json!({ "id": id, "payload": { "key": key }, "version": 1 });
+            segment
+                .storage
+                .put(key.as_bytes(), &serde_json::to_vec(&value).unwrap())
+                .unwrap();
+        }
+        let path = segment.path.clone();
+        drop(segment);
+
+        let segment = Segment::load(&path, StorageType::Log).unwrap();
+        let stored: BTreeMap<_, _> = segment
+            .get_points(None)
+            .unwrap()
+            .into_iter()
+            .map(|point| (point.id, point.payload["key"].clone()))
+            .collect();
+        let legacy_uuid =
+            |key: &str| PointId::Uuid(Uuid::new_v5(&LEGACY_ID_NAMESPACE, key.as_bytes()));
+        assert_eq!(
+            stored,
+            BTreeMap::from([
+                (PointId::Id(12), json!("12")),
+                (uuid.parse().unwrap(), json!(uuid)),
+                (legacy_uuid("dummy-uuid"), json!("dummy-uuid")),
+                (legacy_uuid(&upper_uuid), json!(upper_uuid)),
+            ])
+        );
+        assert_eq!(segment.count_points(), 4);
+    }
+
+    #[test]
+    fn test_points_pages() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let segment = Segment::create(tmp_dir.path(), StorageType::Sled).unwrap();
+        let points: Vec<_> = (0..5)
+            .map(|id| Point {
+                id: PointId::Id(id),
+                payload: json!({ "id": id }),
+                version: 0,
+            })
+            .collect();
+        segment.insert_points(&points).unwrap();
+
+        let mut offset = None;
+        let mut pages = vec![];
+        loop {
+            let page = segment.get_points_page(offset.as_ref(), 2).unwrap();
+            let Some(last) = page.last() else {
+                break;
+            };
+            offset = Some(last.id.clone());
+            pages.push(page.into_iter().map(|point| point.id).collect::<Vec<_>>());
+        }
+        assert_eq!(
+            pages,
+            vec![
+                vec![PointId::Id(0), PointId::Id(1)],
+                vec![PointId::Id(2), PointId::Id(3)],
+                vec![PointId::Id(4)],
+            ]
+        );
+
+        // The offset doesn't have to exist anymore
+        let page = segment.get_points_page(Some(&PointId::Id(10)), 2).unwrap();
+        assert!(page.is_empty());
+        segment.delete_points(&[PointId::Id(1)]).unwrap();
+        let page = segment.get_points_page(Some(&PointId::Id(1)), 1).unwrap();
+        assert_eq!(page[0].id, PointId::Id(2));
+    }
+
+    #[test]
+    fn test_format_marker() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let segment = Segment::create(tmp_dir.path(), StorageType::Log).unwrap();
+        assert_eq!(
+            segment.storage.get(FORMAT_MARKER_KEY).unwrap(),
+            Some(vec![point_encoding::FORMAT_V1])
+        );
+        assert_eq!(segment.count_points(), 0);
+
+        let point = Point {
+            id: PointId::Id(3),
+            payload: json!({}),
+            version: 1,
+        };
+        segment.insert_points(std::slice::from_ref(&point)).unwrap();
+        assert_eq!(segment.count_points(), 1);
+        assert_eq!(segment.get_points(None).unwrap().len(), 1);
+        assert_eq!(segment.iter_points(None, |_| true).count(), 1);
+        assert_eq!(segment.iter_versions().count(), 1);
+        assert!(segment
+            .get_points_page(Some(&point.id), 10)
+            .unwrap()
+            .is_empty());
+
+        // Segments with the marker aren't scanned for legacy points on load
+        let legacy = serde_json::to_vec(&point).unwrap();
+        segment.storage.put(b"4", &legacy).unwrap();
+        let path = segment.path.clone();
+        drop(segment);
+        let segment = Segment::load(&path, StorageType::Log).unwrap();
+        assert_eq!(segment.storage.get(b"4").unwrap(), Some(legacy));
     }
 }
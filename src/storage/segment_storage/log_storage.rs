@@ -0,0 +1,468 @@
+use crate::storage::{
+    error::StorageError,
+    segment_storage::{Entry, SegmentStorage},
+};
+use memmap2::Mmap;
+use siphasher::sip::SipHasher;
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    hash::Hasher,
+    io::{BufWriter, Write},
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+const LOG_FILE: &str = "segment.log";
+/// Copy of the live records while compacting, replaces the log once complete
+const COMPACT_FILE: &str = "segment.log.compact";
+
+/// The log is compacted once overwritten and deleted records take at least this many bytes
+/// and more than the live ones
+const COMPACTION_MIN_GARBAGE: usize = 1024 * 1024;
+
+const PUT: u8 = 1;
+const DELETE: u8 = 2;
+
+/// Op (1) + key length (4) + value length (4) + checksum (8)
+const HEADER_LEN: usize = 17;
+
+fn io_error(e: std::io::Error) -> StorageError {
+    StorageError::ServiceError(format!("Segment log error: {e}"))
+}
+
+fn checksum(op: u8, key: &[u8], value: &[u8]) -> u64 {
+    let mut hasher = SipHasher::new();
+    hasher.write_u8(op);
+    hasher.write(key);
+    hasher.write(value);
+    hasher.finish()
+}
+
+fn record_len(key_len: usize, value_len: usize) -> usize {
+    HEADER_LEN + key_len + value_len
+}
+
+fn encode_record(op: u8, key: &[u8], value: &[u8]) -> Result<Vec<u8>, StorageError> {
+    let key_len = u32::try_from(key.len())
+        .map_err(|_| StorageError::BadInput("Key is too long".to_string()))?;
+    let value_len = u32::try_from(value.len())
+        .map_err(|_| StorageError::BadInput("Value is too long".to_string()))?;
+
+    let mut record = Vec::with_capacity(record_len(key.len(), value.len()));
+    record.push(op);
+    record.extend_from_slice(&key_len.to_le_bytes());
+    record.extend_from_slice(&value_len.to_le_bytes());
+    record.extend_from_slice(&checksum(op, key, value).to_le_bytes());
+    record.extend_from_slice(key);
+    record.extend_from_slice(value);
+    Ok(record)
+}
+
+/// Segments in an append-only log read through a memory map.
+///
+/// Every put and delete appends a record `[op][key length][value length][checksum][key][value]`
+/// (little endian) and an index in memory keeps the position of the latest value of each key.
+/// The log is scanned on open, a torn record at its end is truncated.
+/// Once overwritten and deleted records make up most of it, the log is rewritten with only
+/// the live records, see [`COMPACTION_MIN_GARBAGE`].
+pub struct LogStorage {
+    state: Mutex<LogState>,
+}
+
+/// Offset and length of the latest value of each key
+type Index = BTreeMap<Vec<u8>, (usize, usize)>;
+
+struct LogState {
+    path: PathBuf,
+    file: File,
+    /// None while the log is empty, remapped when reading past its end
+    mmap: Option<Mmap>,
+    /// Bytes of valid records in the log
+    len: usize,
+    /// Bytes of the records holding the latest value of each key, the rest is garbage
+    live: usize,
+    index: Index,
+}
+
+impl LogStorage {
+    pub fn open(path: &Path) -> Result<Self, StorageError> {
+        std::fs::create_dir_all(path).map_err(|e| {
+            StorageError::ServiceError(format!("Failed to create segment directory: {e}"))
+        })?;
+
+        // Left by a compaction that didn't complete, the log is still whole
+        let _ = std::fs::remove_file(path.join(COMPACT_FILE));
+
+        let log_path = path.join(LOG_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&log_path)
+            .map_err(|e| StorageError::ServiceError(format!("Failed to open segment log: {e}")))?;
+
+        let mut state = LogState {
+            path: log_path.clone(),
+            file,
+            mmap: None,
+            len: 0,
+            live: 0,
+            index: BTreeMap::new(),
+        };
+        let file_len = state.file.metadata().map_err(io_error)?.len() as usize;
+        if file_len > 0 {
+            state.remap()?;
+            state.scan();
+        }
+
+        if state.len < file_len {
+            eprintln!(
+                "Truncating {} torn bytes at the end of {log_path:?}",
+                file_len - state.len
+            );
+            // Unmap first, reading truncated pages would crash
+            state.mmap = None;
+            state.file.set_len(state.len as u64).map_err(io_error)?;
+            state.file.sync_data().map_err(io_error)?;
+            if state.len > 0 {
+                state.remap()?;
+            }
+        }
+
+        Ok(LogStorage {
+            state: Mutex::new(state),
+        })
+    }
+}
+
+impl LogState {
+    fn remap(&mut self) -> Result<(), StorageError> {
+        // SAFETY: the log is only appended to through this state, and truncated on open before
+        // being mapped again. Like sled, a segment must not be opened twice at the same time.
+        let mmap = unsafe { Mmap::map(&self.file) }.map_err(io_error)?;
+        self.mmap = Some(mmap);
+        Ok(())
+    }
+
+    /// Indexes the records of the log until its end or the first torn one
+    fn scan(&mut self) {
+        let Some(mmap) = &self.mmap else {
+            return;
+        };
+
+        let mut pos = 0;
+        while pos + HEADER_LEN <= mmap.len() {
+            let header = &mmap[pos..pos + HEADER_LEN];
+            let op = header[0];
+            let key_len = u32::from_le_bytes(header[1..5].try_into().unwrap()) as usize;
+            let value_len = u32::from_le_bytes(header[5..9].try_into().unwrap()) as usize;
+            let stored_checksum = u64::from_le_bytes(header[9..17].try_into().unwrap());
+
+            let key_start = pos + HEADER_LEN;
+            let value_start = key_start + key_len;
+            let end = value_start + value_len;
+            if end > mmap.len() {
+                break;
+            }
+
+            let key = &mmap[key_start..value_start];
+            let value = &mmap[value_start..end];
+            if checksum(op, key, value) != stored_checksum {
+                break;
+            }
+
+            let previous = match op {
+                PUT => {
+                    self.live += record_len(key_len, value_len);
+                    self.index.insert(key.to_vec(), (value_start, value_len))
+                }
+                DELETE => self.index.remove(key),
+                _ => break,
+            };
+            if let Some((_, previous_len)) = previous {
+                self.live -= record_len(key_len, previous_len);
+            }
+            pos = end;
+        }
+        self.len = pos;
+    }
+
+    fn read(&mut self, offset: usize, len: usize) -> Result<Vec<u8>, StorageError> {
+        if self
+            .mmap
+            .as_ref()
+            .is_none_or(|mmap| offset + len > mmap.len())
+        {
+            self.remap()?;
+        }
+        let mmap = self.mmap.as_ref().expect("Log is mapped");
+        Ok(mmap[offset..offset + len].to_vec())
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        match self.index.get(key).copied() {
+            Some((offset, len)) => self.read(offset, len).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Appends a record and returns the offset of its value
+    fn append(&mut self, op: u8, key: &[u8], value: &[u8]) -> Result<usize, StorageError> {
+        let record = encode_record(op, key, value)?;
+
+        if let Err(e) = self.file.write_all(&record) {
+            // Drop a partial record so the next ones are not appended after it
+            let _ = self.file.set_len(self.len as u64);
+            return Err(io_error(e));
+        }
+
+        let value_offset = self.len + HEADER_LEN + key.len();
+        self.len += record.len();
+        Ok(value_offset)
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        let offset = self.append(PUT, key, value)?;
+        self.live += record_len(key.len(), value.len());
+        if let Some((_, previous_len)) = self.index.insert(key.to_vec(), (offset, value.len())) {
+            self.live -= record_len(key.len(), previous_len);
+        }
+        self.maybe_compact();
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<bool, StorageError> {
+        if !self.index.contains_key(key) {
+            return Ok(false);
+        }
+        self.append(DELETE, key, &[])?;
+        if let Some((_, previous_len)) = self.index.remove(key) {
+            self.live -= record_len(key.len(), previous_len);
+        }
+        self.maybe_compact();
+        Ok(true)
+    }
+
+    /// The write is already in the log, so a failed compaction is only logged and tried again later
+    fn maybe_compact(&mut self) {
+        let garbage = self.len - self.live;
+        if garbage < COMPACTION_MIN_GARBAGE || garbage <= self.live {
+            return;
+        }
+
+        if let Err(e) = self.compact() {
+            eprintln!("Failed to compact {}: {e}", self.path.display());
+        }
+    }
+
+    /// Rewrites the log with only the latest value of each key and swaps it in place of the log.
+    /// A crash before the rename leaves the previous log, the partial copy is removed on open.
+    fn compact(&mut self) -> Result<(), StorageError> {
+        let compact_path = self.path.with_file_name(COMPACT_FILE);
+        let result = self.write_compacted(&compact_path);
+        if result.is_err() {
+            let _ = std::fs::remove_file(&compact_path);
+        }
+        let (index, len) = result?;
+
+        // Unmap first, the offsets of the index change along with the file
+        self.mmap = None;
+        std::fs::rename(&compact_path, &self.path).map_err(io_error)?;
+        if let Some(dir) = self.path.parent() {
+            File::open(dir)
+                .and_then(|dir| dir.sync_all())
+                .map_err(io_error)?;
+        }
+
+        self.file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(io_error)?;
+        self.index = index;
+        self.len = len;
+        self.live = len;
+        if len > 0 {
+            self.remap()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the live records to `compact_path`, returns their index and the length of the file
+    fn write_compacted(&mut self, compact_path: &Path) -> Result<(Index, usize), StorageError> {
+        if self.mmap.as_ref().is_none_or(|mmap| mmap.len() < self.len) {
+            self.remap()?;
+        }
+        let mmap = self.mmap.as_ref().expect("Log is mapped");
+
+        let mut writer = BufWriter::new(File::create(compact_path).map_err(io_error)?);
+        let mut index = BTreeMap::new();
+        let mut len = 0;
+        for (key, (offset, value_len)) in &self.index {
+            let record = encode_record(PUT, key, &mmap[*offset..offset + value_len])?;
+            writer.write_all(&record).map_err(io_error)?;
+            index.insert(key.clone(), (len + HEADER_LEN + key.len(), *value_len));
+            len += record.len();
+        }
+
+        let file = writer.into_inner().map_err(|e| io_error(e.into_error()))?;
+        file.sync_all().map_err(io_error)?;
+        Ok((index, len))
+    }
+}
+
+impl SegmentStorage for LogStorage {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        self.state
+            .lock()
+            .expect("Segment log lock is poisoned")
+            .get(key)
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        self.state
+            .lock()
+            .expect("Segment log lock is poisoned")
+            .put(key, value)
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<bool, StorageError> {
+        self.state
+            .lock()
+            .expect("Segment log lock is poisoned")
+            .delete(key)
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        current: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, StorageError> {
+        let mut state = self.state.lock().expect("Segment log lock is poisoned");
+        if state.get(key)?.as_deref() != current {
+            return Ok(false);
+        }
+
+        match new {
+            Some(value) => state.put(key, value)?,
+            None => {
+                state.delete(key)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Looks up each entry after the previous one, so writes are not blocked meanwhile
+    fn iter_from(
+        &self,
+        start: &[u8],
+    ) -> Box<dyn Iterator<Item = Result<Entry, StorageError>> + '_> {
+        let mut from = Bound::Included(start.to_vec());
+        Box::new(std::iter::from_fn(move || {
+            let mut state = self.state.lock().expect("Segment log lock is poisoned");
+            let (key, (offset, len)) = state
+                .index
+                .range((from.clone(), Bound::Unbounded))
+                .next()
+                .map(|(key, position)| (key.clone(), *position))?;
+            from = Bound::Excluded(key.clone());
+            Some(state.read(offset, len).map(|value| (key, value)))
+        }))
+    }
+
+    fn len(&self) -> usize {
+        self.state
+            .lock()
+            .expect("Segment log lock is poisoned")
+            .index
+            .len()
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        self.state
+            .lock()
+            .expect("Segment log lock is poisoned")
+            .file
+            .sync_data()
+            .map_err(io_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_torn_tail() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path();
+
+        let storage = LogStorage::open(path).unwrap();
+        storage.put(b"a", b"1").unwrap();
+        storage.put(b"b", b"2").unwrap();
+        storage.flush().unwrap();
+        drop(storage);
+
+        // Cut the last record in the middle, as if the process crashed while appending it
+        let log_path = path.join(LOG_FILE);
+        let file_len = std::fs::metadata(&log_path).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&log_path).unwrap();
+        file.set_len(file_len - 1).unwrap();
+        drop(file);
+
+        let storage = LogStorage::open(path).unwrap();
+        assert_eq!(storage.get(b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(storage.get(b"b").unwrap(), None);
+
+        // Records appended after the truncation are read back
+        storage.put(b"c", b"3").unwrap();
+        drop(storage);
+        let storage = LogStorage::open(path).unwrap();
+        assert_eq!(storage.len(), 2);
+        assert_eq!(storage.get(b"c").unwrap(), Some(b"3".to_vec()));
+    }
+
+    #[test]
+    fn test_compaction() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path();
+        let log_path = path.join(LOG_FILE);
+
+        let storage = LogStorage::open(path).unwrap();
+        for key in [b"a", b"b", b"c"] {
+            storage.put(key, b"initial").unwrap();
+        }
+
+        // Reads by key go on across a compaction
+        let mut iter = storage.iter();
+        assert_eq!(iter.next().unwrap().unwrap().0, b"a".to_vec());
+
+        let value = vec![7; 10 * 1024];
+        let mut max_len = 0;
+        for _ in 0..300 {
+            storage.put(b"b", &value).unwrap();
+            max_len = max_len.max(std::fs::metadata(&log_path).unwrap().len());
+        }
+        storage.delete(b"c").unwrap();
+        assert!(max_len < 3 * COMPACTION_MIN_GARBAGE as u64);
+        assert!(!path.join(COMPACT_FILE).exists());
+
+        let (key, read_value) = iter.next().unwrap().unwrap();
+        assert_eq!((key, read_value), (b"b".to_vec(), value.clone()));
+        assert!(iter.next().is_none());
+        drop(iter);
+
+        // The compacted log is read back like any other
+        storage.put(b"d", b"new").unwrap();
+        drop(storage);
+        let storage = LogStorage::open(path).unwrap();
+        assert_eq!(storage.len(), 3);
+        assert_eq!(storage.get(b"a").unwrap(), Some(b"initial".to_vec()));
+        assert_eq!(storage.get(b"b").unwrap(), Some(value));
+        assert_eq!(storage.get(b"c").unwrap(), None);
+        assert_eq!(storage.get(b"d").unwrap(), Some(b"new".to_vec()));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::storage::{
+    error::StorageError,
+    segment_storage::{Entry, SegmentStorage},
+};
+use std::{collections::BTreeMap, ops::Bound, sync::RwLock};
+
+/// Segments of collections in memory, nothing is written to disk
+#[derive(Default)]
+pub struct MemoryStorage {
+    entries: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl SegmentStorage for MemoryStorage {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self
+            .entries
+            .read()
+            .expect("Segment entries lock is poisoned")
+            .get(key)
+            .cloned())
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        self.entries
+            .write()
+            .expect("Segment entries lock is poisoned")
+            .insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<bool, StorageError> {
+        Ok(self
+            .entries
+            .write()
+            .expect("Segment entries lock is poisoned")
+            .remove(key)
+            .is_some())
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        current: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, StorageError> {
+        let mut entries = self
+            .entries
+            .write()
+            .expect("Segment entries lock is poisoned");
+        if entries.get(key).map(Vec::as_slice) != current {
+            return Ok(false);
+        }
+
+        match new {
+            Some(value) => entries.insert(key.to_vec(), value.to_vec()),
+            None => entries.remove(key),
+        };
+        Ok(true)
+    }
+
+    /// Looks up each entry after the previous one, so writes are not blocked meanwhile
+    fn iter_from(
+        &self,
+        start: &[u8],
+    ) -> Box<dyn Iterator<Item = Result<Entry, StorageError>> + '_> {
+        let mut from = Bound::Included(start.to_vec());
+        Box::new(std::iter::from_fn(move || {
+            let entries = self
+                .entries
+                .read()
+                .expect("Segment entries lock is poisoned");
+            let (key, value) = entries.range((from.clone(), Bound::Unbounded)).next()?;
+            from = Bound::Excluded(key.clone());
+            Some(Ok((key.clone(), value.clone())))
+        }))
+    }
+
+    fn len(&self) -> usize {
+        self.entries
+            .read()
+            .expect("Segment entries lock is poisoned")
+            .len()
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+pub mod log_storage;
+pub mod memory_storage;
+pub mod sled_storage;
+
+use crate::storage::{
+    error::StorageError,
+    segment::StorageType,
+    segment_storage::{
+        log_storage::LogStorage, memory_storage::MemoryStorage, sled_storage::SledStorage,
+    },
+};
+use std::path::Path;
+
+/// Key and value stored in a segment
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+/// Key-value engine holding the points of a [`crate::storage::segment::Segment`].
+///
+/// Engines are picked per collection with [`StorageType`]. Every engine must pass the
+/// conformance tests of this module, which describe the behavior segments rely on.
+pub trait SegmentStorage: Send + Sync {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError>;
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), StorageError>;
+
+    /// Returns false if the key didn't exist
+    fn delete(&self, key: &[u8]) -> Result<bool, StorageError>;
+
+    /// Replaces the value of `key` with `new`, or removes it if `new` is `None`, only if its
+    /// value is still `current`. Returns false if it changed meanwhile.
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        current: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, StorageError>;
+
+    /// Entries ordered by key bytes
+    fn iter(&self) -> Box<dyn Iterator<Item = Result<Entry, StorageError>> + '_> {
+        self.iter_from(&[])
+    }
+
+    /// Entries with a key greater than or equal to `start`, ordered by key bytes.
+    /// Entries are read as the iteration goes, not all at once.
+    fn iter_from(&self, start: &[u8])
+        -> Box<dyn Iterator<Item = Result<Entry, StorageError>> + '_>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Makes the writes so far durable, engines in memory do nothing
+    fn flush(&self) -> Result<(), StorageError>;
+}
+
+/// Opens the engine of `storage` in `path`, which is created unless the engine is in memory
+pub fn open(path: &Path, storage: StorageType) -> Result<Box<dyn SegmentStorage>, StorageError> {
+    Ok(match storage {
+        StorageType::Sled => Box::new(SledStorage::open(path)?),
+        StorageType::Log => Box::new(LogStorage::open(path)?),
+        StorageType::Memory => Box::new(MemoryStorage::default()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every engine, adding one to [`StorageType`] doesn't compile until it's listed here
+    fn all_engines() -> Vec<StorageType> {
+        let all = vec![StorageType::Sled, StorageType::Log, StorageType::Memory];
+        for storage in &all {
+            match storage {
+                StorageType::Sled | StorageType::Log | StorageType::Memory => {}
+            }
+        }
+        all
+    }
+
+    fn keys(storage: &dyn SegmentStorage) -> Vec<Vec<u8>> {
+        storage.iter().map(|entry| entry.unwrap().0).collect()
+    }
+
+    fn check_conformance(storage: &dyn SegmentStorage) {
+        assert!(storage.is_empty());
+        assert_eq!(storage.get(b"a").unwrap(), None);
+        assert!(!storage.delete(b"a").unwrap());
+
+        // Iteration is ordered by bytes, not by insertion
+        for key in [&b"b"[..], b"a", &[0xff], &[0x00, 0x01], b"c"] {
+            storage.put(key, key).unwrap();
+        }
+        assert_eq!(storage.len(), 5);
+        assert_eq!(
+            keys(storage),
+            vec![
+                vec![0x00, 0x01],
+                b"a".to_vec(),
+                b"b".to_vec(),
+                b"c".to_vec(),
+                vec![0xff]
+            ]
+        );
+
+        // Iteration from a key includes it
+        let from_b = storage
+            .iter_from(b"b")
+            .map(|entry| entry.unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(from_b, vec![b"b".to_vec(), b"c".to_vec(), vec![0xff]]);
+        assert_eq!(storage.iter_from(&[0xff, 0x00]).count(), 0);
+
+        storage.put(b"a", b"updated").unwrap();
+        storage.put(b"empty", b"").unwrap();
+        assert_eq!(storage.get(b"a").unwrap(), Some(b"updated".to_vec()));
+        assert_eq!(storage.get(b"empty").unwrap(), Some(vec![]));
+        assert_eq!(storage.len(), 6);
+
+        assert!(storage.delete(b"b").unwrap());
+        assert_eq!(storage.get(b"b").unwrap(), None);
+        assert!(!storage.delete(b"b").unwrap());
+
+        // Swaps only happen if the value is still the expected one
+        assert!(!storage
+            .compare_and_swap(b"a", Some(b"stale"), Some(b"lost"))
+            .unwrap());
+        assert!(storage
+            .compare_and_swap(b"a", Some(b"updated"), Some(b"swapped"))
+            .unwrap());
+        assert_eq!(storage.get(b"a").unwrap(), Some(b"swapped".to_vec()));
+        assert!(!storage.compare_and_swap(b"a", None, Some(b"lost")).unwrap());
+        assert!(storage.compare_and_swap(b"new", None, Some(b"1")).unwrap());
+        assert!(storage.compare_and_swap(b"new", Some(b"1"), None).unwrap());
+        assert_eq!(storage.get(b"new").unwrap(), None);
+
+        storage.flush().unwrap();
+        let entries = storage.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries.len(), storage.len());
+        assert!(entries.contains(&(b"a".to_vec(), b"swapped".to_vec())));
+    }
+
+    #[test]
+    fn test_conformance() {
+        for storage_type in all_engines() {
+            let tmp_dir = tempfile::tempdir().unwrap();
+            let storage = open(&tmp_dir.path().join("0"), storage_type).unwrap();
+            check_conformance(storage.as_ref());
+        }
+    }
+
+    #[test]
+    fn test_persistence() {
+        for storage_type in all_engines() {
+            let tmp_dir = tempfile::tempdir().unwrap();
+            let path = tmp_dir.path().join("0");
+
+            let storage = open(&path, storage_type).unwrap();
+            storage.put(b"kept", b"1").unwrap();
+            storage.put(b"overwritten", b"1").unwrap();
+            storage.put(b"overwritten", b"2").unwrap();
+            storage.put(b"deleted", b"1").unwrap();
+            storage.delete(b"deleted").unwrap();
+            storage.flush().unwrap();
+            drop(storage);
+
+            let storage = open(&path, storage_type).unwrap();
+            if !storage_type.is_persistent() {
+                assert!(storage.is_empty());
+                continue;
+            }
+            assert_eq!(
+                storage.iter().collect::<Result<Vec<_>, _>>().unwrap(),
+                vec![
+                    (b"kept".to_vec(), b"1".to_vec()),
+                    (b"overwritten".to_vec(), b"2".to_vec()),
+                ]
+            );
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::storage::{
+    error::StorageError,
+    segment_storage::{Entry, SegmentStorage},
+};
+use std::path::Path;
+
+fn sled_error(e: sled::Error) -> StorageError {
+    StorageError::ServiceError(format!("Segment db error: {e}"))
+}
+
+/// Segments in a sled database, the default engine
+pub struct SledStorage {
+    db: sled::Db,
+}
+
+impl SledStorage {
+    pub fn open(path: &Path) -> Result<Self, StorageError> {
+        std::fs::create_dir_all(path).map_err(|e| {
+            StorageError::ServiceError(format!("Failed to create segment directory: {e}"))
+        })?;
+
+        let db = sled::open(path).map_err(|e| {
+            StorageError::ServiceError(format!("Failed to open segment database: {e}"))
+        })?;
+        Ok(SledStorage { db })
+    }
+}
+
+impl SegmentStorage for SledStorage {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self
+            .db
+            .get(key)
+            .map_err(sled_error)?
+            .map(|value| value.to_vec()))
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        self.db.insert(key, value).map_err(sled_error)?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<bool, StorageError> {
+        Ok(self.db.remove(key).map_err(sled_error)?.is_some())
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        current: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, StorageError> {
+        let swapped = self
+            .db
+            .compare_and_swap(key, current, new)
+            .map_err(sled_error)?;
+        Ok(swapped.is_ok())
+    }
+
+    fn iter_from(
+        &self,
+        start: &[u8],
+    ) -> Box<dyn Iterator<Item = Result<Entry, StorageError>> + '_> {
+        Box::new(self.db.range(start..).map(|entry| {
+            entry
+                .map(|(key, value)| (key.to_vec(), value.to_vec()))
+                .map_err(sled_error)
+        }))
+    }
+
+    fn len(&self) -> usize {
+        self.db.len()
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        self.db.flush().map_err(sled_error)?;
+        Ok(())
+    }
+}
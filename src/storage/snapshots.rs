@@ -0,0 +1,301 @@
+use crate::storage::{error::StorageError, toc::COLLECTIONS_DIR};
+use serde::Serialize;
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+pub const SNAPSHOT_EXTENSION: &str = "snapshot";
+
+/// Prefix of the snapshots of the whole storage, see [`archive_storage`]
+pub const STORAGE_SNAPSHOT_PREFIX: &str = "storage";
+
+/// Consensus state of the peer in storage snapshots
+pub const CONSENSUS_FILE: &str = "consensus.json";
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SnapshotDescription {
+    pub name: String,
+    /// Size of the archive in bytes
+    pub size: u64,
+    /// Seconds since the Unix epoch
+    pub creation_time: u64,
+}
+
+impl SnapshotDescription {
+    pub fn from_path(path: &Path) -> Result<Self, StorageError> {
+        let metadata = std::fs::metadata(path).map_err(|e| {
+            StorageError::ServiceError(format!("Failed to read snapshot {}: {e}", path.display()))
+        })?;
+
+        let creation_time = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_secs());
+
+        Ok(SnapshotDescription {
+            name: path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default()
+                .to_string(),
+            size: metadata.len(),
+            creation_time,
+        })
+    }
+}
+
+/// Name of a new snapshot, snapshots of a collection sort by creation time
+pub fn snapshot_name(prefix: &str) -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time is before the Unix epoch")
+        .as_millis();
+    format!("{prefix}-{millis}.{SNAPSHOT_EXTENSION}")
+}
+
+/// Resolves a snapshot name received from a client, which must not escape `dir`
+pub fn snapshot_path(dir: &Path, name: &str) -> Result<PathBuf, StorageError> {
+    let is_plain_name = Path::new(name)
+        .file_name()
+        .is_some_and(|file_name| file_name == name);
+
+    if !is_plain_name || !name.ends_with(&format!(".{SNAPSHOT_EXTENSION}")) {
+        return Err(StorageError::BadInput(format!(
+            "Invalid snapshot name '{name}'"
+        )));
+    }
+
+    let path = dir.join(name);
+    if !path.exists() {
+        return Err(StorageError::BadInput(format!(
+            "Snapshot '{name}' does not exist"
+        )));
+    }
+    Ok(path)
+}
+
+/// Snapshots in `dir`, oldest first
+pub fn list_snapshots(dir: &Path) -> Result<Vec<SnapshotDescription>, StorageError> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let entries = std::fs::read_dir(dir).map_err(|e| {
+        StorageError::ServiceError(format!("Failed to read snapshots directory: {e}"))
+    })?;
+
+    let mut snapshots = vec![];
+    for entry in entries {
+        let path = entry
+            .map_err(|e| StorageError::ServiceError(format!("Failed to read snapshot: {e}")))?
+            .path();
+
+        if path.is_file()
+            && path
+                .extension()
+                .is_some_and(|ext| ext == SNAPSHOT_EXTENSION)
+        {
+            snapshots.push(SnapshotDescription::from_path(&path)?);
+        }
+    }
+
+    snapshots.sort_by(|a, b| (a.creation_time, &a.name).cmp(&(b.creation_time, &b.name)));
+    Ok(snapshots)
+}
+
+/// The archive is written next to its final path first, so a partial archive is never listed.
+fn write_archive(
+    archive_path: &Path,
+    append: impl FnOnce(&mut tar::Builder<File>) -> std::io::Result<()>,
+) -> Result<(), StorageError> {
+    let tmp_path = archive_path.with_extension("tmp");
+
+    let archive = || -> std::io::Result<()> {
+        let mut builder = tar::Builder::new(File::create(&tmp_path)?);
+        builder.follow_symlinks(false);
+        append(&mut builder)?;
+        builder.into_inner()?.sync_all()?;
+        std::fs::rename(&tmp_path, archive_path)
+    };
+
+    archive().map_err(|e| {
+        let _ = std::fs::remove_file(&tmp_path);
+        StorageError::ServiceError(format!(
+            "Failed to write archive {}: {e}",
+            archive_path.display()
+        ))
+    })
+}
+
+/// Writes the content of `source_dir` to a tar archive at `archive_path`
+pub fn archive_dir(source_dir: &Path, archive_path: &Path) -> Result<(), StorageError> {
+    write_archive(archive_path, |builder| {
+        builder.append_dir_all(".", source_dir)
+    })
+}
+
+/// Archives every collection of the storage along with the consensus state of the peer.
+/// Hints are left out, they target the peers of the cluster the snapshot was taken in.
+pub fn archive_storage(
+    storage_dir: &Path,
+    consensus_state: &[u8],
+    archive_path: &Path,
+) -> Result<(), StorageError> {
+    write_archive(archive_path, |builder| {
+        builder.append_dir_all(COLLECTIONS_DIR, storage_dir.join(COLLECTIONS_DIR))?;
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(consensus_state.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, CONSENSUS_FILE, consensus_state)
+    })
+}
+
+/// Unpacks a storage snapshot into `storage_dir`, which must not hold any collection yet.
+/// Returns the consensus state of the snapshot, see [`restore_unpacked_storage`].
+pub fn restore_storage(
+    archive_path: &Path,
+    storage_dir: &Path,
+) -> Result<Option<Vec<u8>>, StorageError> {
+    let unpack_dir = restoring_dir(storage_dir)?;
+    if let Err(e) = unpack_archive(archive_path, &unpack_dir) {
+        let _ = std::fs::remove_dir_all(&unpack_dir);
+        return Err(e);
+    }
+    restore_unpacked_storage(&unpack_dir, storage_dir)
+}
+
+/// Temporary directory to unpack a storage snapshot into before [`restore_unpacked_storage`].
+/// Fails if `storage_dir` already holds collections, they are never overwritten.
+pub fn restoring_dir(storage_dir: &Path) -> Result<PathBuf, StorageError> {
+    let collections_dir = storage_dir.join(COLLECTIONS_DIR);
+    let has_collections = std::fs::read_dir(&collections_dir)
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false);
+    if has_collections {
+        return Err(StorageError::BadInput(format!(
+            "{} already holds collections, snapshots can only be restored into an empty storage",
+            collections_dir.display()
+        )));
+    }
+
+    Ok(storage_dir
+        .join(SNAPSHOTS_DIR)
+        .join(snapshot_name("restoring"))
+        .with_extension("tmp"))
+}
+
+/// Moves the collections unpacked in `unpack_dir` into `storage_dir`, then removes `unpack_dir`.
+/// Returns the consensus state stored along, the peer starts with it instead of a new one.
+pub fn restore_unpacked_storage(
+    unpack_dir: &Path,
+    storage_dir: &Path,
+) -> Result<Option<Vec<u8>>, StorageError> {
+    let restore = || -> Result<Option<Vec<u8>>, StorageError> {
+        let unpacked_collections = unpack_dir.join(COLLECTIONS_DIR);
+        if !unpacked_collections.is_dir() {
+            return Err(StorageError::BadInput(
+                "Not a storage snapshot, it has no collections directory".to_string(),
+            ));
+        }
+
+        let consensus_state = std::fs::read(unpack_dir.join(CONSENSUS_FILE)).ok();
+
+        let collections_dir = storage_dir.join(COLLECTIONS_DIR);
+        let _ = std::fs::remove_dir(&collections_dir);
+        std::fs::rename(&unpacked_collections, &collections_dir).map_err(|e| {
+            StorageError::ServiceError(format!("Failed to move the restored collections: {e}"))
+        })?;
+
+        Ok(consensus_state)
+    };
+
+    let result = restore();
+    let _ = std::fs::remove_dir_all(unpack_dir);
+    result
+}
+
+/// Extracts a tar archive into `target_dir`, which is created if needed.
+/// Entries that would be written outside of `target_dir` are refused by `tar`.
+pub fn unpack_archive(archive_path: &Path, target_dir: &Path) -> Result<(), StorageError> {
+    let unpack = || -> std::io::Result<()> {
+        std::fs::create_dir_all(target_dir)?;
+        tar::Archive::new(File::open(archive_path)?).unpack(target_dir)
+    };
+
+    unpack().map_err(|e| {
+        StorageError::BadInput(format!(
+            "Failed to unpack snapshot {}: {e}",
+            archive_path.display()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let source = tmp_dir.path().join("source");
+        std::fs::create_dir_all(source.join("0/segments")).unwrap();
+        std::fs::write(source.join("config.json"), "{}").unwrap();
+        std::fs::write(source.join("0/segments/db"), "points").unwrap();
+
+        let snapshots_dir = tmp_dir.path().join(SNAPSHOTS_DIR);
+        std::fs::create_dir_all(&snapshots_dir).unwrap();
+        let name = snapshot_name("c1");
+        archive_dir(&source, &snapshots_dir.join(&name)).unwrap();
+
+        let snapshots = list_snapshots(&snapshots_dir).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].name, name);
+        assert!(snapshots[0].size > 0);
+
+        let target = tmp_dir.path().join("target");
+        unpack_archive(&snapshot_path(&snapshots_dir, &name).unwrap(), &target).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(target.join("0/segments/db")).unwrap(),
+            "points"
+        );
+
+        // Names can't point outside of the snapshots directory
+        assert!(snapshot_path(&snapshots_dir, "../source/config.json").is_err());
+        assert!(snapshot_path(&snapshots_dir, "missing.snapshot").is_err());
+    }
+
+    #[test]
+    fn test_restore_storage() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = tmp_dir.path().join("storage");
+        std::fs::create_dir_all(storage.join(COLLECTIONS_DIR).join("c1")).unwrap();
+        std::fs::write(storage.join(COLLECTIONS_DIR).join("c1/config.json"), "{}").unwrap();
+
+        let archive_path = tmp_dir.path().join(snapshot_name(STORAGE_SNAPSHOT_PREFIX));
+        archive_storage(&storage, b"{}", &archive_path).unwrap();
+
+        // Existing collections are never overwritten
+        assert!(restore_storage(&archive_path, &storage).is_err());
+
+        let clone = tmp_dir.path().join("clone");
+        let consensus_state = restore_storage(&archive_path, &clone).unwrap();
+        assert_eq!(consensus_state.as_deref(), Some(b"{}".as_slice()));
+        assert!(clone.join(COLLECTIONS_DIR).join("c1/config.json").exists());
+        assert!(!clone.join(CONSENSUS_FILE).exists());
+
+        // Collection snapshots are not storage snapshots
+        let collection_archive = tmp_dir.path().join(snapshot_name("c1"));
+        archive_dir(
+            &storage.join(COLLECTIONS_DIR).join("c1"),
+            &collection_archive,
+        )
+        .unwrap();
+        assert!(restore_storage(&collection_archive, &tmp_dir.path().join("other")).is_err());
+    }
+}
@@ -1,41 +1,186 @@
 use crate::{
-    api::points::PointsOperation,
+    api::{
+        grpc::p2p_grpc_schema::{
+            collections_internal_client::CollectionsInternalClient, CreateCollectionRequest,
+            CreateCollectionResponse, CreateShardKeyRequest, DrainPeerRequest, ReshardingRequest,
+            ShardingMethod as GrpcShardingMethod, StorageType as GrpcStorageType,
+        },
+        points::PointsOperation,
+    },
     channel_service::ChannelService,
     storage::{
+        backup::{self, BackupDescription, BackupStore},
         collection::{Collection, CollectionConfig, CollectionName, COLLECTION_CONFIG_FILE},
-        error::StorageError,
-        segment::{Point, PointId},
+        drain::{DrainProgress, DrainState, Drains},
+        error::{CollectionError, StorageError},
+        hints::{HintStore, PeerHints, HINTS_DIR},
+        rebalance::{plan_moves, Rebalancer, ShardMove, REBALANCE_INTERVAL},
+        replicas::{
+            consistency::ReadConsistency, ring::RingConfig, ReplicaHolder, ReplicaId,
+            ShardOperationTrait, ShardSelector, ShardingMethod,
+        },
+        segment::{Point, PointId, StorageType},
+        snapshots::{self, SnapshotDescription, SNAPSHOTS_DIR, STORAGE_SNAPSHOT_PREFIX},
     },
+    types::{PeerId, ShardId, ShardKey},
 };
+use futures::future::join_all;
+use http::Uri;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    future::Future,
     path::{Path, PathBuf},
     sync::Arc,
+    time::{Duration, Instant},
 };
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, RwLockWriteGuard};
+use tonic::transport::Channel;
 
 pub const COLLECTIONS_DIR: &str = "collections";
 
+/// How often writes that failed on remote replicas are retried
+pub const HINT_REPLAY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Time peers get to create the collections while the rebalancer plans moves
+const REBALANCE_PLAN_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct TableOfContent {
     pub collections: Arc<RwLock<Collections>>,
     pub channel_service: ChannelService,
+    /// Writes this peer coordinated that are still missing on remote replicas
+    pub hints: Arc<HintStore>,
+    /// Peers being emptied, they don't get replicas of new collections
+    pub drains: Drains,
+    pub rebalancer: Rebalancer,
+    /// Root of the collections, hints and snapshots, see [`crate::settings::StorageSettings`]
+    pub storage_path: PathBuf,
 }
 
 pub type Collections = HashMap<CollectionName, Collection>;
 
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AckStatus {
+    Created,
+    AlreadyExists,
+    Failed,
+    TimedOut,
+}
+
+impl AckStatus {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, AckStatus::Created | AckStatus::AlreadyExists)
+    }
+}
+
+/// Acknowledgement of a collection operation by another peer
+#[derive(Serialize, Debug)]
+pub struct PeerAck {
+    pub status: AckStatus,
+    /// Local shards of the collection on the peer
+    pub shard_ids: Vec<ShardId>,
+    pub error: Option<String>,
+    pub time: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub enum CollectionMetaOperation {
     CreateCollection {
         collection_name: String,
         params: String,
+        shard_count: Option<u32>,
+        #[serde(default)]
+        sharding_method: ShardingMethod,
+        #[serde(default = "RingConfig::legacy")]
+        ring: RingConfig,
+        #[serde(default)]
+        storage: StorageType,
+    },
+    /// Adds the shards of a key to a collection with custom sharding
+    CreateShardKey {
+        collection_name: String,
+        shard_key: ShardKey,
+        shard_ids: Vec<ShardId>,
     },
     DeleteCollection {
         collection_name: String,
     },
+    /// Adds (or marks for removal) shards so that the collection ends up with `shard_count` shards
+    StartResharding {
+        collection_name: String,
+        shard_count: u32,
+    },
+    /// Switches routing to the target ring once points are migrated
+    FinishResharding {
+        collection_name: String,
+        shard_count: u32,
+    },
+}
+
+/// Collection names received from a client become directory names, so they must not escape
+/// the directory they are joined to
+fn check_collection_name(collection_name: &str) -> Result<(), StorageError> {
+    let is_plain_name = Path::new(collection_name)
+        .file_name()
+        .is_some_and(|file_name| file_name == collection_name);
+    if !is_plain_name {
+        return Err(StorageError::BadInput(format!(
+            "Invalid collection name '{collection_name}'"
+        )));
+    }
+    Ok(())
+}
+
+/// Moves the unpacked snapshot in place of the collection directory and loads it.
+/// On failure, the previous directory is moved back and loaded again if there was one.
+fn swap_collection_dir(
+    collection_name: &str,
+    path: &Path,
+    unpack_dir: &Path,
+    replaced_dir: &Path,
+    channel_service: ChannelService,
+) -> Result<Collection, (StorageError, Option<Box<Collection>>)> {
+    let swap = || -> std::io::Result<()> {
+        if path.exists() {
+            std::fs::rename(path, replaced_dir)?;
+        }
+        std::fs::rename(unpack_dir, path)
+    };
+
+    let recovered = swap()
+        .map_err(|e| {
+            StorageError::ServiceError(format!("Failed to move the recovered collection: {e}"))
+        })
+        .and_then(|_| Collection::load(collection_name.to_string(), path, channel_service.clone()));
+
+    let e = match recovered {
+        Ok(collection) => return Ok(collection),
+        Err(e) => e,
+    };
+
+    let _ = std::fs::remove_dir_all(unpack_dir);
+    if !replaced_dir.exists() {
+        return Err((e, None));
+    }
+
+    let _ = std::fs::remove_dir_all(path);
+    let _ = std::fs::rename(replaced_dir, path);
+    match Collection::load(collection_name.to_string(), path, channel_service) {
+        Ok(collection) => Err((e, Some(Box::new(collection)))),
+        Err(reload_error) => {
+            eprintln!(
+                "Failed to reload collection {collection_name} after a failed recovery: {reload_error}"
+            );
+            Err((e, None))
+        }
+    }
 }
 
 impl TableOfContent {
-    pub fn load(channel_service: ChannelService) -> Self {
-        let collections_path = Path::new("storage").join(COLLECTIONS_DIR);
+    /// Loads the collections of the storage
+    pub fn load(storage_path: &Path, channel_service: ChannelService) -> Self {
+        let collections_path = storage_path.join(COLLECTIONS_DIR);
         std::fs::create_dir_all(&collections_path).expect("Failed to create collections directory");
 
         // Load collections from the directory
@@ -62,21 +207,33 @@ impl TableOfContent {
                 .expect("Collection name is not valid UTF-8")
                 .to_string();
 
-            let collection = Collection::load(collection_name, &path)
+            let collection = Collection::load(collection_name, &path, channel_service.clone())
                 .expect("Failed to load collection from path");
 
             collections.insert(collection.id.clone(), collection);
         }
 
+        let hints = HintStore::open(&storage_path.join(HINTS_DIR))
+            .map(Arc::new)
+            .expect("Failed to open hints storage");
+
         TableOfContent {
             collections: Arc::new(RwLock::new(collections)),
             channel_service,
+            hints,
+            drains: Drains::default(),
+            rebalancer: Rebalancer::default(),
+            storage_path: storage_path.to_path_buf(),
         }
     }
 
     /// Creates a new directory at the expected collection path.
-    pub async fn mkdir_collection_dir(collection_name: &str) -> Result<PathBuf, StorageError> {
-        let path = Path::new("storage")
+    pub async fn mkdir_collection_dir(
+        &self,
+        collection_name: &str,
+    ) -> Result<PathBuf, StorageError> {
+        let path = self
+            .storage_path
             .join(COLLECTIONS_DIR)
             .join(collection_name);
 
@@ -102,13 +259,42 @@ impl TableOfContent {
             CollectionMetaOperation::CreateCollection {
                 collection_name,
                 params,
+                shard_count,
+                sharding_method,
+                ring,
+                storage,
             } => {
                 println!("Creating collection {collection_name}");
-                let path = Self::mkdir_collection_dir(&collection_name).await?;
+                ring.validate()?;
+
+                if self.drains.is_draining(self.channel_service.this_peer_id) {
+                    return Err(StorageError::BadInput(format!(
+                        "Can't create collection '{collection_name}' on a peer being drained"
+                    )));
+                }
 
-                let collection =
-                    Collection::init(collection_name.clone(), CollectionConfig { params }, &path)
+                let path = self.mkdir_collection_dir(&collection_name).await?;
+
+                let config =
+                    CollectionConfig::new(params, shard_count, sharding_method, ring, storage);
+
+                let collection = Collection::init(
+                    collection_name.clone(),
+                    config,
+                    &path,
+                    self.channel_service.clone(),
+                )
+                .await?;
+
+                // Every peer holds a replica of every shard, except the ones being drained
+                for (peer_id, _) in self.placement_peers().await {
+                    collection
+                        .replica_holder
+                        .write()
+                        .await
+                        .add_remote_shards(peer_id, collection_name.clone())
                         .await?;
+                }
 
                 {
                     let mut write_collections = self.collections.write().await;
@@ -136,7 +322,1234 @@ impl TableOfContent {
 
                 Ok(true)
             }
+            CollectionMetaOperation::CreateShardKey {
+                collection_name,
+                shard_key,
+                shard_ids,
+            } => {
+                println!("Creating shard key {shard_key} of collection {collection_name}");
+                let peer_ids = self
+                    .placement_peers()
+                    .await
+                    .into_iter()
+                    .map(|(peer_id, _)| peer_id)
+                    .collect();
+
+                let mut write_collections = self.collections.write().await;
+                let collection = write_collections.get_mut(&collection_name).ok_or_else(|| {
+                    StorageError::BadInput(format!(
+                        "Collection with name '{collection_name}' does not exist"
+                    ))
+                })?;
+
+                collection
+                    .create_shard_key(shard_key, shard_ids, peer_ids)
+                    .await
+            }
+            CollectionMetaOperation::StartResharding {
+                collection_name,
+                shard_count,
+            } => {
+                println!("Resharding collection {collection_name} to {shard_count} shards");
+                let mut write_collections = self.collections.write().await;
+                let collection = write_collections.get_mut(&collection_name).ok_or_else(|| {
+                    StorageError::BadInput(format!(
+                        "Collection with name '{collection_name}' does not exist"
+                    ))
+                })?;
+
+                if collection.config.resharding_shard_count == Some(shard_count) {
+                    // Already in progress, only the migration has to be resumed
+                    return Ok(false);
+                }
+
+                if collection.config.shard_count == shard_count {
+                    return Err(StorageError::BadInput(format!(
+                        "Collection '{collection_name}' already has {shard_count} shards"
+                    )));
+                }
+
+                collection.start_resharding(shard_count).await?;
+                Ok(true)
+            }
+            CollectionMetaOperation::FinishResharding {
+                collection_name,
+                shard_count,
+            } => {
+                println!("Finishing resharding of collection {collection_name}");
+                let mut write_collections = self.collections.write().await;
+                let collection = write_collections.get_mut(&collection_name).ok_or_else(|| {
+                    StorageError::BadInput(format!(
+                        "Collection with name '{collection_name}' does not exist"
+                    ))
+                })?;
+
+                if collection.config.resharding_shard_count.is_none()
+                    && collection.config.shard_count == shard_count
+                {
+                    // Finished by the proposal of another peer
+                    return Ok(false);
+                }
+
+                if collection.config.resharding_shard_count != Some(shard_count) {
+                    return Err(StorageError::BadInput(format!(
+                        "Collection '{collection_name}' is not being resharded to {shard_count} shards"
+                    )));
+                }
+
+                collection.finish_resharding().await.map_err(|e| {
+                    StorageError::ServiceError(format!(
+                        "Failed to finish resharding of collection '{collection_name}': {e}"
+                    ))
+                })?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Creates the collection on every other known peer and collects their acknowledgements.
+    /// Peers that don't answer within `timeout` are reported as timed out.
+    pub async fn create_collection_on_peers(
+        &self,
+        collection_name: &str,
+        config: &CollectionConfig,
+        timeout: Duration,
+    ) -> BTreeMap<PeerId, PeerAck> {
+        let request = CreateCollectionRequest {
+            collection_name: collection_name.to_string(),
+            params: config.params.clone(),
+            shard_count: Some(config.shard_count),
+            sharding_method: GrpcShardingMethod::from(config.sharding_method) as i32,
+            virtual_nodes: Some(config.ring.virtual_nodes),
+            shard_weights: config.ring.shard_weights.clone().into_iter().collect(),
+            storage: GrpcStorageType::from(config.storage) as i32,
+        };
+
+        self.collect_acks(self.placement_peers().await, timeout, |mut client| {
+            let request = request.clone();
+            async move { client.create(request).await }
+        })
+        .await
+    }
+
+    /// Creates the shards of a new key locally, their ids are allocated under the collections lock
+    /// so that keys created concurrently never get the same ids.
+    /// An existing key keeps its shards. Returns whether the key was created along with its shard ids.
+    pub async fn create_shard_key(
+        &self,
+        collection_name: &str,
+        shard_key: ShardKey,
+        shard_count: u32,
+    ) -> Result<(bool, Vec<ShardId>), StorageError> {
+        let peer_ids = self
+            .placement_peers()
+            .await
+            .into_iter()
+            .map(|(peer_id, _)| peer_id)
+            .collect();
+
+        let mut write_collections = self.collections.write().await;
+        let collection = write_collections.get_mut(collection_name).ok_or_else(|| {
+            StorageError::BadInput(format!(
+                "Collection with name '{collection_name}' does not exist"
+            ))
+        })?;
+
+        if let Some(shard_ids) = collection.config.shard_keys.get(&shard_key) {
+            return Ok((false, shard_ids.clone()));
+        }
+
+        let shard_ids = collection.next_shard_ids(shard_count);
+        println!("Creating shard key {shard_key} of collection {collection_name}");
+        let created = collection
+            .create_shard_key(shard_key, shard_ids.clone(), peer_ids)
+            .await?;
+        Ok((created, shard_ids))
+    }
+
+    /// Creates the shards of the key on every other known peer, same as [`Self::create_collection_on_peers`].
+    pub async fn create_shard_key_on_peers(
+        &self,
+        collection_name: &str,
+        shard_key: &ShardKey,
+        shard_ids: &[ShardId],
+        timeout: Duration,
+    ) -> BTreeMap<PeerId, PeerAck> {
+        let request = CreateShardKeyRequest {
+            collection_name: collection_name.to_string(),
+            shard_key: shard_key.clone(),
+            shard_ids: shard_ids.to_vec(),
+        };
+
+        self.collect_acks(self.placement_peers().await, timeout, |mut client| {
+            let request = request.clone();
+            async move { client.create_shard_key(request).await }
+        })
+        .await
+    }
+
+    /// Starts resharding the collection on every other known peer, including draining ones
+    /// since they still hold shards. Same acknowledgements as [`Self::create_collection_on_peers`].
+    pub async fn start_resharding_on_peers(
+        &self,
+        collection_name: &str,
+        shard_count: u32,
+        timeout: Duration,
+    ) -> BTreeMap<PeerId, PeerAck> {
+        let request = ReshardingRequest {
+            collection_name: collection_name.to_string(),
+            shard_count,
+        };
+
+        self.collect_acks(
+            self.channel_service.other_peers().await,
+            timeout,
+            |mut client| {
+                let request = request.clone();
+                async move { client.start_resharding(request).await }
+            },
+        )
+        .await
+    }
+
+    /// Finishes resharding the collection on every other known peer,
+    /// see [`Self::start_resharding_on_peers`]
+    pub async fn finish_resharding_on_peers(
+        &self,
+        collection_name: &str,
+        shard_count: u32,
+        timeout: Duration,
+    ) -> BTreeMap<PeerId, PeerAck> {
+        let request = ReshardingRequest {
+            collection_name: collection_name.to_string(),
+            shard_count,
+        };
+
+        self.collect_acks(
+            self.channel_service.other_peers().await,
+            timeout,
+            |mut client| {
+                let request = request.clone();
+                async move { client.finish_resharding(request).await }
+            },
+        )
+        .await
+    }
+
+    /// Finishes a resharding started on every peer: moves the local points, waits for every
+    /// other peer to finish and only then switches the routing of this peer. If a peer fails,
+    /// this peer stays in resharding so that calling it again resumes it.
+    pub async fn finish_resharding_everywhere(
+        &self,
+        collection_name: &str,
+        shard_count: u32,
+        timeout: Duration,
+    ) -> Result<(), StorageError> {
+        let moved_points = self.migrate_resharded_points(collection_name).await?;
+        println!("Moved {moved_points} points of collection {collection_name} while resharding");
+
+        let failed = self
+            .finish_resharding_on_peers(collection_name, shard_count, timeout)
+            .await
+            .into_iter()
+            .filter(|(_, ack)| !ack.status.is_ok())
+            .map(|(peer_id, ack)| format!("{peer_id}: {ack:?}"))
+            .collect::<Vec<_>>();
+        if !failed.is_empty() {
+            return Err(StorageError::ServiceError(format!(
+                "Peers failed to finish resharding of collection '{collection_name}': [{}]",
+                failed.join(", ")
+            )));
+        }
+
+        self.perform_collection_meta_op(CollectionMetaOperation::FinishResharding {
+            collection_name: collection_name.to_string(),
+            shard_count,
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Sends the request to the peers concurrently
+    async fn collect_acks<F, Fut>(
+        &self,
+        peers: Vec<(PeerId, Uri)>,
+        timeout: Duration,
+        request: F,
+    ) -> BTreeMap<PeerId, PeerAck>
+    where
+        F: Fn(CollectionsInternalClient<Channel>) -> Fut,
+        Fut: Future<Output = Result<tonic::Response<CreateCollectionResponse>, tonic::Status>>,
+    {
+        let requests = peers.into_iter().map(|(peer_id, uri)| {
+            let request = &request;
+            async move {
+                let started = Instant::now();
+                let response = async {
+                    let channel = self
+                        .channel_service
+                        .channel_pool
+                        .get_or_create_channel(uri)
+                        .await?;
+
+                    let response = request(CollectionsInternalClient::new(channel))
+                        .await?
+                        .into_inner();
+
+                    Ok::<_, CollectionError>(response)
+                };
+
+                let (status, shard_ids, error) = match tokio::time::timeout(timeout, response).await
+                {
+                    Ok(Ok(response)) if response.created => {
+                        (AckStatus::Created, response.shard_ids, None)
+                    }
+                    Ok(Ok(response)) => (AckStatus::AlreadyExists, response.shard_ids, None),
+                    Ok(Err(e)) => (AckStatus::Failed, vec![], Some(e.to_string())),
+                    Err(_) => (AckStatus::TimedOut, vec![], None),
+                };
+
+                let ack = PeerAck {
+                    status,
+                    shard_ids,
+                    error,
+                    time: started.elapsed().as_secs_f64(),
+                };
+                (peer_id, ack)
+            }
+        });
+
+        join_all(requests).await.into_iter().collect()
+    }
+
+    fn snapshots_dir(&self, collection_name: &str) -> PathBuf {
+        self.storage_path.join(SNAPSHOTS_DIR).join(collection_name)
+    }
+
+    /// Archives the local shards of the collection, see [`Collection::create_snapshot`]
+    pub async fn create_snapshot(
+        &self,
+        collection_name: &str,
+    ) -> Result<SnapshotDescription, StorageError> {
+        let collections = self.collections.read().await;
+        let collection = collections.get(collection_name).ok_or_else(|| {
+            StorageError::BadInput(format!(
+                "Collection with name '{collection_name}' does not exist"
+            ))
+        })?;
+
+        let archive_path = self.new_snapshot_path(collection_name).await?;
+        collection.create_snapshot(&archive_path).await?;
+
+        let snapshot = SnapshotDescription::from_path(&archive_path)?;
+        println!(
+            "Created snapshot {} of collection {collection_name}",
+            snapshot.name
+        );
+        Ok(snapshot)
+    }
+
+    /// Path of a new snapshot archive of the collection, e.g. for uploaded archives
+    pub async fn new_snapshot_path(&self, collection_name: &str) -> Result<PathBuf, StorageError> {
+        check_collection_name(collection_name)?;
+        let snapshots_dir = self.snapshots_dir(collection_name);
+        tokio::fs::create_dir_all(&snapshots_dir)
+            .await
+            .map_err(|e| {
+                StorageError::ServiceError(format!("Can't create snapshots directory: {e}"))
+            })?;
+
+        Ok(snapshots_dir.join(snapshots::snapshot_name(collection_name)))
+    }
+
+    /// Archives every collection of this peer, reads and writes of all of them wait meanwhile.
+    /// `consensus_state` is stored along, see [`snapshots::archive_storage`].
+    pub async fn create_storage_snapshot(
+        &self,
+        consensus_state: Vec<u8>,
+    ) -> Result<SnapshotDescription, StorageError> {
+        // Collections can't be created or deleted meanwhile
+        let collections = self.collections.read().await;
+        let replica_holders = Self::lock_replica_holders(&collections).await?;
+
+        let snapshots_dir = self.storage_path.join(SNAPSHOTS_DIR);
+        tokio::fs::create_dir_all(&snapshots_dir)
+            .await
+            .map_err(|e| {
+                StorageError::ServiceError(format!("Can't create snapshots directory: {e}"))
+            })?;
+
+        let archive_path = snapshots_dir.join(snapshots::snapshot_name(STORAGE_SNAPSHOT_PREFIX));
+        {
+            let (storage_path, archive_path) = (self.storage_path.clone(), archive_path.clone());
+            tokio::task::spawn_blocking(move || {
+                snapshots::archive_storage(&storage_path, &consensus_state, &archive_path)
+            })
+            .await
+            .map_err(|e| StorageError::ServiceError(format!("Snapshot task failed: {e}")))??;
+        }
+        drop(replica_holders);
+
+        let snapshot = SnapshotDescription::from_path(&archive_path)?;
+        println!("Created storage snapshot {}", snapshot.name);
+        Ok(snapshot)
+    }
+
+    /// Takes the write lock of every collection and flushes their local shards,
+    /// so the files of the storage don't change until the guards are dropped.
+    /// Reads of the collections wait for the guards too.
+    async fn lock_replica_holders(
+        collections: &Collections,
+    ) -> Result<Vec<RwLockWriteGuard<'_, ReplicaHolder>>, StorageError> {
+        let mut replica_holders = Vec::with_capacity(collections.len());
+        for collection in collections.values() {
+            let replica_holder = collection.replica_holder.write().await;
+            for replica_set in replica_holder.shards.values() {
+                replica_set.local.flush()?;
+            }
+            replica_holders.push(replica_holder);
         }
+        Ok(replica_holders)
+    }
+
+    /// Uploads the files of the storage that changed since the latest backup of `store`.
+    /// Reads and writes only wait while the changed files are copied to a staging directory.
+    pub async fn create_backup(
+        &self,
+        store: &BackupStore,
+        consensus_state: Vec<u8>,
+    ) -> Result<BackupDescription, StorageError> {
+        let _in_progress = store.in_progress.lock().await;
+        let previous = store.latest().await?;
+
+        let staging_dir = self
+            .storage_path
+            .join(SNAPSHOTS_DIR)
+            .join(snapshots::snapshot_name("backup"))
+            .with_extension("tmp");
+
+        let staged = {
+            let collections = self.collections.read().await;
+            let _replica_holders = Self::lock_replica_holders(&collections).await?;
+
+            let (storage_path, staging_dir) = (self.storage_path.clone(), staging_dir.clone());
+            tokio::task::spawn_blocking(move || {
+                backup::stage_backup(
+                    &storage_path,
+                    &consensus_state,
+                    previous.as_ref(),
+                    &staging_dir,
+                )
+            })
+            .await
+            .map_err(|e| StorageError::ServiceError(format!("Backup task failed: {e}")))?
+        };
+
+        let result = match staged {
+            Ok(staged) => store.upload(&staged).await,
+            Err(e) => Err(e),
+        };
+        let _ = tokio::fs::remove_dir_all(&staging_dir).await;
+
+        let backup = result?;
+        println!(
+            "Created backup {}, uploaded {} of its {} files",
+            backup.id, backup.uploaded_files, backup.file_count
+        );
+        Ok(backup)
+    }
+
+    pub fn list_storage_snapshots(&self) -> Result<Vec<SnapshotDescription>, StorageError> {
+        snapshots::list_snapshots(&self.storage_path.join(SNAPSHOTS_DIR))
+    }
+
+    pub fn storage_snapshot_path(&self, snapshot_name: &str) -> Result<PathBuf, StorageError> {
+        snapshots::snapshot_path(&self.storage_path.join(SNAPSHOTS_DIR), snapshot_name)
+    }
+
+    /// Snapshots are kept after the collection is deleted, so they can still be recovered
+    pub fn list_snapshots(
+        &self,
+        collection_name: &str,
+    ) -> Result<Vec<SnapshotDescription>, StorageError> {
+        snapshots::list_snapshots(&self.snapshots_dir(collection_name))
+    }
+
+    pub fn snapshot_path(
+        &self,
+        collection_name: &str,
+        snapshot_name: &str,
+    ) -> Result<PathBuf, StorageError> {
+        snapshots::snapshot_path(&self.snapshots_dir(collection_name), snapshot_name)
+    }
+
+    /// Replaces the collection on this peer with the content of a snapshot archive,
+    /// or creates it if it doesn't exist. Returns true if an existing collection was replaced.
+    ///
+    /// The archive is unpacked and checked before the current collection is touched, and the
+    /// previous collection is restored if the recovered one fails to load. Other peers keep their
+    /// replicas, newer points they hold come back through anti-entropy.
+    pub async fn recover_snapshot(
+        &self,
+        collection_name: &str,
+        archive_path: &Path,
+    ) -> Result<bool, StorageError> {
+        check_collection_name(collection_name)?;
+
+        if self.drains.is_draining(self.channel_service.this_peer_id) {
+            return Err(StorageError::BadInput(format!(
+                "Can't recover collection '{collection_name}' on a peer being drained"
+            )));
+        }
+
+        // Unpacked next to the snapshots, on the same file system as the collections
+        let unpack_dir = self
+            .snapshots_dir(collection_name)
+            .join(snapshots::snapshot_name("recovering"))
+            .with_extension("tmp");
+        let unpacked = {
+            let archive_path = archive_path.to_path_buf();
+            let unpack_dir = unpack_dir.clone();
+            tokio::task::spawn_blocking(move || {
+                snapshots::unpack_archive(&archive_path, &unpack_dir)?;
+                CollectionConfig::load(&unpack_dir)
+            })
+            .await
+            .map_err(|e| StorageError::ServiceError(format!("Recovery task failed: {e}")))?
+        };
+
+        if let Err(e) = unpacked {
+            let _ = std::fs::remove_dir_all(&unpack_dir);
+            return Err(StorageError::BadInput(format!(
+                "Snapshot {} is not a collection snapshot: {e}",
+                archive_path.display()
+            )));
+        }
+
+        let path = self
+            .storage_path
+            .join(COLLECTIONS_DIR)
+            .join(collection_name);
+        let replaced_dir = unpack_dir.with_extension("replaced");
+
+        let mut collections = self.collections.write().await;
+
+        // The segments of the current collection have to be closed before its directory is moved
+        let replaced = match collections.remove(collection_name) {
+            Some(previous) => {
+                if let Err(e) = previous.wait_released().await {
+                    collections.insert(collection_name.to_string(), previous);
+                    drop(collections);
+                    let _ =
+                        tokio::task::spawn_blocking(move || std::fs::remove_dir_all(unpack_dir))
+                            .await;
+                    return Err(e);
+                }
+                true
+            }
+            None => false,
+        };
+
+        let recovered = {
+            let collection_name = collection_name.to_string();
+            let path = path.clone();
+            let unpack_dir = unpack_dir.clone();
+            let replaced_dir = replaced_dir.clone();
+            let channel_service = self.channel_service.clone();
+            tokio::task::spawn_blocking(move || {
+                swap_collection_dir(
+                    &collection_name,
+                    &path,
+                    &unpack_dir,
+                    &replaced_dir,
+                    channel_service,
+                )
+            })
+            .await
+            .map_err(|e| StorageError::ServiceError(format!("Recovery task failed: {e}")))?
+        };
+
+        let collection = match recovered {
+            Ok(collection) => collection,
+            Err((e, restored)) => {
+                if let Some(restored) = restored {
+                    collections.insert(collection_name.to_string(), *restored);
+                }
+                return Err(e);
+            }
+        };
+
+        // Remote replicas are not persisted, same as when the collection is created
+        for (peer_id, _) in self.placement_peers().await {
+            collection
+                .replica_holder
+                .write()
+                .await
+                .add_remote_shards(peer_id, collection_name.to_string())
+                .await?;
+        }
+
+        collections.insert(collection_name.to_string(), collection);
+        drop(collections);
+
+        if replaced {
+            let removed =
+                tokio::task::spawn_blocking(move || std::fs::remove_dir_all(replaced_dir))
+                    .await
+                    .map_err(|e| {
+                        StorageError::ServiceError(format!("Recovery task failed: {e}"))
+                    })?;
+            if let Err(e) = removed {
+                eprintln!("Failed to delete the replaced collection {collection_name}: {e}");
+            }
+        }
+
+        println!(
+            "Recovered collection {collection_name} from snapshot {}",
+            archive_path.display()
+        );
+        Ok(replaced)
+    }
+
+    /// Other peers that are eligible for new replicas
+    async fn placement_peers(&self) -> Vec<(PeerId, Uri)> {
+        self.channel_service
+            .other_peers()
+            .await
+            .into_iter()
+            .filter(|(peer_id, _)| !self.drains.is_draining(*peer_id))
+            .collect()
+    }
+
+    /// Starts moving every shard replica held by the peer to the other peers in the background.
+    ///
+    /// The peer is first excluded from new placements on every peer, then its shards are copied
+    /// to the remaining replicas and finally every peer drops the replicas of the drained peer,
+    /// which drops its own shards. Progress is reported by [`Self::drain_progress`].
+    pub async fn drain_peer(
+        self: Arc<Self>,
+        peer_id: PeerId,
+    ) -> Result<DrainProgress, StorageError> {
+        if !self
+            .channel_service
+            .id_to_address
+            .read()
+            .await
+            .contains_key(&peer_id)
+        {
+            return Err(StorageError::BadInput(format!(
+                "Peer {peer_id} is not known"
+            )));
+        }
+
+        let mut total_shards = 0;
+        for collection in self.collections.read().await.values() {
+            total_shards += collection.replica_holder.read().await.shards.len();
+        }
+
+        let progress = self.drains.start(peer_id, total_shards)?;
+        println!("Draining peer {peer_id}, {total_shards} shards to transfer");
+
+        tokio::spawn(async move {
+            match self.run_drain(peer_id).await {
+                Ok(()) => {
+                    println!("Peer {peer_id} is drained");
+                    self.drains
+                        .update(peer_id, |progress| progress.state = DrainState::Drained);
+                }
+                Err(e) => {
+                    eprintln!("Failed to drain peer {peer_id}: {e}");
+                    self.drains.update(peer_id, |progress| {
+                        progress.state = DrainState::Failed;
+                        progress.error = Some(e.to_string());
+                    });
+                }
+            }
+        });
+
+        Ok(progress)
+    }
+
+    pub fn drain_progress(&self, peer_id: PeerId) -> Result<DrainProgress, StorageError> {
+        self.drains.progress(peer_id).ok_or_else(|| {
+            StorageError::BadInput(format!("Peer {peer_id} is not being drained by this peer"))
+        })
+    }
+
+    async fn run_drain(&self, peer_id: PeerId) -> Result<(), StorageError> {
+        let other_peers = self.channel_service.other_peers().await;
+
+        // Stop placing new replicas on the peer before its shards are copied
+        for (other_peer_id, uri) in &other_peers {
+            self.request_peer(*other_peer_id, uri.clone(), peer_id, false)
+                .await?;
+        }
+
+        let collection_names = self
+            .collections
+            .read()
+            .await
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for collection_name in collection_names {
+            // Cloned so that the collections aren't locked during the transfers
+            let replica_sets = match self.collections.read().await.get(&collection_name) {
+                Some(collection) => collection
+                    .replica_holder
+                    .read()
+                    .await
+                    .shards
+                    .iter()
+                    .map(|(shard_id, replica_set)| (*shard_id, replica_set.clone()))
+                    .collect::<Vec<_>>(),
+                None => continue, // Deleted in the meantime
+            };
+
+            for (shard_id, replica_set) in replica_sets {
+                // Other draining and dead peers would fail the transfer,
+                // anti-entropy catches them up once they are back
+                let targets = std::iter::once(self.channel_service.this_peer_id)
+                    .chain(
+                        replica_set
+                            .remotes
+                            .iter()
+                            .filter(|remote| remote.is_alive())
+                            .map(|remote| remote.peer_id),
+                    )
+                    .filter(|id| *id != peer_id && !self.drains.is_draining(*id))
+                    .map(|id| replica_set.replica_id_of(id))
+                    .collect::<Vec<ReplicaId>>();
+
+                let transferred = replica_set
+                    .transfer(replica_set.replica_id_of(peer_id), &targets, None)
+                    .await
+                    .map_err(|e| {
+                        StorageError::ServiceError(format!(
+                            "Failed to transfer shard {shard_id} of collection '{collection_name}': {e}"
+                        ))
+                    })?;
+
+                self.drains.update(peer_id, |progress| {
+                    progress.transferred_shards += 1;
+                    progress.transferred_points += transferred;
+                });
+            }
+        }
+
+        self.drains
+            .update(peer_id, |progress| progress.state = DrainState::Detaching);
+
+        // Writes stop going to the drained peer before it drops its shards
+        for (other_peer_id, uri) in &other_peers {
+            if *other_peer_id != peer_id {
+                self.request_peer(*other_peer_id, uri.clone(), peer_id, true)
+                    .await?;
+            }
+        }
+        self.detach_peer(peer_id).await?;
+
+        if let Some((_, uri)) = other_peers.iter().find(|(id, _)| *id == peer_id) {
+            self.request_peer(peer_id, uri.clone(), peer_id, true)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends `MarkDraining`, or `DetachPeer` if `detach` is set, for the drained peer to another peer.
+    async fn request_peer(
+        &self,
+        to_peer_id: PeerId,
+        uri: Uri,
+        peer_id: PeerId,
+        detach: bool,
+    ) -> Result<(), StorageError> {
+        let request = async {
+            let channel = self
+                .channel_service
+                .channel_pool
+                .get_or_create_channel(uri)
+                .await?;
+
+            let mut client = CollectionsInternalClient::new(channel);
+            let request = DrainPeerRequest { peer_id };
+            let response = if detach {
+                client.detach_peer(request).await?
+            } else {
+                client.mark_draining(request).await?
+            };
+
+            Ok::<_, CollectionError>(response.into_inner())
+        };
+
+        request.await.map(|_| ()).map_err(|e| {
+            StorageError::ServiceError(format!(
+                "Peer {to_peer_id} failed to apply the drain of peer {peer_id}: {e}"
+            ))
+        })
+    }
+
+    /// Drops the replicas held by the drained peer. The drained peer itself drops all its
+    /// collections, as its data now lives on the other peers. Returns the number of dropped replicas.
+    pub async fn detach_peer(&self, peer_id: PeerId) -> Result<usize, StorageError> {
+        self.drains.mark(peer_id);
+
+        if peer_id != self.channel_service.this_peer_id {
+            let mut removed = 0;
+            for collection in self.collections.read().await.values() {
+                removed += collection
+                    .replica_holder
+                    .write()
+                    .await
+                    .remove_remote_shards(peer_id);
+            }
+            return Ok(removed);
+        }
+
+        let mut write_collections = self.collections.write().await;
+        let mut removed = 0;
+        for (collection_name, collection) in write_collections.drain() {
+            println!("Dropping collection {collection_name} of drained peer");
+            removed += collection.replica_holder.read().await.shards.len();
+            collection.delete()?;
+        }
+        Ok(removed)
+    }
+
+    /// Checks that the peer was drained and that no collection has replicas on it anymore.
+    pub async fn check_peer_removable(&self, peer_id: PeerId) -> Result<(), StorageError> {
+        if !self.drains.is_draining(peer_id) {
+            return Err(StorageError::BadInput(format!(
+                "Peer {peer_id} must be drained before it's removed"
+            )));
+        }
+
+        for (collection_name, collection) in self.collections.read().await.iter() {
+            let replica_holder = collection.replica_holder.read().await;
+            let holds_replicas = replica_holder
+                .shards
+                .values()
+                .any(|replica_set| replica_set.remotes.iter().any(|r| r.peer_id == peer_id));
+
+            if holds_replicas {
+                return Err(StorageError::BadInput(format!(
+                    "Peer {peer_id} still holds replicas of collection '{collection_name}'"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drops what this peer tracks about a peer that left the cluster
+    pub fn forget_peer(&self, peer_id: PeerId) {
+        self.drains.forget(peer_id);
+        self.channel_service.failure_detector.forget(peer_id);
+    }
+
+    /// Fails if the collection can't be resharded to `shard_count` shards,
+    /// before the resharding is started on any peer.
+    pub async fn check_resharding(
+        &self,
+        collection_name: &str,
+        shard_count: u32,
+    ) -> Result<(), StorageError> {
+        let collections = self.collections.read().await;
+        let collection = collections.get(collection_name).ok_or_else(|| {
+            StorageError::BadInput(format!(
+                "Collection with name '{collection_name}' does not exist"
+            ))
+        })?;
+
+        if collection.config.sharding_method == ShardingMethod::Custom {
+            return Err(StorageError::BadInput(
+                "Collections with custom sharding get more shards by adding shard keys".to_string(),
+            ));
+        }
+        if shard_count == 0 {
+            return Err(StorageError::BadInput(
+                "Shard count must be greater than 0".to_string(),
+            ));
+        }
+        if collection.config.resharding_shard_count.is_none()
+            && collection.config.shard_count == shard_count
+        {
+            return Err(StorageError::BadInput(format!(
+                "Collection '{collection_name}' already has {shard_count} shards"
+            )));
+        }
+        if let Some(resharding_shard_count) = collection.config.resharding_shard_count {
+            if resharding_shard_count != shard_count {
+                return Err(StorageError::BadInput(format!(
+                    "Collection '{collection_name}' is already being resharded to {resharding_shard_count} shards"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves points of a collection being resharded to their new shards.
+    /// Returns the number of moved points.
+    pub async fn migrate_resharded_points(
+        &self,
+        collection_name: &str,
+    ) -> Result<usize, StorageError> {
+        let replica_holder = self
+            .collections
+            .read()
+            .await
+            .get(collection_name)
+            .map(|collection| collection.replica_holder.clone())
+            .ok_or_else(|| {
+                StorageError::BadInput(format!("Collection '{collection_name}' does not exist"))
+            })?;
+
+        Collection::migrate_replica_points(&replica_holder)
+            .await
+            .map_err(|e| {
+                StorageError::ServiceError(format!(
+                    "Failed to migrate points of collection '{collection_name}': {e}"
+                ))
+            })
+    }
+
+    /// Periodically syncs local shards with their remote replicas,
+    /// so that replicas which missed writes while being down converge.
+    pub async fn run_anti_entropy(self: Arc<Self>, period: Duration) {
+        let mut interval = tokio::time::interval(period);
+
+        loop {
+            interval.tick().await;
+
+            let collection_names = self
+                .collections
+                .read()
+                .await
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>();
+
+            for collection_name in collection_names {
+                let replica_holder = self
+                    .collections
+                    .read()
+                    .await
+                    .get(&collection_name)
+                    .map(|collection| collection.replica_holder.clone());
+                let Some(replica_holder) = replica_holder else {
+                    continue; // Deleted in the meantime
+                };
+
+                match Collection::sync_replicas(&replica_holder).await {
+                    Ok(stats) if stats.differing_ranges > 0 => {
+                        println!("Synced replicas of collection {collection_name}: {stats:?}")
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        eprintln!("Failed to sync replicas of collection {collection_name}: {e}")
+                    }
+                }
+            }
+        }
+    }
+
+    /// Copies shards to the peers that don't hold them yet, e.g. after they joined the cluster.
+    ///
+    /// A new plan is computed whenever the set of alive peers eligible for replicas changes,
+    /// then the moves are executed one by one at `rate_limit` points per second.
+    /// Pending moves wait while the rebalancer is paused through the API.
+    pub async fn run_rebalancer(self: Arc<Self>, rate_limit: u64) {
+        self.rebalancer.enable(rate_limit);
+        let mut interval = tokio::time::interval(REBALANCE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if self.rebalancer.is_paused() {
+                continue;
+            }
+
+            let peers = self
+                .placement_peers()
+                .await
+                .into_iter()
+                .map(|(peer_id, _)| peer_id)
+                .filter(|peer_id| self.channel_service.failure_detector.is_alive(*peer_id))
+                .collect::<BTreeSet<_>>();
+
+            if self.rebalancer.needs_plan(&peers) {
+                let moves = self.plan_rebalance(&peers).await;
+                if !moves.is_empty() {
+                    println!("Rebalancer planned {} shard moves", moves.len());
+                }
+                self.rebalancer.set_plan(peers, moves);
+            }
+
+            while let Some((index, shard_move)) = self.rebalancer.next_move() {
+                // The peer might have left or started draining since the plan was computed
+                if !self.is_rebalance_target(shard_move.to_peer_id).await {
+                    println!(
+                        "Peer {} left, dropping its pending shard moves",
+                        shard_move.to_peer_id
+                    );
+                    let error = "Peer left the cluster";
+                    self.rebalancer.finish_move(index, Err(error.to_string()));
+                    self.rebalancer.fail_moves_to(shard_move.to_peer_id, error);
+                    continue;
+                }
+
+                let result = self.execute_move(&shard_move, rate_limit).await;
+                if let Err(e) = &result {
+                    eprintln!(
+                        "Failed to move shard {} of collection {} to peer {}: {e}",
+                        shard_move.shard_id, shard_move.collection_name, shard_move.to_peer_id
+                    );
+                }
+                self.rebalancer
+                    .finish_move(index, result.map_err(|e| e.to_string()));
+            }
+        }
+    }
+
+    /// True if the peer is alive and still eligible for replicas
+    async fn is_rebalance_target(&self, peer_id: PeerId) -> bool {
+        self.channel_service.failure_detector.is_alive(peer_id)
+            && !self.drains.is_draining(peer_id)
+            && self
+                .channel_service
+                .id_to_address
+                .read()
+                .await
+                .contains_key(&peer_id)
+    }
+
+    /// Creates the local collections on the peers that miss them and returns the shards to copy.
+    /// Peers that fail to answer are planned again on the next tick.
+    async fn plan_rebalance(&self, peers: &BTreeSet<PeerId>) -> Vec<ShardMove> {
+        let mut local_collections = vec![];
+        for (collection_name, collection) in self.collections.read().await.iter() {
+            let replica_holder = collection.replica_holder.read().await;
+            // Shards are being moved already
+            if replica_holder.resharding().is_some() {
+                continue;
+            }
+
+            let mut shard_ids = replica_holder.shards.keys().copied().collect::<Vec<_>>();
+            shard_ids.sort();
+            local_collections.push((
+                collection_name.clone(),
+                collection.config.clone(),
+                shard_ids,
+            ));
+        }
+
+        let mut local_shards = BTreeMap::new();
+        let mut peer_shards = peers
+            .iter()
+            .map(|peer_id| (*peer_id, BTreeMap::new()))
+            .collect::<BTreeMap<_, _>>();
+        let mut failed_peers = BTreeSet::new();
+
+        for (collection_name, config, shard_ids) in local_collections {
+            let acks = self
+                .create_collection_on_peers(&collection_name, &config, REBALANCE_PLAN_TIMEOUT)
+                .await;
+
+            for (peer_id, ack) in acks {
+                let Some(held) = peer_shards.get_mut(&peer_id) else {
+                    continue; // Joined or died while planning
+                };
+
+                if !ack.status.is_ok() {
+                    eprintln!(
+                        "Rebalancer failed to create collection {collection_name} on peer {peer_id}: {ack:?}"
+                    );
+                    failed_peers.insert(peer_id);
+                    continue;
+                }
+
+                // A collection that was just created holds no points yet, neither do the shards
+                // of a previous move that didn't complete
+                let held_shards = if ack.status == AckStatus::Created {
+                    self.rebalancer
+                        .track_created(peer_id, &collection_name, ack.shard_ids);
+                    BTreeSet::new()
+                } else {
+                    ack.shard_ids
+                        .into_iter()
+                        .filter(|shard_id| {
+                            self.rebalancer
+                                .is_filled(peer_id, &collection_name, *shard_id)
+                        })
+                        .collect()
+                };
+                held.insert(collection_name.clone(), held_shards);
+            }
+
+            // Shards of custom keys only exist once the key is created on the peer
+            for (shard_key, key_shard_ids) in &config.shard_keys {
+                let acks = self
+                    .create_shard_key_on_peers(
+                        &collection_name,
+                        shard_key,
+                        key_shard_ids,
+                        REBALANCE_PLAN_TIMEOUT,
+                    )
+                    .await;
+
+                for (peer_id, ack) in acks {
+                    let Some(held) = peer_shards.get_mut(&peer_id) else {
+                        continue;
+                    };
+
+                    match ack.status {
+                        AckStatus::Created => {
+                            self.rebalancer.track_created(
+                                peer_id,
+                                &collection_name,
+                                key_shard_ids.iter().copied(),
+                            );
+                            if let Some(held_shards) = held.get_mut(&collection_name) {
+                                held_shards.retain(|shard_id| !key_shard_ids.contains(shard_id));
+                            }
+                        }
+                        AckStatus::AlreadyExists => {}
+                        _ => {
+                            eprintln!(
+                                "Rebalancer failed to create shard key {shard_key} of collection {collection_name} on peer {peer_id}: {ack:?}"
+                            );
+                            failed_peers.insert(peer_id);
+                        }
+                    }
+                }
+            }
+
+            local_shards.insert(collection_name, shard_ids);
+        }
+
+        if !failed_peers.is_empty() {
+            peer_shards.retain(|peer_id, _| !failed_peers.contains(peer_id));
+            // Never fails, the rebalancer is running
+            let _ = self.rebalancer.replan();
+        }
+
+        plan_moves(&local_shards, &peer_shards)
+    }
+
+    async fn execute_move(
+        &self,
+        shard_move: &ShardMove,
+        rate_limit: u64,
+    ) -> Result<usize, StorageError> {
+        let replica_holder = self
+            .collections
+            .read()
+            .await
+            .get(&shard_move.collection_name)
+            .map(|collection| (collection.replica_holder.clone(), collection.id.clone()));
+        let Some((replica_holder, collection_id)) = replica_holder else {
+            return Err(StorageError::BadInput(format!(
+                "Collection '{}' does not exist",
+                shard_move.collection_name
+            )));
+        };
+
+        let replica_set = {
+            let mut replica_holder = replica_holder.write().await;
+            // Peers that joined through another peer might not be a replica here yet
+            replica_holder
+                .add_remote_shards(shard_move.to_peer_id, collection_id)
+                .await?;
+            replica_holder
+                .get_replica_set(shard_move.shard_id)
+                .await?
+                .clone()
+        };
+
+        // The transfer is rate limited, so no lock is held meanwhile
+        replica_set
+            .transfer(
+                ReplicaId::Local,
+                &[replica_set.replica_id_of(shard_move.to_peer_id)],
+                Some(rate_limit),
+            )
+            .await
+            .map_err(|e| StorageError::ServiceError(e.to_string()))
+    }
+
+    /// Periodically replays the writes that failed on remote replicas, see [`HintStore`].
+    pub async fn run_hint_handoff(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(HINT_REPLAY_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            for peer_id in self.hints.peers() {
+                match self.replay_hints(peer_id).await {
+                    Ok(0) => {}
+                    Ok(replayed) => println!("Replayed {replayed} hints to peer {peer_id}"),
+                    Err(e) => eprintln!("Failed to replay hints to peer {peer_id}: {e}"),
+                }
+            }
+        }
+    }
+
+    /// Sends the pending hints of a peer in order and stops at the first failure,
+    /// as the peer is most likely still unreachable. Returns the number of replayed hints.
+    async fn replay_hints(&self, peer_id: PeerId) -> Result<usize, StorageError> {
+        let mut replayed = 0;
+
+        for (key, hint) in self.hints.hints(peer_id)? {
+            if hint.is_expired() {
+                println!(
+                    "Dropping expired hint for shard {} of collection {} on peer {peer_id}",
+                    hint.shard_id, hint.collection_name
+                );
+                self.hints.remove(&key)?;
+                continue;
+            }
+
+            let remote = match self.collections.read().await.get(&hint.collection_name) {
+                Some(collection) => {
+                    let replica_holder = collection.replica_holder.read().await;
+                    replica_holder
+                        .shards
+                        .get(&hint.shard_id)
+                        .and_then(|replica_set| {
+                            replica_set
+                                .remotes
+                                .iter()
+                                .find(|remote| remote.peer_id == peer_id)
+                                .cloned()
+                        })
+                }
+                None => None,
+            };
+
+            let Some(remote) = remote else {
+                // Collection, shard or replica is gone, nothing to hand off anymore
+                self.hints.remove(&key)?;
+                continue;
+            };
+
+            // Versions are preserved, so a replayed write never overrides a newer one
+            if let Err(e) = remote.upsert_points(hint.points).await {
+                return Err(StorageError::ServiceError(format!(
+                    "Peer is still unreachable after {replayed} hints: {e}"
+                )));
+            }
+
+            self.hints.remove(&key)?;
+            replayed += 1;
+        }
+
+        Ok(replayed)
+    }
+
+    pub fn hints_info(&self) -> Result<HashMap<PeerId, PeerHints>, StorageError> {
+        self.hints.info()
     }
 
     pub async fn perform_points_op(
@@ -153,12 +1566,19 @@ impl TableOfContent {
         match operation {
             PointsOperation::Upsert(upsert_points) => {
                 collection
-                    .upsert_points(upsert_points.points, false)
+                    .upsert_points(
+                        upsert_points.points,
+                        &upsert_points.shard_key.into(),
+                        false,
+                        Some(self.hints.clone()),
+                    )
                     .await
-                    .map_err(|e| {
-                        StorageError::ServiceError(format!(
+                    .map_err(|e| match e {
+                        // E.g. an unknown shard key
+                        CollectionError::StorageError(e @ StorageError::BadInput(_)) => e,
+                        e => StorageError::ServiceError(format!(
                             "Failed to upsert points in collection '{collection_name}': {e}"
-                        ))
+                        )),
                     })?;
             }
         }
@@ -170,16 +1590,23 @@ impl TableOfContent {
         &self,
         collection_name: &str,
         ids: Option<Vec<PointId>>,
+        shard_selector: &ShardSelector,
+        read_consistency: ReadConsistency,
     ) -> Result<Vec<Point>, StorageError> {
         let collections = self.collections.read().await;
         let collection = collections.get(collection_name).ok_or_else(|| {
             StorageError::BadInput(format!("Collection '{collection_name}' does not exist"))
         })?;
 
-        collection.get_points(ids, None, false).await.map_err(|e| {
-            StorageError::ServiceError(format!(
-                "Failed to retrieve points from collection '{collection_name}': {e}"
-            ))
-        })
+        collection
+            .get_points(ids, shard_selector, read_consistency, false)
+            .await
+            .map_err(|e| match e {
+                // E.g. a read consistency the shards can't satisfy
+                CollectionError::StorageError(e @ StorageError::BadInput(_)) => e,
+                e => StorageError::ServiceError(format!(
+                    "Failed to retrieve points from collection '{collection_name}': {e}"
+                )),
+            })
     }
 }
@@ -1,3 +1,5 @@
 pub type SegmentId = u64;
 pub type ShardId = u32;
 pub type PeerId = u64;
+/// User defined key, e.g. a tenant id, that selects the shards of a collection with custom sharding
+pub type ShardKey = String;
@@ -51,7 +51,7 @@ echo ""
 
 
 # Start first node (bootstrap node)
-NODE1_CMD="cd node1 && $SMOLDB_ABS --url 0.0.0.0:9001 --p2p-url 0.0.0.0:5001 --peer-id 101"
+NODE1_CMD="cd node1 && $SMOLDB_ABS --url 0.0.0.0:9001 --p2p-url http://0.0.0.0:5001 --peer-id 101"
 echo "Starting Node 1 (Bootstrap) in ./cluster/node1..."
 open_terminal "$NODE1_CMD" "SmolDB Node 1 (Bootstrap)"
 
@@ -64,7 +64,7 @@ sleep 5
 echo "Initializing Node 2 (Peer)..."
 
 # Start second node (connects to bootstrap)
-NODE2_CMD="cd node2 && $SMOLDB_ABS --url 0.0.0.0:9002 --p2p-url 0.0.0.0:5002 --bootstrap http://0.0.0.0:5001 --peer-id 102"
+NODE2_CMD="cd node2 && $SMOLDB_ABS --url 0.0.0.0:9002 --p2p-url http://0.0.0.0:5002 --bootstrap http://0.0.0.0:5001 --peer-id 102"
 echo "Starting Node 2 (Peer) in ./cluster/node2..."
 open_terminal "$NODE2_CMD" "SmolDB Node 2 (Peer)"
 
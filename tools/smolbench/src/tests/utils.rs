@@ -14,7 +14,7 @@ fn get_smoldb_exec() -> PathBuf {
 async fn wait_peer_start(uri: &str) {
     let client = reqwest::Client::new();
     let start = std::time::Instant::now();
-    while let Err(_) = client.get(uri).send().await {
+    while client.get(uri).send().await.is_err() {
         if start.elapsed() > MAX_PEER_WAIT {
             panic!("Smoldb peer did not start within the expected time");
         }
@@ -36,7 +36,7 @@ pub async fn start_peer(
         panic!("Smoldb executable not found at {:?}", smoldb_path);
     }
 
-    fs::create_dir_all(&peer_dir).expect("Failed to create peer directory");
+    fs::create_dir_all(peer_dir).expect("Failed to create peer directory");
 
     // Create and open log file
     let log_file = OpenOptions::new()
@@ -62,7 +62,7 @@ pub async fn start_peer(
     }
 
     let child = cmd
-        .current_dir(&peer_dir)
+        .current_dir(peer_dir)
         .stdout(Stdio::from(
             log_file
                 .try_clone()
@@ -8,7 +8,6 @@ pub async fn log_latencies<T>(
     let mut latencies = batch_responses
         .iter()
         .map(|res| res.time * 1000.0) // Convert s to ms
-        .map(|latency| latency as f64)
         .collect::<Vec<_>>();
 
     latencies.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());